@@ -0,0 +1,356 @@
+use std::env;
+use std::io::{stdout, Stdout, Write};
+use std::time::Duration;
+
+use crossterm::{
+    cursor::{DisableBlinking, EnableBlinking, Hide, MoveTo, Show},
+    event::{self, Event},
+    queue,
+    style::{Attribute, Print, SetAttribute, SetBackgroundColor, SetForegroundColor},
+    terminal::{self, Clear, ClearType},
+    Result,
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+use crate::colour_string::{Char, Info};
+
+// where frames are drawn, the terminal or a grid in memory
+pub trait Backend {
+    fn size(&self) -> Result<(u16, u16)>;
+    // blanks every cell
+    fn clear(&mut self) -> Result<()>;
+    fn draw_cell(&mut self, x: u16, y: u16, cell: &Char) -> Result<()>;
+    fn hide_cursor(&mut self) -> Result<()>;
+    fn show_cursor(&mut self, x: u16, y: u16) -> Result<()>;
+    fn flush(&mut self) -> Result<()>;
+    // sets the system clipboard with an osc 52 escape sequence
+    fn set_clipboard(&mut self, text: &str) -> Result<()>;
+    // whether any rgb colour can be shown, or only the 16 terminal colours
+    fn truecolor(&self) -> bool;
+}
+
+// where input comes from, the terminal or a list of events
+pub trait Events {
+    // whether an event is ready within `timeout`
+    fn poll(&mut self, timeout: Duration) -> Result<bool>;
+    fn read(&mut self) -> Result<Event>;
+}
+
+pub struct CrosstermBackend {
+    out: Stdout,
+    // where the terminal cursor is after the last cell, so cells next to each other don't need a move between them
+    next: Option<(u16, u16)>,
+    style: Option<Info>,
+}
+
+impl CrosstermBackend {
+    pub fn new() -> Self {
+        Self {
+            out: stdout(),
+            next: None,
+            style: None,
+        }
+    }
+}
+
+impl Backend for CrosstermBackend {
+    fn size(&self) -> Result<(u16, u16)> {
+        terminal::size()
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        self.style = None;
+        queue!(self.out, SetAttribute(Attribute::Reset), Clear(ClearType::All))
+    }
+
+    fn draw_cell(&mut self, x: u16, y: u16, cell: &Char) -> Result<()> {
+        if self.next != Some((x, y)) {
+            queue!(self.out, MoveTo(x, y))?;
+        }
+        if self.style.as_ref() != Some(&cell.colour) {
+            set_style(&mut self.out, self.style.as_ref(), &cell.colour)?;
+            self.style = Some(cell.colour.clone());
+        }
+        queue!(self.out, Print(&cell.content))?;
+        // after the last column the terminal cursor doesn't move to the next row, so that cell never comes next
+        self.next = Some((x + cell.width() as u16, y));
+        Ok(())
+    }
+
+    fn hide_cursor(&mut self) -> Result<()> {
+        queue!(self.out, Hide, DisableBlinking)
+    }
+
+    fn show_cursor(&mut self, x: u16, y: u16) -> Result<()> {
+        self.next = None;
+        queue!(self.out, MoveTo(x, y), Show, EnableBlinking)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if self.style.take().is_some() {
+            queue!(self.out, SetAttribute(Attribute::Reset))?;
+        }
+        self.out.flush()
+    }
+
+    fn set_clipboard(&mut self, text: &str) -> Result<()> {
+        queue!(self.out, Print(format!("\x1b]52;c;{}\x07", STANDARD.encode(text))))
+    }
+
+    // terminals that can say so in $COLORTERM, or in $TERM with the -direct terminfo entries
+    fn truecolor(&self) -> bool {
+        env::var("COLORTERM").is_ok_and(|value| value == "truecolor" || value == "24bit") || env::var("TERM").is_ok_and(|value| value.ends_with("-direct"))
+    }
+}
+
+// only the colours that changed are sent, attributes can't be turned off one by one so a change to them starts over
+fn set_style(out: &mut impl Write, from: Option<&Info>, to: &Info) -> Result<()> {
+    match from {
+        Some(from) if from.attributes == to.attributes => {
+            if from.foreground != to.foreground {
+                queue!(out, SetForegroundColor(to.foreground))?;
+            }
+            if from.background != to.background {
+                queue!(out, SetBackgroundColor(to.background))?;
+            }
+        },
+        _ => {
+            queue!(out, SetAttribute(Attribute::Reset), SetForegroundColor(to.foreground), SetBackgroundColor(to.background))?;
+            for attribute in &to.attributes {
+                queue!(out, SetAttribute(*attribute))?;
+            }
+        },
+    }
+    Ok(())
+}
+
+pub struct TerminalEvents;
+
+impl Events for TerminalEvents {
+    fn poll(&mut self, timeout: Duration) -> Result<bool> {
+        event::poll(timeout)
+    }
+
+    fn read(&mut self) -> Result<Event> {
+        event::read()
+    }
+}
+
+// a screen of a fixed size that keeps what was drawn on it
+#[cfg(test)]
+pub struct MemoryBackend {
+    width: u16,
+    height: u16,
+    pub cells: Vec<Char>,
+    // None while hidden
+    pub cursor: Option<(u16, u16)>,
+    // the last text sent to the terminal's clipboard
+    pub clipboard: Option<String>,
+    pub truecolor: bool,
+    // every cell written, in order, to check what a draw sends
+    pub drawn: Vec<(u16, u16)>,
+}
+
+#[cfg(test)]
+impl MemoryBackend {
+    pub fn new(width: u16, height: u16) -> Self {
+        Self {
+            width,
+            height,
+            cells: vec![blank(); width as usize * height as usize],
+            cursor: None,
+            clipboard: None,
+            truecolor: true,
+            drawn: Vec::new(),
+        }
+    }
+
+    // what the terminal does when its window is resized, the event for it is played separately
+    pub fn resize(&mut self, width: u16, height: u16) {
+        self.width = width;
+        self.height = height;
+        self.cells = vec![blank(); width as usize * height as usize];
+    }
+
+    // the text of every row
+    pub fn lines(&self) -> Vec<String> {
+        self.cells.chunks(self.width.max(1) as usize).map(|row| row.iter().map(|cell| cell.content.as_str()).collect()).collect()
+    }
+}
+
+#[cfg(test)]
+fn blank() -> Char {
+    Char { content: String::from(" "), colour: Info::new(crossterm::style::Color::White, crossterm::style::Color::Reset, vec![]) }
+}
+
+#[cfg(test)]
+impl Backend for MemoryBackend {
+    fn size(&self) -> Result<(u16, u16)> {
+        Ok((self.width, self.height))
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        self.cells.fill(blank());
+        Ok(())
+    }
+
+    // a wide grapheme covers the cell after it too
+    fn draw_cell(&mut self, x: u16, y: u16, cell: &Char) -> Result<()> {
+        self.drawn.push((x, y));
+        for (i, x) in (x..x + cell.width() as u16).enumerate() {
+            if x < self.width && y < self.height {
+                let content = if i == 0 { cell.content.clone() } else { String::new() };
+                self.cells[y as usize * self.width as usize + x as usize] = Char { content, colour: cell.colour.clone() };
+            }
+        }
+        Ok(())
+    }
+
+    fn hide_cursor(&mut self) -> Result<()> {
+        self.cursor = None;
+        Ok(())
+    }
+
+    fn show_cursor(&mut self, x: u16, y: u16) -> Result<()> {
+        self.cursor = Some((x, y));
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_clipboard(&mut self, text: &str) -> Result<()> {
+        self.clipboard = Some(text.to_string());
+        Ok(())
+    }
+
+    fn truecolor(&self) -> bool {
+        self.truecolor
+    }
+}
+
+// events given up front, written like `ihello<Esc>:s<Enter>`
+#[cfg(test)]
+pub struct ScriptedEvents {
+    events: std::collections::VecDeque<Event>,
+}
+
+#[cfg(test)]
+impl ScriptedEvents {
+    // <Esc>, <Enter>, <Tab>, <BS>, <Del>, <Home>, <End>, <Up>, <Down>, <Left>, <Right>, <C-x> for ctrl, <A-x> for alt, <S-Tab> and <lt> for <
+    pub fn keys(script: &str) -> Self {
+        use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+        let mut events = std::collections::VecDeque::new();
+        let mut rest = script;
+        while let Some(c) = rest.chars().next() {
+            let special = rest.strip_prefix('<').and_then(|tail| tail.split_once('>')).filter(|(name, _)| !name.is_empty());
+            let (code, modifiers) = match special {
+                Some((name, tail)) => {
+                    rest = tail;
+                    let (modifiers, key) = match name.split_once('-') {
+                        Some(("C", key)) => (KeyModifiers::CONTROL, key),
+                        Some(("A", key)) => (KeyModifiers::ALT, key),
+                        Some(("S", key)) => (KeyModifiers::SHIFT, key),
+                        _ => (KeyModifiers::NONE, name),
+                    };
+                    let code = match key {
+                        "Esc" => KeyCode::Esc,
+                        "Enter" => KeyCode::Enter,
+                        "Tab" if modifiers == KeyModifiers::SHIFT => KeyCode::BackTab,
+                        "Tab" => KeyCode::Tab,
+                        "BS" => KeyCode::Backspace,
+                        "Del" => KeyCode::Delete,
+                        "Home" => KeyCode::Home,
+                        "End" => KeyCode::End,
+                        "Up" => KeyCode::Up,
+                        "Down" => KeyCode::Down,
+                        "Left" => KeyCode::Left,
+                        "Right" => KeyCode::Right,
+                        "lt" => KeyCode::Char('<'),
+                        key if key.chars().count() == 1 => KeyCode::Char(key.chars().next().unwrap()),
+                        _ => panic!("unknown key <{}>", name),
+                    };
+                    (code, modifiers)
+                },
+                None => {
+                    rest = &rest[c.len_utf8()..];
+                    (KeyCode::Char(c), if c.is_uppercase() { KeyModifiers::SHIFT } else { KeyModifiers::NONE })
+                },
+            };
+            events.push_back(Event::Key(KeyEvent::new(code, modifiers)));
+        }
+        Self { events }
+    }
+
+    // events that can't be written as keys, like the mouse, go after the script
+    pub fn then(mut self, events: impl IntoIterator<Item = Event>) -> Self {
+        self.events.extend(events);
+        self
+    }
+}
+
+#[cfg(test)]
+impl Events for ScriptedEvents {
+    fn poll(&mut self, _: Duration) -> Result<bool> {
+        Ok(!self.events.is_empty())
+    }
+
+    fn read(&mut self) -> Result<Event> {
+        self.events.pop_front().ok_or(std::io::Error::other("No more events"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crossterm::style::Color;
+
+    use super::*;
+
+    fn style(from: Option<&Info>, to: &Info) -> Vec<u8> {
+        let mut out = Vec::new();
+        set_style(&mut out, from, to).unwrap();
+        out
+    }
+
+    fn reset() -> Vec<u8> {
+        let mut out = Vec::new();
+        queue!(out, SetAttribute(Attribute::Reset)).unwrap();
+        out
+    }
+
+    fn contains(out: &[u8], part: &[u8]) -> bool {
+        out.windows(part.len()).any(|window| window == part)
+    }
+
+    #[test]
+    fn a_colour_change_only_sends_that_colour() {
+        let from = Info::new(Color::Red, Color::Black, vec![Attribute::Bold]);
+        let mut expected = Vec::new();
+        queue!(expected, SetForegroundColor(Color::Blue)).unwrap();
+        assert_eq!(style(Some(&from), &Info::new(Color::Blue, Color::Black, vec![Attribute::Bold])), expected);
+
+        let mut expected = Vec::new();
+        queue!(expected, SetBackgroundColor(Color::Reset)).unwrap();
+        assert_eq!(style(Some(&from), &Info::new(Color::Red, Color::Reset, vec![Attribute::Bold])), expected);
+        assert!(style(Some(&from), &from).is_empty());
+    }
+
+    #[test]
+    fn an_attribute_change_starts_over() {
+        let from = Info::new(Color::Red, Color::Reset, vec![Attribute::Bold]);
+        let to = Info::new(Color::Red, Color::Reset, vec![Attribute::Italic]);
+        let mut expected = Vec::new();
+        queue!(expected, SetAttribute(Attribute::Reset), SetForegroundColor(Color::Red), SetBackgroundColor(Color::Reset), SetAttribute(Attribute::Italic)).unwrap();
+        assert_eq!(style(Some(&from), &to), expected);
+
+        // dropping the only attribute still has to reset it
+        let out = style(Some(&from), &Info::new(Color::Red, Color::Reset, vec![]));
+        assert!(out.starts_with(&reset()));
+        // and nothing is known about the terminal before the first cell
+        assert!(style(None, &to).starts_with(&reset()));
+        assert!(!contains(&style(Some(&to), &Info::new(Color::Blue, Color::Reset, vec![Attribute::Italic])), &reset()));
+    }
+}
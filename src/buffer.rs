@@ -0,0 +1,253 @@
+use std::{borrow::Cow, io};
+use ropey::{Rope, RopeSlice};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineEnding {
+    Lf,
+    Crlf,
+}
+
+impl LineEnding {
+    // the style most lines use, and whether both styles appear
+    pub fn detect(text: &str) -> (Self, bool) {
+        let crlf = text.matches("\r\n").count();
+        let lf = text.matches('\n').count() - crlf;
+        (if crlf > lf { LineEnding::Crlf } else { LineEnding::Lf }, crlf > 0 && lf > 0)
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "unix" => Some(LineEnding::Lf),
+            "dos" => Some(LineEnding::Crlf),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::Crlf => "\r\n",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "unix",
+            LineEnding::Crlf => "dos",
+        }
+    }
+}
+
+// display column reached after drawing grapheme at column, tabs stop at multiples of tab_width
+pub fn advance(column: usize, grapheme: &str, tab_width: usize) -> usize {
+    if grapheme == "\t" {
+        column + tab_width - column % tab_width
+    }
+    else {
+        column + grapheme_width(grapheme)
+    }
+}
+
+// columns a grapheme takes on the terminal, 2 for wide ones like CJK and emoji. combining marks add nothing to
+// the character they're on, and the variation selector that asks for emoji presentation makes it wide
+pub fn grapheme_width(grapheme: &str) -> usize {
+    if grapheme.contains('\u{fe0f}') {
+        return 2;
+    }
+    grapheme.width().clamp(1, 2)
+}
+
+pub fn text_width(text: &str) -> usize {
+    text.graphemes(true).map(grapheme_width).sum()
+}
+
+// ropey is built without its unicode_lines / cr_lines features, so only '\n' splits lines and
+// a trailing '\r' is stripped from the line content instead
+pub struct Buffer {
+    rope: Rope,
+    // counts the edits, so anything worked out from the text can tell when it is out of date
+    revision: usize,
+}
+
+impl Buffer {
+    pub fn new(text: &str) -> Self {
+        Self {
+            rope: Rope::from_str(text),
+            revision: 0,
+        }
+    }
+
+    pub fn revision(&self) -> usize {
+        self.revision
+    }
+
+    pub fn len_bytes(&self) -> usize {
+        self.rope.len_bytes()
+    }
+
+    // a trailing newline does not start another line
+    pub fn len_lines(&self) -> usize {
+        let lines = self.rope.len_lines();
+        if lines > 1 && self.rope.line(lines - 1).len_chars() == 0 {
+            lines - 1
+        }
+        else {
+            lines
+        }
+    }
+
+    fn line_slice(&self, y: usize) -> RopeSlice<'_> {
+        let line = self.rope.line(y);
+        let mut end = line.len_chars();
+        if end > 0 && line.char(end - 1) == '\n' {
+            end -= 1;
+        }
+        if end > 0 && line.char(end - 1) == '\r' {
+            end -= 1;
+        }
+        line.slice(..end)
+    }
+
+    // borrowed straight from the rope, only a line split across two of its chunks is copied
+    pub fn line(&self, y: usize) -> Cow<'_, str> {
+        let line = self.line_slice(y);
+        match line.as_str() {
+            Some(text) => Cow::Borrowed(text),
+            None => Cow::Owned(line.to_string()),
+        }
+    }
+
+    pub fn line_len(&self, y: usize) -> usize {
+        self.line(y).graphemes(true).count()
+    }
+
+    pub fn text(&self) -> String {
+        self.rope.to_string()
+    }
+
+    // columns past the end of the line count as one column per missing grapheme
+    pub fn display_column(&self, position: (usize, usize), tab_width: usize) -> usize {
+        let (x, y) = position;
+        let (mut column, mut count) = (0, 0);
+        for grapheme in self.line(y).graphemes(true) {
+            if count == x {
+                return column;
+            }
+            column = advance(column, grapheme, tab_width);
+            count += 1;
+        }
+        column + (x - count)
+    }
+
+    // grapheme drawn at column on line y, a column inside a tab maps to the tab itself
+    pub fn grapheme_index(&self, y: usize, column: usize, tab_width: usize) -> usize {
+        let (mut current, mut count) = (0, 0);
+        for grapheme in self.line(y).graphemes(true) {
+            let next = advance(current, grapheme, tab_width);
+            if column < next {
+                return count;
+            }
+            current = next;
+            count += 1;
+        }
+        count + (column - current)
+    }
+
+    // char index of grapheme x on line y, clamped to the end of the line
+    fn char_index(&self, position: (usize, usize)) -> usize {
+        let (x, y) = position;
+        let chars: usize = self.line(y).graphemes(true).take(x).map(|grapheme| grapheme.chars().count()).sum();
+        self.rope.line_to_char(y) + chars
+    }
+
+    pub fn slice(&self, start: (usize, usize), end: (usize, usize)) -> String {
+        self.rope.slice(self.char_index(start)..self.char_index(end)).to_string()
+    }
+
+    pub fn insert(&mut self, position: (usize, usize), text: &str) {
+        let index = self.char_index(position);
+        self.rope.insert(index, text);
+        self.revision += 1;
+    }
+
+    pub fn remove(&mut self, start: (usize, usize), end: (usize, usize)) -> String {
+        let (start, end) = (self.char_index(start), self.char_index(end));
+        let removed = self.rope.slice(start..end).to_string();
+        self.rope.remove(start..end);
+        self.revision += 1;
+        removed
+    }
+
+    pub fn write_to<T: io::Write>(&self, writer: T) -> io::Result<()> {
+        self.rope.write_to(writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grapheme_widths() {
+        assert_eq!(grapheme_width("a"), 1);
+        assert_eq!(grapheme_width("e\u{301}"), 1);
+        assert_eq!(grapheme_width("字"), 2);
+        assert_eq!(grapheme_width("👍"), 2);
+        assert_eq!(grapheme_width("👍🏽"), 2);
+        assert_eq!(grapheme_width("👩‍👩‍👧"), 2);
+        assert_eq!(grapheme_width("❤\u{fe0f}"), 2);
+        assert_eq!(text_width("a字e\u{301}👍"), 6);
+    }
+
+    #[test]
+    fn tabs_stop_at_multiples_of_the_width() {
+        assert_eq!(advance(0, "\t", 4), 4);
+        assert_eq!(advance(3, "\t", 4), 4);
+        assert_eq!(advance(4, "\t", 4), 8);
+        assert_eq!(advance(2, "字", 4), 4);
+    }
+
+    #[test]
+    fn columns_of_wide_and_combining_graphemes() {
+        let buffer = Buffer::new("a字e\u{301}\tb\n");
+        let columns: Vec<usize> = (0..6).map(|x| buffer.display_column((x, 0), 4)).collect();
+        assert_eq!(columns, vec![0, 1, 3, 4, 8, 9]);
+
+        // both halves of a wide grapheme and every column of a tab are that grapheme
+        let indices: Vec<usize> = (0..10).map(|column| buffer.grapheme_index(0, column, 4)).collect();
+        assert_eq!(indices, vec![0, 1, 1, 2, 3, 3, 3, 3, 4, 5]);
+        assert_eq!(buffer.line_len(0), 5);
+    }
+
+    #[test]
+    fn edits_are_by_grapheme() {
+        let mut buffer = Buffer::new("e\u{301}字👍🏽z\nnext");
+        assert_eq!(buffer.slice((1, 0), (3, 0)), "字👍🏽");
+        buffer.insert((3, 0), "!");
+        assert_eq!(buffer.line(0), "e\u{301}字👍🏽!z");
+        assert_eq!(buffer.remove((0, 0), (2, 0)), "e\u{301}字");
+        // past the end of the line is clamped to it
+        buffer.insert((10, 0), "?");
+        assert_eq!(buffer.text(), "👍🏽!z?\nnext");
+    }
+
+    #[test]
+    fn lines_leave_out_their_endings() {
+        let buffer = Buffer::new("one\r\ntwo\nthree\n");
+        assert_eq!(buffer.len_lines(), 3);
+        assert_eq!(buffer.line(0), "one");
+        assert_eq!(buffer.line(1), "two");
+        assert_eq!(buffer.line(2), "three");
+        assert_eq!(Buffer::new("").len_lines(), 1);
+        assert_eq!(Buffer::new("no newline").len_lines(), 1);
+    }
+
+    #[test]
+    fn lines_are_borrowed_from_the_rope() {
+        let text = "some line of text\n".repeat(10_000);
+        let buffer = Buffer::new(&text);
+        assert!(matches!(buffer.line(5_000), Cow::Borrowed("some line of text")));
+    }
+}
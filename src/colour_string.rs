@@ -5,6 +5,9 @@ use crossterm::{
 };
 
 use std::fmt;
+use std::ops::Range;
+
+use crate::buffer::{advance, grapheme_width};
 
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct Info {
@@ -13,6 +16,13 @@ pub struct Info {
     pub attributes: Vec<Attribute>,
 }
 
+// what text is given when no colour is
+impl Default for Info {
+    fn default() -> Self {
+        Self::new(Color::White, Color::Reset, vec![])
+    }
+}
+
 impl Info {
     pub fn new(foreground: Color, background: Color, attributes: Vec<Attribute>) -> Self {
         Self {
@@ -29,21 +39,40 @@ pub struct Char {
     pub colour: Info,
 }
 
+impl Char {
+    // the second column of a wide grapheme, which the grapheme before it is drawn over
+    pub fn is_continuation(&self) -> bool {
+        self.content.is_empty()
+    }
+
+    pub fn width(&self) -> usize {
+        if self.is_continuation() { 0 } else { grapheme_width(&self.content) }
+    }
+}
+
+// one Char per column, so indices are columns on the screen
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ColourString {
     content: Vec<Char>,
 }
 
+// a wide grapheme is followed by an empty Char for the second column it covers
+fn chars(text: &str, colour: Option<Info>) -> Vec<Char> {
+    let colour = colour.unwrap_or_default();
+    let mut chars = vec![];
+    for grapheme in text.graphemes(true) {
+        chars.push(Char { content: grapheme.to_string(), colour: colour.clone() });
+        if grapheme_width(grapheme) == 2 {
+            chars.push(Char { content: String::new(), colour: colour.clone() });
+        }
+    }
+    chars
+}
+
 impl ColourString {
     pub fn new(content: String, colour: Option<Info>) -> Self {
-        let content: Vec<String> = content.graphemes(true).map(|x| x.to_string()).collect();
-        match colour {
-            Some(colour) => Self {
-                content: content.into_iter().map(|x| Char { content: x, colour: colour.clone() }).collect(),
-            },
-            None => Self {
-                content: content.into_iter().map(|x| Char { content: x, colour: Info::new(Color::White, Color::Reset, vec![]) }).collect(),
-            },
+        Self {
+            content: chars(&content, colour),
         }
     }
 
@@ -53,16 +82,39 @@ impl ColourString {
 
     pub fn truncate(&mut self, len: usize) {
         self.content.truncate(len);
-    }
-
-    pub fn l_shift(&mut self, content: String, colour: Option<Info>) {
-        self.content.pop();
-        self.content.insert(0, Char { content, colour: colour.unwrap_or(Info::new(Color::White, Color::Reset, vec![])) });
+        self.repair(len.saturating_sub(1)..len);
     }
 
     pub fn skip(&mut self, n: usize) -> ColourString {
-        ColourString {
+        let mut skipped = ColourString {
             content: self.content.split_at(n.clamp(0, self.content.len())).1.to_vec(),
+        };
+        skipped.repair(0..1);
+        skipped
+    }
+
+    // puts a single column in place, a wide grapheme is expected to have its second column put right after it
+    pub fn set_char(&mut self, x: usize, c: Char) {
+        if x < self.content.len() {
+            self.content[x] = c;
+            self.repair(x.saturating_sub(1)..x);
+            self.repair(x + 1..x + 2);
+        }
+    }
+
+    // a wide grapheme that lost one of its columns, by being cut at an edge or half drawn over, is left as a space
+    fn repair(&mut self, range: Range<usize>) {
+        let len = self.content.len();
+        for i in range.start..range.end.min(len) {
+            let broken = if self.content[i].is_continuation() {
+                i == 0 || self.content[i - 1].width() != 2
+            }
+            else {
+                self.content[i].width() == 2 && !self.content.get(i + 1).is_some_and(Char::is_continuation)
+            };
+            if broken {
+                self.content[i].content = String::from(" ");
+            }
         }
     }
 
@@ -71,6 +123,7 @@ impl ColourString {
     //     self.content.push(Char { content, colour: colour.unwrap_or(Info::new(Color::White, Color::Reset, vec![])) });
     // }
 
+    #[allow(dead_code)]
     pub fn set_colour_pattern(&mut self, pattern: String, colour: Info) {
         let indices = self.content.iter_mut().map(|x| x.content.to_string()).collect::<Vec<String>>().join("");
         let indices = indices.match_indices(&pattern).collect::<Vec<_>>();
@@ -80,12 +133,12 @@ impl ColourString {
         }
     }
 
+    #[allow(dead_code)]
     pub fn replace_char(&mut self, pattern: String, replacement: String, colour: Option<Info>) {
         let mut new_content = Vec::new();
-        let colour = colour.unwrap_or(Info::new(Color::White, Color::Reset, vec![]));
         for i in 0..self.content.len() {
             if self.content[i].content == pattern {
-                new_content.push(Char { content: replacement.clone(), colour: colour.clone() });
+                new_content.extend(chars(&replacement, colour.clone()));
             }
             else {
                 new_content.push(self.content[i].clone());
@@ -95,13 +148,12 @@ impl ColourString {
     }
 
     pub fn replace(&mut self, pattern: String, replacement: String, colour: Option<Info>) {
-        let colour = colour.unwrap_or(Info::new(Color::White, Color::Reset, vec![]));
         let pattern = pattern.graphemes(true).collect::<Vec<&str>>();
         let mut indices = vec![];
         let mut start: Option<usize> = None;
         for (i, c) in self.content.iter().enumerate() {
-            if let None = start {
-                if c.content == pattern[0 as usize] {
+            if start.is_none() {
+                if c.content == pattern[0_usize] {
                     start = Some(i);
                     if pattern.len() == 1 {
                         indices.push(i);
@@ -112,7 +164,7 @@ impl ColourString {
             else if let Some(s) = start {
                 if i-s >= pattern.len()-1 {
                     indices.push(s);
-                    if c.content == pattern[0 as usize] {
+                    if c.content == pattern[0_usize] {
                         start = Some(i);
                         if pattern.len() == 1 {
                             indices.push(i);
@@ -130,36 +182,70 @@ impl ColourString {
         }
 
         for i in indices {
-            self.content.splice(i..((i + pattern.join("").graphemes(true).count()) as usize).clamp(0, self.content.len()), replacement.graphemes(true).map(|x| Char { content: x.to_string(), colour: colour.clone() }).collect::<Vec<Char>>());
+            self.content.splice(i..(i + pattern.join("").graphemes(true).count()).clamp(0, self.content.len()), chars(&replacement, colour.clone()));
         }
+        self.repair(0..self.content.len());
 
     }
 
+    pub fn expand_tabs(&self, tab_width: usize) -> ColourString {
+        let mut content = Vec::new();
+        for c in &self.content {
+            if c.content == "\t" {
+                let spaces = advance(content.len(), "\t", tab_width) - content.len();
+                content.extend(vec![Char { content: " ".to_string(), colour: c.colour.clone() }; spaces]);
+            }
+            else {
+                content.push(c.clone());
+            }
+        }
+        ColourString { content }
+    }
+
     pub fn pad(&mut self, len: usize, content: String, colour: Option<Info>) {
-        let colour = colour.unwrap_or(Info::new(Color::White, Color::Reset, vec![]));
+        let colour = colour.unwrap_or_default();
         while self.content.len() < len {
             self.content.push(Char { content: content.clone(), colour: colour.clone() });
         }
     }
 
+    // changes only the background so the foreground colours underneath stay visible
+    pub fn highlight(&mut self, start: usize, end: usize, colour: Color) {
+        let len = self.content.len();
+        for c in self.content[start.min(len)..end.clamp(start.min(len), len)].iter_mut() {
+            if c.content == "█" {
+                c.content = " ".to_string();
+            }
+            c.colour.background = colour;
+        }
+    }
+
     pub fn set_background(&mut self, colour: Color) {
         for i in 0..self.content.len() {
             self.content[i].colour.background = colour;
         }
     }
 
+    // for what is behind an overlay, blocks drawn as background take `shade` and everything else `dimmed`
+    pub fn dim(&mut self, dimmed: Color, shade: Color) {
+        for c in self.content.iter_mut() {
+            c.colour = Info::new(if c.content == "█" { shade } else { dimmed }, Color::Reset, vec![]);
+        }
+    }
+
     pub fn push_colour_string(&mut self, other: ColourString) {
         self.content.extend(other.content);
     }
 
     pub fn push_str(&mut self, content: &str, colour: Option<Info>) {
-        let content = content.graphemes(true).map(|x| x.to_string()).collect::<Vec<String>>();
-        let colour = colour.unwrap_or(Info::new(Color::White, Color::Reset, vec![]));
-        self.content.extend(content.into_iter().map(|x| Char { content: x, colour: colour.clone() }));
+        self.content.extend(chars(content, colour));
     }
 
     pub fn replace_range(&mut self, start: usize, end: usize, content: ColourString) {
+        let len = content.content.len();
         self.content.splice(start..end, content.content);
+        self.repair(start.saturating_sub(1)..start + 1);
+        self.repair(start + len..start + len + 1);
     }
 
     pub fn set_colour(&mut self, colour: Info, start: usize, end: usize) {
@@ -177,9 +263,11 @@ impl ColourString {
     // }
 
     pub fn insert(&mut self, index: usize, content: String, colour: Option<Info>) {
-        let colour = colour.unwrap_or(Info::new(Color::White, Color::Reset, vec![]));
-        let content = content.graphemes(true).map(|x| x.to_string()).collect::<Vec<String>>();
-        self.content.splice(index..index, content.into_iter().map(|x| Char { content: x, colour: colour.clone() }));
+        let content = chars(&content, colour);
+        let len = content.len();
+        self.content.splice(index..index, content);
+        self.repair(index.saturating_sub(1)..index);
+        self.repair(index + len..index + len + 1);
     }
 
     pub fn render(&self) -> String {
@@ -208,6 +296,7 @@ impl ColourString {
         output
     }
 
+    #[allow(dead_code)]
     pub fn render_vector(vector: Vec<ColourString>) -> String {
         let mut output = ColourString::new(String::new(), None);
         for mut line in vector {
@@ -217,6 +306,7 @@ impl ColourString {
         output.render().trim_end().to_string()
     }
 
+    #[allow(dead_code)]
     pub fn parse_ansi_string(string: String) -> ColourString {
         let mut output = ColourString::new(String::new(), None);
         let mut current_colour = Info::new(Color::White, Color::Reset, vec![]);
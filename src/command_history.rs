@@ -0,0 +1,91 @@
+use std::{fs, path::PathBuf};
+
+// the oldest entries of each kind are dropped past this many
+const CAP: usize = 500;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Command,
+    Search,
+}
+
+impl Kind {
+    // what each line of the file starts with
+    fn prefix(self) -> char {
+        match self {
+            Kind::Command => ':',
+            Kind::Search => '/',
+        }
+    }
+}
+
+// commands and searches from the palette, oldest first, kept in a file so they last between sessions
+pub struct CommandHistory {
+    commands: Vec<String>,
+    searches: Vec<String>,
+    // None keeps it in memory only
+    path: Option<PathBuf>,
+}
+
+impl CommandHistory {
+    // a missing or unreadable file is an empty history
+    pub fn new(path: Option<PathBuf>) -> Self {
+        let mut history = Self {
+            commands: vec![],
+            searches: vec![],
+            path,
+        };
+        let text = history.path.as_ref().and_then(|path| fs::read_to_string(path).ok()).unwrap_or_default();
+        for line in text.lines() {
+            let mut chars = line.chars();
+            match chars.next() {
+                Some(':') => history.commands.push(chars.as_str().to_string()),
+                Some('/') => history.searches.push(chars.as_str().to_string()),
+                _ => {},
+            }
+        }
+        history
+    }
+
+    pub fn entries(&self, kind: Kind) -> &[String] {
+        match kind {
+            Kind::Command => &self.commands,
+            Kind::Search => &self.searches,
+        }
+    }
+
+    // an entry that was already there moves to the end instead of being there twice
+    pub fn add(&mut self, kind: Kind, entry: &str) {
+        if entry.trim().is_empty() {
+            return;
+        }
+        let entries = match kind {
+            Kind::Command => &mut self.commands,
+            Kind::Search => &mut self.searches,
+        };
+        entries.retain(|existing| existing != entry);
+        entries.push(entry.to_string());
+        if entries.len() > CAP {
+            entries.drain(..entries.len() - CAP);
+        }
+        if let Err(e) = self.save() {
+            warn!("couldn't save history: {}", e);
+        }
+    }
+
+    fn save(&self) -> std::io::Result<()> {
+        let Some(path) = &self.path else { return Ok(()) };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut text = String::new();
+        for (kind, entries) in [(Kind::Command, &self.commands), (Kind::Search, &self.searches)] {
+            for entry in entries {
+                text.push(kind.prefix());
+                text.push_str(entry);
+                text.push('\n');
+            }
+        }
+        fs::write(path, text)
+    }
+}
@@ -1,45 +1,257 @@
-use crossterm::{terminal, style::Color};
+use crossterm::{event::{KeyCode, KeyEvent, KeyModifiers}, style::{Attribute, Color}};
+
 use unicode_segmentation::UnicodeSegmentation;
 
-use crate::{colour_string::{ColourString, Info}, cursor::Cursor};
+use crate::buffer::{grapheme_width, text_width};
+use crate::command_history::{CommandHistory, Kind};
+use crate::completion::{self, Suggestion};
+use crate::line_editor::LineEditor;
+use crate::{colour_string::{ColourString, Info}, cursor::Cursor, theme::Theme};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PaletteMode {
+    Command,
+    Search { backward: bool },
+}
 
 pub struct CommandPalette {
-    pub command: String,
+    pub input: LineEditor,
     pub enabled: bool,
-    pub cursor: Cursor
+    pub mode: PaletteMode,
+    pub hint: String,
+    pub cursor: Cursor,
+    // shown in a dropdown under the command
+    pub suggestions: Vec<Suggestion>,
+    // the suggestion tab last put in the command
+    pub selected: Option<usize>,
+    pub history: CommandHistory,
+    // where up, down and ctrl + r have got to in the history
+    browse: Option<Browse>,
+    // what ctrl + r is looking for, while it is
+    pub reverse_search: Option<String>,
+}
+
+struct Browse {
+    // what was in the command before the history was gone into, up and down only go to entries starting with it
+    typed: String,
+    // the entry in the command, the length of the history for none
+    index: usize,
 }
 
+// rows of the dropdown, it scrolls to keep the selected suggestion in them
+const SUGGESTION_ROWS: usize = 8;
+
 impl CommandPalette {
     pub fn new() -> Self {
         Self {
-            command: String::new(),
+            input: LineEditor::new(),
             enabled: false,
-            cursor: Cursor::new()
+            mode: PaletteMode::Command,
+            hint: String::new(),
+            cursor: Cursor::new(),
+            suggestions: vec![],
+            selected: None,
+            history: CommandHistory::new(crate::state_path("history")),
+            browse: None,
+            reverse_search: None,
+        }
+    }
+
+    fn kind(&self) -> Kind {
+        if self.mode == PaletteMode::Command { Kind::Command } else { Kind::Search }
+    }
+
+    // after the command is edited, going through the history starts again from what is there now, and no suggestion
+    // is selected until tab is pressed again. an empty command only lists every command once tab is pressed
+    pub fn changed(&mut self) {
+        self.browse = None;
+        self.suggestions = if self.mode == PaletteMode::Command && !self.input.is_empty() { completion::suggestions(self.input.text()) } else { vec![] };
+        self.selected = None;
+    }
+
+    // puts the next or previous suggestion in the command, the list stays as it was for what was typed
+    pub fn cycle(&mut self, forward: bool) {
+        if self.suggestions.is_empty() && self.mode == PaletteMode::Command {
+            self.suggestions = completion::suggestions(self.input.text());
+        }
+        let len = self.suggestions.len();
+        if len == 0 {
+            return;
+        }
+        let selected = match self.selected {
+            Some(i) if forward => (i + 1) % len,
+            Some(i) => (i + len - 1) % len,
+            None if forward => 0,
+            None => len - 1,
+        };
+        self.selected = Some(selected);
+        self.input.set(&self.suggestions[selected].line);
+    }
+
+    // editing keys go to the input, returning whether the key was one of them
+    pub fn edit(&mut self, key: KeyEvent) -> bool {
+        let before = self.input.text().to_string();
+        if !self.input.handle(key) {
+            return false;
+        }
+        if self.input.text() != before {
+            self.changed();
+        }
+        true
+    }
+
+    // for when the palette closes
+    pub fn reset(&mut self) {
+        self.suggestions.clear();
+        self.selected = None;
+        self.browse = None;
+        self.reverse_search = None;
+    }
+
+    // the newest entry before the current one that starts with what was typed
+    pub fn older(&mut self) {
+        let entries = self.history.entries(self.kind());
+        let browse = self.browse.get_or_insert_with(|| Browse { typed: self.input.text().to_string(), index: entries.len() });
+        if let Some(i) = entries[..browse.index].iter().rposition(|entry| entry.starts_with(&browse.typed)) {
+            browse.index = i;
+            self.input.set(&entries[i]);
+            self.suggestions.clear();
+        }
+    }
+
+    // the oldest entry after the current one that starts with what was typed, and past the newest, what was typed
+    pub fn newer(&mut self) {
+        let entries = self.history.entries(self.kind());
+        let Some(browse) = &mut self.browse else { return };
+        match entries.iter().enumerate().skip(browse.index + 1).find(|(_, entry)| entry.starts_with(&browse.typed)) {
+            Some((i, entry)) => {
+                browse.index = i;
+                self.input.set(entry);
+            },
+            None => {
+                self.input.set(&browse.typed);
+                self.browse = None;
+            },
+        }
+        self.suggestions.clear();
+    }
+
+    // ctrl + r starts a reverse search, and again goes to the next older entry containing the query
+    pub fn search_older(&mut self) {
+        let Some(query) = &self.reverse_search else {
+            self.reverse_search = Some(String::new());
+            self.browse = Some(Browse { typed: self.input.text().to_string(), index: self.history.entries(self.kind()).len() });
+            return;
+        };
+        let entries = self.history.entries(self.kind());
+        let browse = self.browse.get_or_insert_with(|| Browse { typed: self.input.text().to_string(), index: entries.len() });
+        if let Some(i) = entries[..browse.index].iter().rposition(|entry| entry.contains(query.as_str())) {
+            browse.index = i;
+            self.input.set(&entries[i]);
+        }
+        self.suggestions.clear();
+    }
+
+    // typing during a reverse search changes the query and goes to the newest entry with it. any other key ends the
+    // search with the entry found left in the command, and false is returned so the key does what it usually does
+    pub fn search_history(&mut self, key: KeyEvent) -> bool {
+        let kind = self.kind();
+        let Some(query) = &mut self.reverse_search else { return false };
+        match (key.modifiers, key.code) {
+            (KeyModifiers::NONE | KeyModifiers::SHIFT, KeyCode::Char(c)) => query.push(c),
+            (KeyModifiers::NONE, KeyCode::Backspace) => {
+                query.pop();
+            },
+            // left for search_older
+            (KeyModifiers::CONTROL, KeyCode::Char('r')) => return false,
+            _ => {
+                self.reverse_search = None;
+                self.browse = None;
+                return false;
+            },
+        }
+        let entries = self.history.entries(kind);
+        if let Some(browse) = &mut self.browse {
+            if let Some(i) = entries.iter().rposition(|entry| entry.contains(query.as_str())) {
+                browse.index = i;
+                self.input.set(&entries[i]);
+            }
         }
+        self.suggestions.clear();
+        true
     }
 
-    pub fn render(&mut self) -> Vec<ColourString> {
-        let (terminal_x, terminal_y) = terminal::size().unwrap();
-        let mut frame: Vec<ColourString> = vec![ColourString::new(String::from(" ".repeat(terminal_x as usize)), None); terminal_y as usize];
+    pub fn render(&mut self, theme: &Theme, (terminal_x, terminal_y): (u16, u16)) -> Vec<ColourString> {
+        let mut frame: Vec<ColourString> = vec![ColourString::new(" ".repeat(terminal_x as usize), None); terminal_y as usize];
 
-        let command = format!("▏:{}▕", self.command);
-        let command_len = command.graphemes(true).count();
+        let prefix = match self.mode {
+            PaletteMode::Command => ':',
+            PaletteMode::Search { backward: false } => '/',
+            PaletteMode::Search { backward: true } => '?',
+        };
+        let hint = match &self.reverse_search {
+            Some(query) => format!("  (reverse search: {})", query),
+            None if self.hint.is_empty() => String::new(),
+            None => format!("  ({})", self.hint),
+        };
+        let command = format!("▏{}{}{}▕", prefix, self.input.text(), hint);
+        let command_len = text_width(&command);
         let start = if terminal_y / 8 > 1 { terminal_y / 8 } else { 2 };
-        let padding = ((terminal_x - command.len() as u16)/2) as usize;
+        let padding = (terminal_x as usize).saturating_sub(command_len) / 2;
 
-        let border_colour = Info::new(Color::White, Color::Reset, vec![]);
-        let mut command = ColourString::new(command, Some(Info::new(Color::White, Color::Reset, vec![])));
+        let border_colour = Info::new(theme.border, Color::Reset, vec![]);
+        let mut command = ColourString::new(command, Some(Info::new(theme.text, Color::Reset, vec![])));
 
         command.set_colour(border_colour.clone(), 0, 1);
         command.set_colour(border_colour.clone(), command_len-1, command_len);
+        command.set_colour(Info::new(theme.dimmed, Color::Reset, vec![]), command_len-1-text_width(&hint), command_len-1);
         // command.replace_char(" ".to_string(), "█".to_string(), Some(Info::new(Color::Black, Color::Reset, vec![])));
 
-        frame[(start-1) as usize].replace_range(padding, terminal_x as usize, ColourString::new("▁".repeat(command_len), Some(border_colour.clone())));
-        frame[start as usize].replace_range(padding, terminal_x as usize, command);
-        frame[(start+1) as usize].replace_range(padding, terminal_x as usize, ColourString::new("▔".repeat(command_len), Some(border_colour.clone())));
+        // rows the terminal is too short for are left out
+        let mut rows = vec![
+            ColourString::new("▁".repeat(command_len), Some(border_colour.clone())),
+            command,
+            ColourString::new("▔".repeat(command_len), Some(border_colour.clone())),
+        ];
+        rows.extend(self.render_suggestions(theme, command_len));
+        for (line, row) in frame.iter_mut().skip((start - 1) as usize).zip(rows) {
+            line.replace_range(padding, terminal_x as usize, row);
+        }
 
-        self.cursor.update((padding + self.command.graphemes(true).count() + 2) as u16, start);
+        let x = (padding + self.input.cursor_width() + 2).min(terminal_x.saturating_sub(1) as usize);
+        self.cursor.update(x as u16, start.min(terminal_y.saturating_sub(1)));
 
         frame
     }
-}
\ No newline at end of file
+
+    // at least as wide as the command, with the typed characters in bold and the selected suggestion shaded
+    fn render_suggestions(&self, theme: &Theme, min_width: usize) -> Vec<ColourString> {
+        if self.suggestions.is_empty() {
+            return vec![];
+        }
+        let border_colour = Info::new(theme.border, Color::Reset, vec![]);
+        let width = self.suggestions.iter().map(|suggestion| text_width(&suggestion.name)).max().unwrap_or(0).max(min_width.saturating_sub(2));
+        let first = self.selected.map_or(0, |selected| (selected + 1).saturating_sub(SUGGESTION_ROWS));
+
+        let mut rows = vec![];
+        for (i, suggestion) in self.suggestions.iter().enumerate().skip(first).take(SUGGESTION_ROWS) {
+            let background = if self.selected == Some(i) { theme.selection } else { Color::Reset };
+            let mut row = ColourString::new(format!("▏{}▕", suggestion.name), Some(Info::new(theme.dimmed, background, vec![])));
+            row.set_colour(border_colour.clone(), 0, 1);
+            let mut column = 1;
+            for (i, grapheme) in suggestion.name.graphemes(true).enumerate() {
+                let grapheme_width = grapheme_width(grapheme);
+                if suggestion.matched.contains(&i) {
+                    row.set_colour(Info::new(theme.text, background, vec![Attribute::Bold]), column, column + grapheme_width);
+                }
+                column += grapheme_width;
+            }
+            let end = row.get_content().len() - 1;
+            row.insert(end, " ".repeat(width - text_width(&suggestion.name)), Some(Info::new(theme.text, background, vec![])));
+            row.set_colour(border_colour.clone(), width + 1, width + 2);
+            rows.push(row);
+        }
+        rows.push(ColourString::new("▔".repeat(width + 2), Some(border_colour)));
+        rows
+    }
+}
@@ -0,0 +1,114 @@
+// what a command takes after its name, with the name shown for it in help
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Arguments {
+    None,
+    Optional(&'static str),
+    Required(&'static str),
+    // any number, including none
+    Many(&'static str),
+}
+
+pub struct Command {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub arguments: Arguments,
+    // commands that work on the current file aren't run without one
+    pub needs_file: bool,
+    pub help: &'static str,
+}
+
+pub const COMMANDS: [Command; 22] = [
+    Command { name: "quit", aliases: &["q"], arguments: Arguments::None, needs_file: false, help: "close the current pane, or quit when it's the last one, asking about unsaved changes" },
+    Command { name: "quit!", aliases: &["q!"], arguments: Arguments::None, needs_file: false, help: "close the current pane, or quit when it's the last one, throwing away unsaved changes" },
+    Command { name: "quitall", aliases: &["qa"], arguments: Arguments::None, needs_file: false, help: "quit, asking about unsaved changes" },
+    Command { name: "quitall!", aliases: &["qa!"], arguments: Arguments::None, needs_file: false, help: "quit, throwing away unsaved changes" },
+    Command { name: "close", aliases: &[], arguments: Arguments::None, needs_file: false, help: "close the current pane" },
+    Command { name: "open", aliases: &["o"], arguments: Arguments::Required("path"), needs_file: false, help: "open a file" },
+    Command { name: "cd", aliases: &[], arguments: Arguments::Optional("directory"), needs_file: false, help: "change the working directory that paths are relative to, or go home" },
+    Command { name: "save", aliases: &["s", "w"], arguments: Arguments::Optional("path"), needs_file: true, help: "save the current file, or a copy of it to path" },
+    Command { name: "saveas", aliases: &[], arguments: Arguments::Required("path"), needs_file: true, help: "save the current file to path and carry on editing it there" },
+    Command { name: "saveall", aliases: &["wa"], arguments: Arguments::None, needs_file: false, help: "save every file with unsaved changes" },
+    Command { name: "wq", aliases: &["x"], arguments: Arguments::None, needs_file: true, help: "save the current file, then close the pane or quit like quit" },
+    Command { name: "split", aliases: &["sp"], arguments: Arguments::Optional("path"), needs_file: true, help: "split the current pane in two, one above the other, and open path in the new one" },
+    Command { name: "vsplit", aliases: &["vs"], arguments: Arguments::Optional("path"), needs_file: true, help: "split the current pane in two side by side, and open path in the new one" },
+    Command { name: "set", aliases: &[], arguments: Arguments::Many("option"), needs_file: true, help: "set options on the current file: ff, fenc, bomb, ts, et or syntax" },
+    Command { name: "reopen-with-encoding", aliases: &[], arguments: Arguments::Required("encoding"), needs_file: true, help: "read the current file again with another encoding" },
+    Command { name: "registers", aliases: &["reg"], arguments: Arguments::Many("names"), needs_file: false, help: "show what the registers hold" },
+    Command { name: "theme", aliases: &[], arguments: Arguments::Optional("name"), needs_file: false, help: "switch colour theme, or list the available ones" },
+    Command { name: "syntaxes", aliases: &[], arguments: Arguments::None, needs_file: false, help: "list the built in languages and the bundled and loaded grammars" },
+    Command { name: "nohlsearch", aliases: &["noh"], arguments: Arguments::None, needs_file: false, help: "stop highlighting search matches" },
+    Command { name: "undo", aliases: &["u"], arguments: Arguments::None, needs_file: true, help: "undo the last change" },
+    Command { name: "redo", aliases: &["red"], arguments: Arguments::None, needs_file: true, help: "redo the last undone change" },
+    Command { name: "help", aliases: &["h"], arguments: Arguments::Optional("command"), needs_file: false, help: "list the commands, or explain one" },
+];
+
+impl Command {
+    // by name or alias, ignoring case
+    pub fn find(name: &str) -> Option<&'static Command> {
+        let name = name.to_lowercase();
+        COMMANDS.iter().find(|command| command.name == name || command.aliases.contains(&name.as_str()))
+    }
+
+    // `open (o) <path>`
+    pub fn usage(&self) -> String {
+        let mut usage = String::from(self.name);
+        if !self.aliases.is_empty() {
+            usage.push_str(&format!(" ({})", self.aliases.join(", ")));
+        }
+        match self.arguments {
+            Arguments::None => {},
+            Arguments::Optional(name) => usage.push_str(&format!(" [{}]", name)),
+            Arguments::Required(name) => usage.push_str(&format!(" <{}>", name)),
+            Arguments::Many(name) => usage.push_str(&format!(" [{}...]", name)),
+        }
+        usage
+    }
+
+    fn check(&self, args: &[String]) -> Result<(), String> {
+        let fits = match self.arguments {
+            Arguments::None => args.is_empty(),
+            Arguments::Optional(_) => args.len() <= 1,
+            Arguments::Required(_) => args.len() == 1,
+            Arguments::Many(_) => true,
+        };
+        if fits { Ok(()) } else { Err(format!("Usage: {}", self.usage())) }
+    }
+}
+
+// a command line split like a shell would, quotes keep spaces in an argument and a backslash escapes the next character
+pub fn parse(line: &str) -> Result<(&'static Command, Vec<String>), String> {
+    let mut words = shlex::split(line).ok_or(String::from("Unterminated quote"))?.into_iter();
+    let name = words.next().ok_or(String::from("No command given"))?;
+    let command = Command::find(&name).ok_or_else(|| match suggest(&name) {
+        Some(suggestion) => format!("{}: Command not found, did you mean {}?", name, suggestion),
+        None => format!("{}: Command not found", name),
+    })?;
+    let args: Vec<String> = words.collect();
+    command.check(&args).map_err(|e| format!("{}: {}", command.name, e))?;
+    Ok((command, args))
+}
+
+// the closest name or alias, if it's close enough to be a typo
+fn suggest(name: &str) -> Option<&'static str> {
+    let name = name.to_lowercase();
+    COMMANDS.iter()
+        .flat_map(|command| std::iter::once(command.name).chain(command.aliases.iter().copied()))
+        .map(|candidate| (strsim::damerau_levenshtein(&name, candidate), candidate))
+        .filter(|&(distance, candidate)| distance <= (candidate.len() / 3).max(1))
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate)
+}
+
+// every command on one line, or one command in full
+pub fn help(name: Option<&str>) -> Result<String, String> {
+    match name {
+        Some(name) => {
+            let command = Command::find(name).ok_or(format!("{}: Command not found", name))?;
+            Ok(format!("{} - {}", command.usage(), command.help))
+        },
+        None => {
+            let names: Vec<String> = COMMANDS.iter().map(Command::usage).collect();
+            Ok(format!("commands: {} | :help <command> for more", names.join(", ")))
+        },
+    }
+}
@@ -0,0 +1,103 @@
+use std::{fs, path::Path};
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::commands::{Arguments, Command, COMMANDS};
+
+// more than this many aren't worth cycling through
+const LIMIT: usize = 50;
+
+pub struct Suggestion {
+    // what the dropdown shows
+    pub name: String,
+    // graphemes of the name the typed text matched
+    pub matched: Vec<usize>,
+    // the command line with this suggestion in place of what was typed
+    pub line: String,
+}
+
+// the command names matching the first word, or the paths matching the argument of a command that takes one
+pub fn suggestions(line: &str) -> Vec<Suggestion> {
+    let Some((name, rest)) = line.split_once(char::is_whitespace) else {
+        return commands(line);
+    };
+    let Some(command) = Command::find(name) else { return vec![] };
+    let directories_only = match command.arguments {
+        Arguments::Required("path") | Arguments::Optional("path") => false,
+        Arguments::Required("directory") | Arguments::Optional("directory") => true,
+        _ => return vec![],
+    };
+    // an argument still being typed may not have its closing quote yet, and one followed by a space is finished
+    let rest = rest.trim_start();
+    let finished = shlex::split(rest).is_some() && rest.ends_with(char::is_whitespace) && !rest.ends_with("\\ ");
+    let words = shlex::split(rest).or_else(|| shlex::split(&format!("{}\"", rest))).or_else(|| shlex::split(&format!("{}'", rest)));
+    match words.as_deref() {
+        Some([]) => paths(name, "", directories_only),
+        Some([typed]) if !finished => paths(name, typed, directories_only),
+        _ => vec![],
+    }
+}
+
+fn commands(typed: &str) -> Vec<Suggestion> {
+    let mut matches: Vec<(usize, Suggestion)> = COMMANDS.iter().filter_map(|command| {
+        let (score, matched) = fuzzy_match(typed, command.name)?;
+        Some((score, Suggestion { name: command.name.to_string(), matched, line: command.name.to_string() }))
+    }).collect();
+    // registry order when nothing has been typed
+    matches.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+    matches.into_iter().map(|(_, suggestion)| suggestion).collect()
+}
+
+// entries of the directory typed so far, relative to the working directory, matched against the rest of the path
+fn paths(command: &str, typed: &str, directories_only: bool) -> Vec<Suggestion> {
+    let (directory, partial) = match typed.rfind('/') {
+        Some(i) => typed.split_at(i + 1),
+        None => ("", typed),
+    };
+    let Ok(entries) = fs::read_dir(if directory.is_empty() { Path::new(".") } else { Path::new(directory) }) else { return vec![] };
+
+    let mut matches = vec![];
+    for entry in entries.flatten() {
+        let is_dir = entry.path().is_dir();
+        let name = entry.file_name().to_string_lossy().to_string();
+        // hidden files only once a dot has been typed
+        if (directories_only && !is_dir) || (name.starts_with('.') && !partial.starts_with('.')) {
+            continue;
+        }
+        let Some((score, matched)) = fuzzy_match(partial, &name) else { continue };
+        let name = if is_dir { format!("{}/", name) } else { name };
+        let path = format!("{}{}", directory, name);
+        let line = format!("{} {}", command, shlex::try_quote(&path).unwrap_or_default());
+        matches.push((score, Suggestion { name, matched, line }));
+    }
+    matches.sort_by(|(a, first), (b, second)| b.cmp(a).then_with(|| first.name.cmp(&second.name)));
+    matches.into_iter().take(LIMIT).map(|(_, suggestion)| suggestion).collect()
+}
+
+// whether every grapheme of `pattern` is in `candidate` in order, ignoring case, and which ones matched. matches next to
+// each other or at the start of a word score higher
+pub fn fuzzy_match(pattern: &str, candidate: &str) -> Option<(usize, Vec<usize>)> {
+    let mut pattern = pattern.graphemes(true).map(str::to_lowercase).peekable();
+    let mut matched: Vec<usize> = vec![];
+    let mut score = 0;
+    let mut previous = None;
+    for (i, grapheme) in candidate.graphemes(true).enumerate() {
+        let Some(wanted) = pattern.peek() else { break };
+        if grapheme.to_lowercase() == *wanted {
+            score += 1;
+            if matched.last().is_some_and(|last| last + 1 == i) {
+                score += 2;
+            }
+            if previous.is_none_or(|previous: &str| matches!(previous, "/" | "-" | "_" | "." | " ")) {
+                score += 3;
+            }
+            matched.push(i);
+            pattern.next();
+        }
+        previous = Some(grapheme);
+    }
+    if pattern.peek().is_some() {
+        return None;
+    }
+    Some((score, matched))
+}
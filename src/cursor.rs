@@ -1,10 +1,11 @@
 use crossterm::{self, event::KeyCode};
 
+#[derive(Clone)]
 pub struct Cursor {
     pub x: u16,
     pub y: u16,
-    pub x_offset: u16,
-    pub y_offset: u16,
+    pub x_offset: usize,
+    pub y_offset: usize,
     pub x_max: u16,
     pub y_max: u16,
     pub x_min: u16,
@@ -12,23 +13,32 @@ pub struct Cursor {
 }
 
 impl Cursor {
+    // the limits are set by whatever the cursor is in each time it's drawn
     pub fn new() -> Self {
-        let (x_limit, y_limit) = crossterm::terminal::size().unwrap();
         Self {
             x: 0,
             y: 0,
             x_offset: 0,
             y_offset: 0,
-            x_max: x_limit,
-            y_max: y_limit-2,
+            x_max: 0,
+            y_max: 0,
             x_min: 0,
             y_min: 0,
         }
     }
 
+    // when the area shrinks the cursor stays on the same position and the view scrolls to keep it in sight
     pub fn set_max(&mut self, x: u16, y: u16) {
-        self.x_max = x;
-        self.y_max = y;
+        self.x_max = x.max(self.x_min);
+        self.y_max = y.max(self.y_min);
+        if self.x > self.x_max {
+            self.x_offset += (self.x - self.x_max) as usize;
+            self.x = self.x_max;
+        }
+        if self.y > self.y_max {
+            self.y_offset += (self.y - self.y_max) as usize;
+            self.y = self.y_max;
+        }
     }
 
     pub fn set_min(&mut self, x: u16, y: u16) {
@@ -41,20 +51,43 @@ impl Cursor {
         self.y = y;
     }
 
+    pub fn position(&self) -> (usize, usize) {
+        (self.x.saturating_sub(self.x_min) as usize + self.x_offset, self.y.saturating_sub(self.y_min) as usize + self.y_offset)
+    }
+
+    pub fn set_position(&mut self, x: usize, y: usize) {
+        let width = self.x_max.saturating_sub(self.x_min) as usize;
+        let height = self.y_max.saturating_sub(self.y_min) as usize;
+
+        if x < self.x_offset {
+            self.x_offset = x;
+        } else if x > self.x_offset.saturating_add(width) {
+            self.x_offset = x - width;
+        }
+
+        if y < self.y_offset {
+            self.y_offset = y;
+        } else if y > self.y_offset.saturating_add(height) {
+            self.y_offset = y - height;
+        }
+
+        self.update((x - self.x_offset) as u16 + self.x_min, (y - self.y_offset) as u16 + self.y_min);
+    }
+
     pub fn move_to(&mut self, mut x: u16, mut y: u16) {
         if x > self.x_max {
-            self.x_offset += x-self.x_max;
+            self.x_offset += (x - self.x_max) as usize;
             x = self.x_max;
         } else if x < self.x_min {
-            self.x_offset = self.x_offset.saturating_sub(x-self.x_min);
+            self.x_offset = self.x_offset.saturating_sub((self.x_min - x) as usize);
             x = self.x_min;
         }
         
         if y > self.y_max {
-            self.y_offset += y-self.y_max;
+            self.y_offset += (y - self.y_max) as usize;
             y = self.y_max;
         } else if y < self.y_min {
-            self.y = self.y_offset.saturating_sub(y.saturating_sub(self.y_min));
+            self.y_offset = self.y_offset.saturating_sub((self.y_min - y) as usize);
             y = self.y_min; 
         }
 
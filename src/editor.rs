@@ -1,184 +1,672 @@
 use crate::colour_string::{ColourString, Info};
 use crate::cursor::Cursor;
 use crate::syntax_highlighter::SyntaxHighlighter;
-use crossterm::{Result, terminal};
+use crate::syntaxes::Syntax;
+use crate::history::{History, Operation, end_position};
+use crate::buffer::{Buffer, LineEnding, advance};
+use crate::encoding::FileEncoding;
+use crate::hex_view::{HexView, BYTES_PER_ROW};
+use crate::search::Search;
+use crate::selection::{Selection, VisualMode};
+use crate::theme::Theme;
+use regex::Regex;
+use crossterm::{Result, event::KeyCode};
 use crossterm::style::Color;
+use std::collections::HashMap;
 use std::fs;
+use std::io::{BufWriter, Error, ErrorKind};
 use std::path::Path;
 use unicode_segmentation::UnicodeSegmentation;
 
+// room for the `00000000  ` offset column of the hex view
+const HEX_GUTTER: u16 = 10;
+
 pub struct File {
     pub path: String,
     pub name: String,
-    pub lines: Vec<String>,
+    pub buffer: Buffer,
     pub cursor: Cursor,
+    pub gutter: u16,
+    pub encoding: FileEncoding,
+    // what the file on disk was written as, so setting the encoding back to it leaves nothing to save
+    saved_encoding: FileEncoding,
+    pub line_ending: LineEnding,
+    pub mixed_line_endings: bool,
+    pub tab_width: usize,
+    pub expand_tab: bool,
     pub modified: bool,
     pub insert: bool,
-    pub highlighted_lines: Vec<ColourString>,
+    pub highlighted_lines: HashMap<usize, ColourString>,
     pub syntax_highlighter: SyntaxHighlighter,
+    pub history: History,
+    pub hex: Option<HexView>,
+    // first and last row of the last visual selection, used by the '<,'> range
+    pub last_visual: Option<(usize, usize)>,
+    pub selection: Option<Selection>,
+    // extra cursors as grapheme positions, `cursor` stays the one the view follows
+    pub cursors: Vec<(usize, usize)>,
 }
 
 impl File {
     pub fn new(path: String) -> Result<Self> {
         let path = Path::new(&path);
         let name = path.file_name().unwrap().to_str().unwrap().to_string();
-        let lines: Vec<String> = fs::read_to_string(path)?.lines().map(|s| s.to_string().replace("\t", "    ")).collect();
-
-        let mut syntax_highlighter = SyntaxHighlighter::new();
-        syntax_highlighter.init(lines.clone());
+        let bytes = fs::read(path)?;
+        let encoding = FileEncoding::detect(&bytes);
+        let binary = !encoding.is_utf_16() && HexView::is_binary(&bytes);
+        let text = if binary { String::new() } else { encoding.decode(&bytes) };
+        let hex = binary.then(|| HexView::new(bytes));
 
-        let highlighted_lines: Vec<ColourString> = lines.iter().map(|line| {
-            syntax_highlighter.highlight(line.to_string())
-        }).collect();
+        let (line_ending, mixed_line_endings) = LineEnding::detect(&text);
+        let expand_tab = !(text.starts_with('\t') || text.contains("\n\t"));
+        let buffer = Buffer::new(&text);
+        drop(text);
+        let language = if hex.is_some() { None } else { Syntax::detect(&path.to_string_lossy(), &buffer.line(0)) };
 
         Ok(Self {
             path: fs::canonicalize(path)?.to_str().unwrap().to_string(),
             name,
-            lines,
+            gutter: if hex.is_some() { HEX_GUTTER } else { Self::gutter_width(buffer.len_lines()) },
+            buffer,
+            encoding,
+            saved_encoding: encoding,
+            line_ending,
+            mixed_line_endings,
+            tab_width: 4,
+            expand_tab,
             cursor: Cursor::new(),
             modified: false,
             insert: false,
-            highlighted_lines,
-            syntax_highlighter,
+            highlighted_lines: HashMap::new(),
+            syntax_highlighter: SyntaxHighlighter::new(language),
+            history: History::new(),
+            hex,
+            last_visual: None,
+            selection: None,
+            cursors: Vec::new(),
         })
     }
 
-    pub fn refresh_highlight(&mut self) {
-        ("refresh");
-        self.syntax_highlighter.init(self.lines.clone());
-        self.highlighted_lines = self.lines.iter().map(|line| {
-            self.syntax_highlighter.highlight(line.to_string())
-        }).collect();
+    pub fn len_bytes(&self) -> usize {
+        match &self.hex {
+            Some(hex) => hex.bytes.len(),
+            None => self.buffer.len_bytes(),
+        }
     }
 
-    pub fn save(&mut self) -> Result<ColourString> {
-        fs::write(&self.path, self.lines.join("\n"))?;
-        Ok(ColourString::new(format!("saved file to {}", self.path), Some(Info::new(Color::Green, Color::Reset, vec![]))))
+    fn gutter_width(lines: usize) -> u16 {
+        lines.to_string().len().max(4) as u16 + 1
     }
 
-    pub fn insert_char(&mut self, c: char) {
-        ("insert");
-        while self.lines.len() <= (self.cursor.y + self.cursor.y_offset - self.cursor.y_min) as usize {
-            self.lines.push(String::new());
+    // lines are only highlighted once they are drawn, so opening a large file stays cheap
+    fn highlighted_line(&mut self, y: usize, theme: &Theme) -> ColourString {
+        if !self.highlighted_lines.contains_key(&y) {
+            let line = self.syntax_highlighter.highlight(&self.buffer, y, theme).expand_tabs(self.tab_width);
+            self.highlighted_lines.insert(y, line);
         }
+        self.highlighted_lines[&y].clone()
+    }
 
-        while self.highlighted_lines.len() <= (self.cursor.y + self.cursor.y_offset - self.cursor.y_min) as usize {
-            self.highlighted_lines.push(ColourString::new(String::new(), None));
+    pub fn set_option(&mut self, option: &str) -> std::result::Result<(), String> {
+        if self.hex.is_some() {
+            return Err(String::from("Options can't be set on a binary file"));
         }
+        let (name, value) = option.split_once('=').unwrap_or((option, ""));
+        match (name, value) {
+            ("ff" | "fileformat", value) => {
+                let line_ending = LineEnding::parse(value).ok_or(format!("{}: Invalid file format", value))?;
+                self.set_line_ending(line_ending);
+            },
+            ("syn" | "syntax", value) => {
+                let syntax = match value {
+                    "" | "none" | "off" => None,
+                    name => Some(Syntax::find(name).ok_or(format!("{}: Unknown syntax", name))?),
+                };
+                self.syntax_highlighter = SyntaxHighlighter::new(syntax);
+                self.highlighted_lines.clear();
+            },
+            ("ts" | "tabstop", value) => {
+                self.tab_width = value.parse().ok().filter(|&width| width > 0).ok_or(format!("{}: Invalid tab width", value))?;
+                self.highlighted_lines.clear();
+            },
+            ("fenc" | "fileencoding", value) => {
+                self.encoding = FileEncoding::from_label(value).ok_or(format!("{}: Unknown encoding", value))?;
+                self.update_modified();
+            },
+            ("bomb", "") | ("nobomb", "") => {
+                self.encoding.bom = name == "bomb";
+                self.update_modified();
+            },
+            ("et" | "expandtab", "") => self.expand_tab = true,
+            ("noet" | "noexpandtab", "") => self.expand_tab = false,
+            _ => return Err(format!("{}: Unknown option", option)),
+        }
+        Ok(())
+    }
 
-        let line = self.lines.get_mut((self.cursor.y + self.cursor.y_offset - self.cursor.y_min) as usize).unwrap();
-        let mut graphemes = line.graphemes(true).collect::<Vec<&str>>();
-        
-        let x = self.cursor.x + self.cursor.x_offset - self.cursor.x_min;
-        let c = c.to_string();
-        if graphemes.len() < (x) as usize {
-            while graphemes.len() < (x) as usize {
-                graphemes.push(" ");
-            }
+    // swaps the whole text in one group so a single undo converts it back. the history holds a copy of the old and
+    // new text, still less than an operation and a re-lex for every line of a large file would cost
+    pub fn set_line_ending(&mut self, line_ending: LineEnding) {
+        let text = self.buffer.text();
+        let mut converted = text.replace("\r\n", "\n");
+        if line_ending == LineEnding::Crlf {
+            converted = converted.replace('\n', "\r\n");
         }
-        graphemes.insert((x) as usize, c.as_str());
-        *line = graphemes.join("");
-        self.cursor.x += 1;
 
-        self.highlighted_lines[(self.cursor.y + self.cursor.y_offset - self.cursor.y_min) as usize] = self.syntax_highlighter.highlight(line.to_string());
-        self.modified = true;
+        if converted != text {
+            let cursor = self.cursor_index();
+            self.history.begin_group();
+            self.edit(Operation::Remove { position: (0, 0), text }, (0, 0), false);
+            self.edit(Operation::Insert { position: (0, 0), text: converted }, cursor, false);
+            self.history.end_group();
+        }
+        self.line_ending = line_ending;
+        self.mixed_line_endings = false;
     }
 
-    pub fn backspace(&mut self) {
-        ("backspace");
-        let y = self.cursor.y + self.cursor.y_offset - self.cursor.y_min;
+    pub fn file_format(&self) -> &'static str {
+        if self.mixed_line_endings { "mixed" } else { self.line_ending.name() }
+    }
+
+    // the cursor stores display columns, edits work on grapheme indices
+    pub fn cursor_index(&self) -> (usize, usize) {
+        let (x, y) = self.cursor.position();
+        if y < self.buffer.len_lines() {
+            (self.buffer.grapheme_index(y, x, self.tab_width), y)
+        }
+        else {
+            (x, y)
+        }
+    }
+
+    pub fn set_cursor_index(&mut self, position: (usize, usize)) {
+        let (x, y) = position;
+        let column = if y < self.buffer.len_lines() { self.buffer.display_column(position, self.tab_width) } else { x };
+        self.cursor.set_position(column, y);
+    }
+
+    fn set_hex_offset(&mut self, offset: usize) {
+        if let Some(hex) = &mut self.hex {
+            hex.move_to(offset);
+            self.sync_hex_cursor();
+        }
+    }
+
+    fn sync_hex_cursor(&mut self) {
+        if let Some(hex) = &self.hex {
+            let (x, y) = hex.cursor_position();
+            self.cursor.set_position(x, y);
+        }
+    }
+
+    pub fn move_to(&mut self, x: u16, y: u16) {
+        self.cursor.move_to(x, y);
+        if self.hex.is_some() {
+            let (x, y) = self.cursor.position();
+            self.set_hex_offset(y * BYTES_PER_ROW + HexView::byte_column(x));
+        }
+        else {
+            self.snap();
+        }
+    }
+
+    // a column in the middle of a wide grapheme or a tab moves to where it starts
+    fn snap(&mut self) {
+        let position = self.cursor_index();
+        self.set_cursor_index(position);
+    }
+
+    pub fn parse_direction(&mut self, direction: KeyCode) {
+        if let Some(hex) = &self.hex {
+            let offset = match direction {
+                KeyCode::Left | KeyCode::Char('a') => hex.offset.saturating_sub(1),
+                KeyCode::Right | KeyCode::Char('d') => hex.offset + 1,
+                KeyCode::Up | KeyCode::Char('w') => hex.offset.saturating_sub(BYTES_PER_ROW),
+                KeyCode::Down | KeyCode::Char('s') => hex.offset + BYTES_PER_ROW,
+                _ => hex.offset,
+            };
+            return self.set_hex_offset(offset);
+        }
+
+        let (x, y) = self.cursor_index();
+        match direction {
+            KeyCode::Left | KeyCode::Char('a') => self.set_cursor_index((x.saturating_sub(1), y)),
+            KeyCode::Right | KeyCode::Char('d') => self.set_cursor_index((x + 1, y)),
+            direction => {
+                self.cursor.parse_direction(direction);
+                self.snap();
+            },
+        }
+    }
+
+    // decodes the file on disk again, dropping the undo history
+    pub fn reopen_with_encoding(&mut self, label: &str) -> std::result::Result<(), String> {
+        if self.modified {
+            return Err(String::from("No write since last change"));
+        }
+        let encoding = FileEncoding::from_label(label).ok_or(format!("{}: Unknown encoding", label))?;
+        let bytes = fs::read(&self.path).map_err(|e| e.to_string())?;
+
+        let text = encoding.decode(&bytes);
+        (self.line_ending, self.mixed_line_endings) = LineEnding::detect(&text);
+        self.buffer = Buffer::new(&text);
+        self.encoding = encoding;
+        self.saved_encoding = encoding;
+        self.hex = None;
+        self.history = History::new();
+        self.highlighted_lines.clear();
+        self.syntax_highlighter.reset();
+
+        let (x, y) = self.cursor_index();
+        self.set_cursor_index((x, y.min(self.buffer.len_lines() - 1)));
+        Ok(())
+    }
+
+    pub fn save(&mut self) -> Result<String> {
+        self.write(&self.path)?;
+        self.history.mark_saved();
+        self.saved_encoding = self.encoding;
+        self.modified = false;
+        Ok(format!("saved file to {}", self.path))
+    }
+
+    // the file is kept at its own path and still has whatever changes it had
+    pub fn save_copy(&self, path: &str) -> Result<String> {
+        self.write(path)?;
+        Ok(format!("saved a copy to {}", path))
+    }
+
+    // the file moves to `path` and is saved there
+    pub fn save_as(&mut self, path: &str) -> Result<String> {
+        self.write(path)?;
+        let path = Path::new(path);
+        self.name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        self.path = fs::canonicalize(path)?.to_string_lossy().to_string();
+        self.history.mark_saved();
+        self.saved_encoding = self.encoding;
+        self.modified = false;
+        Ok(format!("saved file to {}", self.path))
+    }
+
+    fn write(&self, path: &str) -> Result<()> {
+        if let Some(hex) = &self.hex {
+            fs::write(path, &hex.bytes)?;
+        }
+        else if self.encoding == FileEncoding::new(encoding_rs::UTF_8, false) {
+            self.buffer.write_to(BufWriter::new(fs::File::create(path)?))?;
+        }
+        else {
+            let bytes = self.encoding.encode(&self.buffer.text()).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+            fs::write(path, bytes)?;
+        }
+        Ok(())
+    }
+
+    // returns the text that was actually removed
+    fn apply(&mut self, operation: &Operation) -> Option<String> {
+        let (position, removed) = match operation {
+            Operation::Insert { position, text } => {
+                self.buffer.insert(*position, text);
+                (position, None)
+            },
+            Operation::Remove { position, text } => (position, Some(self.buffer.remove(*position, end_position(*position, text)))),
+            Operation::Overwrite { offset, new, .. } => {
+                if let Some(hex) = &mut self.hex {
+                    hex.bytes[*offset] = *new;
+                }
+                return None;
+            },
+        };
+        match (operation, &removed) {
+            (Operation::Insert { text, .. }, _) => self.rehighlight(position.1, 0, text.matches('\n').count()),
+            (_, Some(text)) => self.rehighlight(position.1, text.matches('\n').count(), 0),
+            _ => {},
+        }
+        for cursor in &mut self.cursors {
+            *cursor = operation.transform(*cursor);
+        }
+        removed
+    }
+
+    // drops the cached lines an edit changed and moves the ones below it
+    fn rehighlight(&mut self, first: usize, removed: usize, inserted: usize) {
+        let last = self.syntax_highlighter.edit(&self.buffer, first, removed, inserted);
+        self.highlighted_lines = std::mem::take(&mut self.highlighted_lines)
+            .into_iter()
+            .filter_map(|(y, line)| match y {
+                y if y < first => Some((y, line)),
+                y if y > first + removed && y - removed + inserted > last => Some((y - removed + inserted, line)),
+                _ => None,
+            })
+            .collect();
+    }
+
+    fn edit(&mut self, mut operation: Operation, cursor_after: (usize, usize), typing: bool) {
+        let cursor_before = self.cursor_index();
+        if let (Some(removed), Operation::Remove { text, .. }) = (self.apply(&operation), &mut operation) {
+            *text = removed;
+        }
+        self.set_cursor_index(cursor_after);
+        self.history.record(operation, cursor_before, cursor_after, typing);
+        self.update_modified();
+    }
+
+    // swaps `old` at position for `new`, callers group these so a whole command undoes at once
+    pub fn replace(&mut self, position: (usize, usize), old: &str, new: &str) {
+        if !old.is_empty() {
+            self.edit(Operation::Remove { position, text: old.to_string() }, position, false);
+        }
+        if !new.is_empty() {
+            self.edit(Operation::Insert { position, text: new.to_string() }, position, false);
+        }
+    }
+
+    fn update_modified(&mut self) {
+        self.modified = !self.history.is_saved() || self.encoding != self.saved_encoding;
+    }
+
+    // changes in the hex view keep the byte offset as the x of their cursor
+    fn restore_cursor(&mut self, position: (usize, usize)) {
+        if self.hex.is_some() {
+            self.set_hex_offset(position.0);
+        }
+        else {
+            self.set_cursor_index(position);
+        }
+    }
+
+    pub fn undo(&mut self) -> bool {
+        match self.history.undo() {
+            Some(change) => {
+                for operation in change.operations.iter().rev() {
+                    self.apply(&operation.inverse());
+                }
+                self.restore_cursor(change.cursor_before);
+                self.update_modified();
+                true
+            },
+            None => false
+        }
+    }
+
+    pub fn redo(&mut self) -> bool {
+        match self.history.redo() {
+            Some(change) => {
+                for operation in &change.operations {
+                    self.apply(operation);
+                }
+                self.restore_cursor(change.cursor_after);
+                self.update_modified();
+                true
+            },
+            None => false
+        }
+    }
+
+    // where text typed at (x, y) has to go, plus the newlines and spaces needed to reach it
+    fn padding(&self, x: usize, y: usize, pad_line: bool) -> ((usize, usize), String) {
+        let mut padding = String::new();
+        let (mut position, mut len) = ((0, y), 0);
 
-        if self.cursor.x > self.cursor.x_min {
-            let line = self.lines.get_mut(y as usize).unwrap();
-            let mut graphemes = line.graphemes(true).collect::<Vec<&str>>();
+        if y >= self.buffer.len_lines() {
+            let last = self.buffer.len_lines() - 1;
+            position = (self.buffer.line_len(last), last);
+            padding.push_str(&self.line_ending.as_str().repeat(y - last));
+        }
+        else {
+            len = self.buffer.line_len(y);
+            position.0 = x.min(len);
+        }
+
+        if pad_line && x > len {
+            padding.push_str(&" ".repeat(x - len));
+        }
+        (position, padding)
+    }
 
-            if graphemes.len() > (self.cursor.x + self.cursor.x_offset - self.cursor.x_min - 1) as usize  {
-                graphemes.remove((self.cursor.x + self.cursor.x_offset - self.cursor.x_min - 1) as usize);
+    // runs an edit at the main cursor and every extra one, each edit shifts the cursors after it
+    fn at_every_cursor(&mut self, mut action: impl FnMut(&mut Self)) {
+        if self.cursors.is_empty() {
+            return action(self);
+        }
+
+        self.cursors.push(self.cursor_index());
+        for i in 0..self.cursors.len() {
+            self.set_cursor_index(self.cursors[i]);
+            action(self);
+            self.cursors[i] = self.cursor_index();
+        }
+        let cursor = self.cursors.pop().unwrap();
+        self.set_cursor_index(cursor);
+        self.merge_cursors();
+    }
+
+    // cursors that end up in the same place become one
+    fn merge_cursors(&mut self) {
+        let cursor = self.cursor_index();
+        self.cursors.sort_unstable_by_key(|&(x, y)| (y, x));
+        self.cursors.dedup();
+        self.cursors.retain(|&position| position != cursor);
+    }
+
+    // the cursor can sit past the end of a line or the file, selections and extra cursors only reach real text
+    pub fn clamp(&self, (x, y): (usize, usize)) -> (usize, usize) {
+        let y = y.min(self.buffer.len_lines() - 1);
+        (x.min(self.buffer.line_len(y)), y)
+    }
+
+    // alt + click adds a cursor, or removes the one already there
+    pub fn toggle_cursor(&mut self, x: u16, y: u16) {
+        if self.hex.is_some() || x < self.cursor.x_min || y < self.cursor.y_min {
+            return;
+        }
+
+        let row = (y - self.cursor.y_min) as usize + self.cursor.y_offset;
+        let column = (x - self.cursor.x_min) as usize + self.cursor.x_offset;
+        let row = row.min(self.buffer.len_lines() - 1);
+        let position = self.clamp((self.buffer.grapheme_index(row, column, self.tab_width), row));
+
+        match self.cursors.iter().position(|&cursor| cursor == position) {
+            Some(index) => {
+                self.cursors.remove(index);
+            },
+            None => {
+                self.cursors.push(position);
+                self.merge_cursors();
+            },
+        }
+    }
+
+    // adds a cursor at the same spot in the next whole word occurrence of the word under the cursor
+    pub fn add_cursor_at_next_occurrence(&mut self) -> bool {
+        if self.hex.is_some() {
+            return false;
+        }
+
+        let (x, y) = self.clamp(self.cursor_index());
+        let line = self.buffer.line(y);
+        let graphemes: Vec<&str> = line.graphemes(true).collect();
+        let is_word = |grapheme: &str| grapheme.chars().all(|c| c.is_alphanumeric() || c == '_');
+        let x = if x < graphemes.len() && is_word(graphemes[x]) { x } else if x > 0 && is_word(graphemes[x - 1]) { x - 1 } else { return false };
+        let start = (0..x).rev().take_while(|&i| is_word(graphemes[i])).last().unwrap_or(x);
+        let end = (x..graphemes.len()).take_while(|&i| is_word(graphemes[i])).last().unwrap_or(x) + 1;
+        let offset = self.cursor_index().0.min(end) - start;
+
+        // searched for from the last cursor added, past the occurrences that already have one
+        let mut search = Search::new();
+        search.query = graphemes[start..end].concat();
+        search.smart_case = false;
+        search.whole_word = true;
+        let primary = self.cursor_index();
+        let last = self.cursors.last().copied().unwrap_or(primary);
+        let (mut from, mut first) = ((last.0.saturating_sub(offset), last.1), None);
+        while let Some(found) = search.find(&self.buffer, from, false, false) {
+            let position = (found.0 + offset, found.1);
+            if position != primary && !self.cursors.contains(&position) {
+                self.cursors.push(position);
+                return true;
+            }
+            if first == Some(found) {
+                break;
             }
-            *line = graphemes.join("");
-            self.highlighted_lines[y as usize] = self.syntax_highlighter.highlight(line.to_string());
-            self.cursor.x -= 1;
+            first.get_or_insert(found);
+            from = found;
         }
-        else if y != 0{
-            let line = self.lines.remove(y as usize);
-            self.highlighted_lines.remove(y as usize);
-            let prev_line = self.lines.get_mut(y.saturating_sub(1) as usize).unwrap();
+        false
+    }
 
-            self.cursor.y -= 1;
-            self.cursor.x = prev_line.graphemes(true).count() as u16 + self.cursor.x_min;
+    pub fn move_cursors(&mut self, direction: KeyCode) {
+        self.at_every_cursor(|file| file.parse_direction(direction));
+    }
 
-            prev_line.push_str(&line);
-            self.highlighted_lines[y as usize - 1] = self.syntax_highlighter.highlight(prev_line.to_string());
+    pub fn insert_char(&mut self, c: char) {
+        if let Some(hex) = &mut self.hex {
+            let (offset, old) = (hex.offset, hex.bytes.get(hex.offset).copied().unwrap_or_default());
+            if hex.overwrite(c) {
+                let operation = Operation::Overwrite { offset, old, new: hex.bytes[offset] };
+                let cursor_after = (hex.offset, 0);
+                // both digits of a byte, and the bytes typed after it, undo together like typed text
+                self.history.record(operation, (offset, 0), cursor_after, true);
+                self.update_modified();
+            }
+            return self.sync_hex_cursor();
         }
+        self.at_every_cursor(|file| file.insert_char_at_cursor(c));
+    }
 
-        self.modified = true;
+    fn insert_char_at_cursor(&mut self, c: char) {
+        let (x, y) = self.cursor_index();
+        let (position, mut text) = self.padding(x, y, true);
+        text.push(c);
+
+        self.edit(Operation::Insert { position, text }, (x + 1, y), true);
     }
 
-    pub fn enter(&mut self) {
-        ("enter");
-        let y = self.cursor.y + self.cursor.y_offset - self.cursor.y_min;
-        let line = self.lines.get_mut(y as usize).unwrap();
-        let mut graphemes = line.graphemes(true).collect::<Vec<&str>>();
+    pub fn insert_tab(&mut self) {
+        if self.hex.is_some() {
+            return;
+        }
+        self.at_every_cursor(|file| file.insert_tab_at_cursor());
+    }
 
-        let x = self.cursor.x + self.cursor.x_offset - self.cursor.x_min;
+    fn insert_tab_at_cursor(&mut self) {
+        if self.expand_tab {
+            let column = self.cursor.position().0;
+            for _ in column..advance(column, "\t", self.tab_width) {
+                self.insert_char_at_cursor(' ');
+            }
+        }
+        else {
+            self.insert_char_at_cursor('\t');
+        }
+    }
+
+    pub fn backspace(&mut self) {
+        if self.hex.is_some() {
+            return self.parse_direction(KeyCode::Left);
+        }
+        self.at_every_cursor(|file| file.backspace_at_cursor());
+    }
 
-        let mut new_line = String::new();
-        if graphemes.len() > x as usize {
-            new_line = graphemes.split_off(x as usize).join("");
+    fn backspace_at_cursor(&mut self) {
+        let (x, y) = self.cursor_index();
+
+        if x > 0 {
+            match (y < self.buffer.len_lines()).then(|| self.buffer.line(y)).and_then(|line| line.graphemes(true).nth(x - 1).map(|grapheme| grapheme.to_string())) {
+                Some(text) => self.edit(Operation::Remove { position: (x - 1, y), text }, (x - 1, y), true),
+                None => self.set_cursor_index((x - 1, y)),
+            }
         }
-        *line = graphemes.join("");
+        else if y != 0 && y < self.buffer.len_lines() {
+            let end = self.buffer.line_len(y - 1);
+            self.edit(Operation::Remove { position: (end, y - 1), text: "\n".to_string() }, (end, y - 1), true);
+        }
+    }
 
-        self.highlighted_lines[y as usize] = self.syntax_highlighter.highlight(line.to_string());
-        self.highlighted_lines.insert(y as usize + 1, self.syntax_highlighter.highlight(new_line.to_string()));
+    pub fn enter(&mut self) {
+        if self.hex.is_some() {
+            return;
+        }
+        self.at_every_cursor(|file| file.enter_at_cursor());
+    }
 
-        self.lines.insert(y as usize + 1, new_line);
+    fn enter_at_cursor(&mut self) {
+        let (x, y) = self.cursor_index();
+        let (position, mut text) = self.padding(x, y, false);
+        text.push_str(self.line_ending.as_str());
 
-        self.cursor.y += 1;
-        self.cursor.x = self.cursor.x_min;
-        
-        self.modified = true;
+        self.edit(Operation::Insert { position, text }, (0, y + 1), true);
     }
 
-    pub fn render(&mut self) -> Vec<ColourString> {
-        let (terminal_x, terminal_y) = terminal::size().unwrap();
-        let mut frame: Vec<ColourString> = vec![ColourString::new(String::from(" ".repeat(terminal_x as usize)), None); terminal_y as usize];
-        let default = String::new();
-        self.cursor.set_max(terminal_x, terminal_y-2);
+    pub fn render(&mut self, search: Option<&Regex>, theme: &Theme, (width, height): (u16, u16)) -> Vec<ColourString> {
+        let mut frame: Vec<ColourString> = vec![ColourString::new(" ".repeat(width as usize), None); height as usize];
+        self.cursor.set_max(width.saturating_sub(1), height.saturating_sub(1));
+
+        let gutter = if self.hex.is_some() { HEX_GUTTER } else { Self::gutter_width(self.buffer.len_lines()) };
+        if gutter != self.gutter {
+            self.cursor.x_min = (self.cursor.x_min + gutter).saturating_sub(self.gutter);
+            self.cursor.x = (self.cursor.x + gutter).saturating_sub(self.gutter);
+            self.gutter = gutter;
+        }
 
-        let mut line_number = 1 + self.cursor.y_offset;
-        for i in 0..terminal_y {
-            let mut line = self.highlighted_lines.get((i + self.cursor.y_offset)as usize).unwrap_or(&ColourString::new(default.clone(), None)).clone();
-            line = line.skip(self.cursor.x_offset as usize);
+        let selection = self.selection.map(|selection| (selection.mode, selection.spans(self))).unwrap_or((VisualMode::Char, vec![]));
+        let mut highlights = vec![];
+        for i in 0..height {
+            let y = i as usize + self.cursor.y_offset;
+            if let Some(hex) = self.hex.as_ref().filter(|hex| y < hex.len_rows()) {
+                let (mut colour_line, line) = hex.render_row(y, theme);
+                colour_line.push_colour_string(line.clone().skip(self.cursor.x_offset));
+                colour_line.truncate(width as usize);
+                frame[i as usize].replace_range(0, width as usize, colour_line);
+                continue;
+            }
+
+            let mut line = if y < self.buffer.len_lines() { self.highlighted_line(y, theme) } else { ColourString::new(String::new(), None) };
+            if let Some(regex) = search.filter(|_| y < self.buffer.len_lines()) {
+                for (start, end) in Search::line_matches(regex, &self.buffer.line(y)) {
+                    let start = self.buffer.display_column((start, y), self.tab_width);
+                    let end = self.buffer.display_column((end, y), self.tab_width);
+                    highlights.push((i as usize, start, end, theme.search));
+                }
+            }
+            if let Some(&(_, start, end)) = selection.1.iter().find(|&&(row, _, _)| row == y) {
+                let start_column = self.buffer.display_column((start, y), self.tab_width);
+                let end_column = self.buffer.display_column((end, y), self.tab_width);
+                // empty lines still show that they are selected, except in a block
+                let end_column = if end_column == start_column && selection.0 != VisualMode::Block { end_column + 1 } else { end_column };
+                highlights.push((i as usize, start_column, end_column, theme.selection));
+            }
+            for &(x, _) in self.cursors.iter().filter(|&&(_, row)| row == y) {
+                let column = self.buffer.display_column((x, y), self.tab_width);
+                highlights.push((i as usize, column, column + 1, theme.cursor));
+            }
+            line = line.skip(self.cursor.x_offset);
             line.insert(0, " ".to_string(), None);
 
-            let mut colour_line = ColourString::new(if line_number > self.lines.len() as u16 { "~".to_string() } else { line_number.to_string() }, Some(Info::new(Color::Rgb {
-                r: 248, 
-                g: 248, 
-                b: 242
-            }, Color::Reset, vec![])));
-            colour_line.insert(0, " ".repeat(if line_number > self.lines.len() as u16 { 3 } else { 4-line_number.to_string().len() }), None);
+            let line_number = if y < self.buffer.len_lines() && self.hex.is_none() { (y + 1).to_string() } else { "~".to_string() };
+            let mut colour_line = ColourString::new(format!("{:>width$}", line_number, width = (self.gutter - 1) as usize), Some(Info::new(theme.gutter, Color::Reset, vec![])));
             
             colour_line.push_colour_string(line);
-            colour_line.truncate(terminal_x as usize);
+            colour_line.truncate(width as usize);
 
-            frame[i as usize].replace_range(0, terminal_x as usize, colour_line);
-            line_number += 1;
+            frame[i as usize].replace_range(0, width as usize, colour_line);
         }
         
         for f in frame.iter_mut() {
-            f.pad(terminal_x as usize, " ".to_string(), None);
+            f.pad(width as usize, " ".to_string(), None);
             
-            f.replace(" ".to_string(), "█".to_string(), Some(Info::new(Color::Rgb {
-                r: 40,
-                g: 42,
-                b: 54,
-            }, Color::Reset, vec![])));
-
-            f.set_background(Color::Rgb {
-                r: 40,
-                g: 42,
-                b: 54,
-            })
+            // a block in the background colour, unless the theme keeps the terminal's own background
+            if theme.background != Color::Reset {
+                f.replace(" ".to_string(), "█".to_string(), Some(Info::new(theme.background, Color::Reset, vec![])));
+            }
+            f.set_background(theme.background)
+        }
+
+        let gutter = self.gutter as usize;
+        for (i, start, end, colour) in highlights {
+            let start = (start + gutter).saturating_sub(self.cursor.x_offset).max(gutter);
+            let end = (end + gutter).saturating_sub(self.cursor.x_offset);
+            frame[i].highlight(start, end, colour);
         }
         frame
     }
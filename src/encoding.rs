@@ -0,0 +1,154 @@
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FileEncoding {
+    pub encoding: &'static Encoding,
+    pub bom: bool,
+}
+
+impl FileEncoding {
+    pub fn new(encoding: &'static Encoding, bom: bool) -> Self {
+        Self {
+            encoding,
+            bom,
+        }
+    }
+
+    // accepts any WHATWG label, e.g. utf-8, utf-16le, latin1, shift_jis
+    pub fn from_label(label: &str) -> Option<Self> {
+        let encoding = Encoding::for_label(label.as_bytes())?;
+        Some(Self::new(encoding, encoding == UTF_16LE || encoding == UTF_16BE))
+    }
+
+    pub fn is_utf_16(&self) -> bool {
+        self.encoding == UTF_16LE || self.encoding == UTF_16BE
+    }
+
+    pub fn detect(bytes: &[u8]) -> Self {
+        if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+            return Self::new(encoding, true);
+        }
+
+        if let Some(encoding) = utf_16_heuristic(bytes) {
+            return Self::new(encoding, false);
+        }
+
+        if std::str::from_utf8(bytes).is_ok() {
+            Self::new(UTF_8, false)
+        }
+        else {
+            Self::new(WINDOWS_1252, false)
+        }
+    }
+
+    pub fn name(&self) -> String {
+        format!("{}{}", self.encoding.name().to_lowercase(), if self.bom { " bom" } else { "" })
+    }
+
+    pub fn decode(&self, bytes: &[u8]) -> String {
+        let bytes = match Encoding::for_bom(bytes) {
+            Some((encoding, len)) if self.bom && encoding == self.encoding => &bytes[len..],
+            _ => bytes,
+        };
+        self.encoding.decode_without_bom_handling(bytes).0.into_owned()
+    }
+
+    // encoding_rs only decodes utf-16, so it is encoded by hand
+    pub fn encode(&self, text: &str) -> Result<Vec<u8>, String> {
+        let mut bytes = Vec::new();
+        if self.is_utf_16() {
+            let big_endian = self.encoding == UTF_16BE;
+            for unit in std::iter::once(0xFEFF).filter(|_| self.bom).chain(text.encode_utf16()) {
+                bytes.extend(if big_endian { unit.to_be_bytes() } else { unit.to_le_bytes() });
+            }
+            return Ok(bytes);
+        }
+
+        if self.bom && self.encoding == UTF_8 {
+            bytes.extend([0xEF, 0xBB, 0xBF]);
+        }
+
+        let (encoded, _, unmappable) = self.encoding.encode(text);
+        if unmappable {
+            return Err(format!("file contains characters that can't be written as {}", self.encoding.name().to_lowercase()));
+        }
+        bytes.extend_from_slice(&encoded);
+        Ok(bytes)
+    }
+}
+
+// mostly-ascii utf-16 without a bom has a zero byte in every other position
+fn utf_16_heuristic(bytes: &[u8]) -> Option<&'static Encoding> {
+    let sample = &bytes[..bytes.len().min(4096) & !1];
+    let pairs = sample.len() / 2;
+    if pairs < 2 {
+        return None;
+    }
+
+    let even_zeros = sample.iter().step_by(2).filter(|&&byte| byte == 0).count();
+    let odd_zeros = sample.iter().skip(1).step_by(2).filter(|&&byte| byte == 0).count();
+
+    if odd_zeros * 10 > pairs * 3 && even_zeros * 20 < pairs {
+        Some(UTF_16LE)
+    }
+    else if even_zeros * 10 > pairs * 3 && odd_zeros * 20 < pairs {
+        Some(UTF_16BE)
+    }
+    else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(bytes: &[u8]) -> (FileEncoding, String) {
+        let encoding = FileEncoding::detect(bytes);
+        let text = encoding.decode(bytes);
+        assert_eq!(encoding.encode(&text).unwrap(), bytes, "{} didn't round trip", encoding.name());
+        (encoding, text)
+    }
+
+    fn utf_16(text: &str, big_endian: bool, bom: bool) -> Vec<u8> {
+        let units = std::iter::once(0xFEFF).filter(|_| bom).chain(text.encode_utf16());
+        units.flat_map(|unit| if big_endian { unit.to_be_bytes() } else { unit.to_le_bytes() }).collect()
+    }
+
+    #[test]
+    fn utf_8() {
+        assert_eq!(round_trip("héllo\n".as_bytes()), (FileEncoding::new(UTF_8, false), String::from("héllo\n")));
+        assert_eq!(round_trip(b"\xEF\xBB\xBFbom\n"), (FileEncoding::new(UTF_8, true), String::from("bom\n")));
+    }
+
+    #[test]
+    fn utf_16_with_a_bom() {
+        let (encoding, text) = round_trip(&utf_16("wide 字\n", false, true));
+        assert_eq!((encoding, text.as_str()), (FileEncoding::new(UTF_16LE, true), "wide 字\n"));
+        let (encoding, text) = round_trip(&utf_16("wide 字\n", true, true));
+        assert_eq!((encoding, text.as_str()), (FileEncoding::new(UTF_16BE, true), "wide 字\n"));
+    }
+
+    #[test]
+    fn utf_16_without_a_bom_is_guessed() {
+        let (encoding, text) = round_trip(&utf_16("mostly ascii\n", false, false));
+        assert_eq!((encoding, text.as_str()), (FileEncoding::new(UTF_16LE, false), "mostly ascii\n"));
+        let (encoding, _) = round_trip(&utf_16("mostly ascii\n", true, false));
+        assert_eq!(encoding, FileEncoding::new(UTF_16BE, false));
+        // too short to tell
+        assert_eq!(FileEncoding::detect(b"a\0").encoding, UTF_8);
+    }
+
+    #[test]
+    fn invalid_utf_8_falls_back_to_windows_1252() {
+        let (encoding, text) = round_trip(b"caf\xE9 \x80\n");
+        assert_eq!((encoding, text.as_str()), (FileEncoding::new(WINDOWS_1252, false), "café €\n"));
+    }
+
+    #[test]
+    fn unmappable_characters_are_an_error() {
+        assert!(FileEncoding::new(WINDOWS_1252, false).encode("字").is_err());
+        assert_eq!(FileEncoding::from_label("latin1").unwrap().encoding, WINDOWS_1252);
+        assert_eq!(FileEncoding::from_label("utf-16le").unwrap(), FileEncoding::new(UTF_16LE, true));
+    }
+}
@@ -1,7 +1,6 @@
 use std::{path::Path, fs::{DirEntry, self}};
-use crossterm::{terminal, style::Color};
-use crate::{colour_string::{ColourString, Info}, cursor::Cursor, editor::File};
-use unicode_segmentation::UnicodeSegmentation;
+use crossterm::style::Color;
+use crate::{colour_string::{ColourString, Info}, cursor::Cursor, editor::File, theme::Theme};
 
 #[derive(Clone, Debug)]
 pub struct Content {
@@ -60,8 +59,7 @@ impl FileExplorer {
             open_dirs: vec![],
             contents: vec![],
         };
-        new.cursor.set_min(0, 1);
-        new.cursor.y = 1;
+        new.cursor.set_min(0, 0);
         new
     }
 
@@ -81,19 +79,19 @@ impl FileExplorer {
         names.push(Name::new(name, file.path == child.path));
 
         if self.open_dirs.contains(&child.path) {
-            fs::read_dir(&child.path).unwrap().for_each(|child| {
-                names.extend(self.load_child(child.unwrap(), file, i + 1));
-            })
+            for child in sorted(&child.path) {
+                names.extend(self.load_child(child, file, i + 1));
+            }
         }
         names
     }
 
     pub fn parse_input(&mut self) -> Option<String> {
         let mut new = self.contents.clone();
-        let y = self.cursor.y as usize + self.cursor.y_offset as usize + self.cursor.y_min as usize;
+        let y = self.cursor.y as usize + self.cursor.y_offset;
 
         new.retain(|content| content.y == y);
-        if new.len() > 0 {
+        if !new.is_empty() {
             let new = new[0].clone();
             if new.is_dir {
                 if self.open_dirs.contains(&new.path) {
@@ -110,38 +108,36 @@ impl FileExplorer {
         None
     }
 
-    pub fn render(&mut self, file: &File) -> Vec<ColourString> {
-        let (terminal_x, terminal_y) = terminal::size().unwrap();
-        let max_x = terminal_x / 5;
-        let mut frame: Vec<ColourString> = vec![ColourString::new(String::from(format!("{}▕", " ".repeat(max_x as usize))), None); terminal_y as usize];
+    // names fill all but the last column, which is the divider
+    pub fn render(&mut self, file: &File, theme: &Theme, (width, height): (u16, u16)) -> Vec<ColourString> {
+        let max_x = width.saturating_sub(1);
+        let divider = Some(Info::new(theme.border, Color::Reset, vec![]));
+        let mut frame: Vec<ColourString> = vec![ColourString::new(format!("{}▕", " ".repeat(max_x as usize)), divider); height as usize];
         let mut files = vec![];
         self.contents.clear();
 
-        for child in Path::new(&file.path).parent().unwrap().read_dir().unwrap() {
-            if let Ok(child) = child {
-                files.extend(self.load_child(child, file, 0));
-            }
-        } // change
-
-        let mut y = self.cursor.y_offset as usize + self.cursor.y_min as usize + 1;
-        for content in &mut self.contents {
-            content.y = y;
-            y += 1;
+        for child in sorted(Path::new(&file.path).parent().unwrap()) {
+            files.extend(self.load_child(child, file, 0));
         }
 
-        for (i, file) in files.iter_mut().enumerate() {
-            if i > frame.len() - 1 {
-                frame.push(ColourString::new(String::from(format!("{}▕", " ".repeat(max_x as usize))), None));
-            }
+        for (i, content) in self.contents.iter_mut().enumerate() {
+            content.y = i;
+        }
 
-            let mut name = file.name.graphemes(true).skip(self.cursor.x_offset as usize).collect::<Vec<&str>>();
+        for (i, file) in files.iter().skip(self.cursor.y_offset).take(height as usize).enumerate() {
+            let mut name = ColourString::new(file.name.clone(), Some(Info::new(if file.current_file { theme.dimmed } else { theme.text }, Color::Reset, vec![]))).skip(self.cursor.x_offset);
             name.truncate(max_x as usize);
-            let name = name.join("");
-            frame[i].replace_range(0, name.graphemes(true).count(), ColourString::new(name.clone(), if file.current_file { Some(Info::new(Color::DarkGrey, Color::Reset, vec![])) } else { None }));
+            frame[i].replace_range(0, name.get_content().len(), name);
         }
 
-        self.cursor.set_max(max_x, terminal_y-2);
-        frame[self.cursor.y_offset as usize..].to_vec()
+        self.cursor.set_max(max_x.saturating_sub(1), height.saturating_sub(1));
+        frame
     }
 }
 
+// a directory's entries by name, read_dir gives them in whatever order the file system keeps them
+fn sorted(directory: impl AsRef<Path>) -> Vec<DirEntry> {
+    let mut children: Vec<DirEntry> = fs::read_dir(directory).into_iter().flatten().flatten().collect();
+    children.sort_by_key(|child| child.file_name());
+    children
+}
@@ -0,0 +1,183 @@
+use crossterm::style::{Attribute, Color};
+
+use crate::colour_string::{ColourString, Info};
+use crate::theme::Theme;
+
+pub const BYTES_PER_ROW: usize = 16;
+
+pub struct HexView {
+    pub bytes: Vec<u8>,
+    pub offset: usize,
+    nibble: bool,
+}
+
+impl HexView {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self {
+            bytes,
+            offset: 0,
+            nibble: false,
+        }
+    }
+
+    // nul bytes or lots of control characters near the start of the file
+    pub fn is_binary(bytes: &[u8]) -> bool {
+        let sample = &bytes[..bytes.len().min(8192)];
+        let control = sample.iter().filter(|&&byte| (byte < 0x20 && !b"\t\n\r\x0c\x1b".contains(&byte)) || byte == 0x7f).count();
+        sample.contains(&0) || control * 10 > sample.len()
+    }
+
+    pub fn len_rows(&self) -> usize {
+        self.bytes.len().div_ceil(BYTES_PER_ROW).max(1)
+    }
+
+    // display column of the first hex digit of byte x in a row, with a gap after the eighth byte
+    pub fn column(x: usize) -> usize {
+        x * 3 + x / 8
+    }
+
+    pub fn byte_column(column: usize) -> usize {
+        (0..BYTES_PER_ROW).rev().find(|&x| Self::column(x) <= column).unwrap_or(0)
+    }
+
+    pub fn cursor_position(&self) -> (usize, usize) {
+        (Self::column(self.offset % BYTES_PER_ROW) + self.nibble as usize, self.offset / BYTES_PER_ROW)
+    }
+
+    pub fn move_to(&mut self, offset: usize) {
+        self.offset = offset.min(self.bytes.len().saturating_sub(1));
+        self.nibble = false;
+    }
+
+    // typing two hex digits replaces the byte under the cursor, returns whether anything changed
+    pub fn overwrite(&mut self, c: char) -> bool {
+        let digit = match c.to_digit(16) {
+            Some(digit) if self.offset < self.bytes.len() => digit as u8,
+            _ => return false,
+        };
+
+        let byte = &mut self.bytes[self.offset];
+        if self.nibble {
+            *byte = (*byte & 0xf0) | digit;
+            self.move_to(self.offset + 1);
+        }
+        else {
+            *byte = (digit << 4) | (*byte & 0x0f);
+            self.nibble = true;
+        }
+        true
+    }
+
+    fn colour(byte: u8, theme: &Theme) -> Info {
+        let foreground = match byte {
+            0 => theme.hex_null,
+            b'\t' | b'\n' | b'\r' | b' ' => theme.hex_whitespace,
+            0x21..=0x7e => theme.hex_printable,
+            _ => theme.hex_other,
+        };
+        Info::new(foreground, Color::Reset, vec![])
+    }
+
+    // offset, hex bytes and an ascii column, e.g. `00000010  48 65 6c 6c ...  |Hell...|`
+    pub fn render_row(&self, y: usize, theme: &Theme) -> (ColourString, ColourString) {
+        let start = y * BYTES_PER_ROW;
+        let row = &self.bytes[start.min(self.bytes.len())..(start + BYTES_PER_ROW).min(self.bytes.len())];
+
+        let offset = ColourString::new(format!("{:08x}  ", start), Some(Info::new(theme.gutter, Color::Reset, vec![])));
+        let mut hex = ColourString::new(String::new(), None);
+        let mut ascii = ColourString::new(String::from("|"), None);
+
+        for x in 0..BYTES_PER_ROW {
+            let text = match row.get(x) {
+                Some(byte) => format!("{:02x} ", byte),
+                None => String::from("   "),
+            };
+            hex.push_str(&text, row.get(x).map(|&byte| Self::colour(byte, theme)));
+            if x == 7 {
+                hex.push_str(" ", None);
+            }
+        }
+
+        for (x, &byte) in row.iter().enumerate() {
+            let mut colour = Self::colour(byte, theme);
+            if start + x == self.offset {
+                colour.attributes.push(Attribute::Reverse);
+            }
+            ascii.push_str(&String::from(if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' }), Some(colour));
+        }
+        ascii.push_str("|", None);
+
+        hex.push_str(" ", None);
+        hex.push_colour_string(ascii);
+        (offset, hex)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(string: &ColourString) -> String {
+        string.get_content().iter().map(|c| c.content.as_str()).collect()
+    }
+
+    #[test]
+    fn binary_files() {
+        assert!(HexView::is_binary(b"PK\x03\x04\x00\x00"));
+        assert!(HexView::is_binary(b"\x01\x02\x03abcdefg"));
+        assert!(!HexView::is_binary(b"fn main() {\r\n\tprint!(\"\x1b[1m\x0c\");\n}\n"));
+        assert!(!HexView::is_binary("grüße 😀".as_bytes()));
+        assert!(!HexView::is_binary(b""));
+    }
+
+    #[test]
+    fn two_digits_overwrite_a_byte() {
+        let mut hex = HexView::new(vec![0x00, 0xff]);
+        assert!(hex.overwrite('4'));
+        assert_eq!((hex.bytes[0], hex.offset, hex.cursor_position()), (0x40, 0, (1, 0)));
+        assert!(!hex.overwrite('g'));
+        assert!(hex.overwrite('A'));
+        assert_eq!((hex.bytes[0], hex.offset), (0x4a, 1));
+
+        // the last byte keeps the cursor
+        assert!(hex.overwrite('1'));
+        assert!(hex.overwrite('2'));
+        assert_eq!((hex.bytes.clone(), hex.offset), (vec![0x4a, 0x12], 1));
+        assert!(!HexView::new(vec![]).overwrite('1'));
+    }
+
+    #[test]
+    fn columns_skip_the_gap_after_the_eighth_byte() {
+        assert_eq!(HexView::column(7), 21);
+        assert_eq!(HexView::column(8), 25);
+        assert_eq!(HexView::byte_column(22), 7);
+        // the gap and the space after a byte belong to the byte before them
+        assert_eq!(HexView::byte_column(24), 7);
+        assert_eq!(HexView::byte_column(25), 8);
+        assert_eq!(HexView::byte_column(200), 15);
+    }
+
+    #[test]
+    fn rows_show_non_printable_bytes_as_dots() {
+        let theme = Theme::default();
+        let mut bytes = vec![b'x'; 16];
+        bytes.extend([0x00, b'A', b'\n', 0xff]);
+        let mut hex = HexView::new(bytes);
+        hex.move_to(17);
+        assert_eq!(hex.len_rows(), 2);
+
+        let (offset, row) = hex.render_row(1, &theme);
+        assert_eq!(text(&offset), "00000010  ");
+        assert_eq!(text(&row), format!("00 41 0a ff {} {} |.A..|", "   ".repeat(4), "   ".repeat(8)));
+
+        let colour = |x: usize| row.get_content()[x].colour.clone();
+        assert_eq!(colour(0).foreground, theme.hex_null);
+        assert_eq!(colour(3).foreground, theme.hex_printable);
+        assert_eq!(colour(6).foreground, theme.hex_whitespace);
+        assert_eq!(colour(9).foreground, theme.hex_other);
+        // the byte under the cursor is reversed in the ascii column
+        let ascii = text(&row).find('|').unwrap();
+        assert_eq!(colour(ascii + 2).attributes, vec![Attribute::Reverse]);
+        assert!(colour(ascii + 1).attributes.is_empty());
+    }
+}
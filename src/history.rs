@@ -0,0 +1,280 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Operation {
+    Insert { position: (usize, usize), text: String },
+    Remove { position: (usize, usize), text: String },
+    // a byte of a binary file in the hex view
+    Overwrite { offset: usize, old: u8, new: u8 },
+}
+
+impl Operation {
+    pub fn inverse(&self) -> Operation {
+        match self {
+            Operation::Insert { position, text } => Operation::Remove { position: *position, text: text.clone() },
+            Operation::Remove { position, text } => Operation::Insert { position: *position, text: text.clone() },
+            Operation::Overwrite { offset, old, new } => Operation::Overwrite { offset: *offset, old: *new, new: *old },
+        }
+    }
+
+    // where a position after this operation ends up, positions inside removed text collapse onto its start
+    pub fn transform(&self, (x, y): (usize, usize)) -> (usize, usize) {
+        match self {
+            Operation::Insert { position, text } if (y, x) >= (position.1, position.0) => {
+                let end = end_position(*position, text);
+                if y == position.1 { (end.0 + x - position.0, end.1) } else { (x, y + end.1 - position.1) }
+            },
+            Operation::Remove { position, text } if (y, x) > (position.1, position.0) => {
+                let end = end_position(*position, text);
+                if (y, x) <= (end.1, end.0) {
+                    *position
+                }
+                else if y == end.1 {
+                    (position.0 + x - end.0, position.1)
+                }
+                else {
+                    (x, y - (end.1 - position.1))
+                }
+            },
+            _ => (x, y),
+        }
+    }
+}
+
+// position just after `text` once it has been inserted at `position`
+pub fn end_position(position: (usize, usize), text: &str) -> (usize, usize) {
+    let (x, y) = position;
+    match text.rsplit_once('\n') {
+        Some((_, last)) => (last.graphemes(true).count(), y + text.matches('\n').count()),
+        None => (x + text.graphemes(true).count(), y),
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Change {
+    pub operations: Vec<Operation>,
+    pub cursor_before: (usize, usize),
+    pub cursor_after: (usize, usize),
+    id: usize,
+}
+
+pub struct History {
+    undo_stack: Vec<Change>,
+    redo_stack: Vec<Change>,
+    open: bool,
+    grouping: bool,
+    saved: usize,
+    next_id: usize,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self {
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            open: false,
+            grouping: false,
+            saved: 0,
+            next_id: 1,
+        }
+    }
+
+    fn current(&self) -> usize {
+        self.undo_stack.last().map(|change| change.id).unwrap_or(0)
+    }
+
+    fn id(&mut self) -> usize {
+        self.next_id += 1;
+        self.next_id - 1
+    }
+
+    // typing edits, and every edit inside a group, are merged into the previous change until the history is sealed
+    pub fn record(&mut self, operation: Operation, cursor_before: (usize, usize), cursor_after: (usize, usize), typing: bool) {
+        self.redo_stack.clear();
+
+        if (typing || self.grouping) && self.open {
+            let id = self.id();
+            if let Some(change) = self.undo_stack.last_mut() {
+                change.operations.push(operation);
+                change.cursor_after = cursor_after;
+                change.id = id;
+                return;
+            }
+        }
+
+        let id = self.id();
+        self.undo_stack.push(Change {
+            operations: vec![operation],
+            cursor_before,
+            cursor_after,
+            id,
+        });
+        self.open = typing || self.grouping;
+    }
+
+    pub fn seal(&mut self) {
+        self.open = false;
+    }
+
+    pub fn begin_group(&mut self) {
+        self.seal();
+        self.grouping = true;
+    }
+
+    pub fn end_group(&mut self) {
+        self.grouping = false;
+        self.seal();
+    }
+
+    pub fn undo(&mut self) -> Option<Change> {
+        self.seal();
+        let change = self.undo_stack.pop()?;
+        self.redo_stack.push(change.clone());
+        Some(change)
+    }
+
+    pub fn redo(&mut self) -> Option<Change> {
+        self.seal();
+        let change = self.redo_stack.pop()?;
+        self.undo_stack.push(change.clone());
+        Some(change)
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.seal();
+        self.saved = self.current();
+    }
+
+    pub fn is_saved(&self) -> bool {
+        self.saved == self.current()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert(x: usize, y: usize, text: &str) -> Operation {
+        Operation::Insert { position: (x, y), text: text.to_string() }
+    }
+
+    fn remove(x: usize, y: usize, text: &str) -> Operation {
+        Operation::Remove { position: (x, y), text: text.to_string() }
+    }
+
+    #[test]
+    fn typing_is_one_change_until_sealed() {
+        let mut history = History::new();
+        history.record(insert(0, 0, "a"), (0, 0), (1, 0), true);
+        history.record(insert(1, 0, "b"), (1, 0), (2, 0), true);
+        history.record(insert(2, 0, "c"), (2, 0), (3, 0), true);
+        history.seal();
+        history.record(insert(3, 0, "d"), (3, 0), (4, 0), true);
+
+        let change = history.undo().unwrap();
+        assert_eq!(change.operations, vec![insert(3, 0, "d")]);
+        let change = history.undo().unwrap();
+        assert_eq!(change.operations.len(), 3);
+        assert_eq!((change.cursor_before, change.cursor_after), ((0, 0), (3, 0)));
+        assert!(history.undo().is_none());
+    }
+
+    #[test]
+    fn other_edits_are_not_merged() {
+        let mut history = History::new();
+        history.record(remove(0, 0, "a"), (0, 0), (0, 0), false);
+        history.record(remove(0, 0, "b"), (0, 0), (0, 0), false);
+        assert_eq!(history.undo().unwrap().operations, vec![remove(0, 0, "b")]);
+        assert_eq!(history.undo().unwrap().operations, vec![remove(0, 0, "a")]);
+    }
+
+    #[test]
+    fn a_group_is_one_change() {
+        let mut history = History::new();
+        history.record(insert(0, 0, "x"), (0, 0), (1, 0), true);
+        history.begin_group();
+        history.record(remove(0, 0, "x"), (1, 0), (0, 0), false);
+        history.record(insert(0, 0, "yz"), (0, 0), (2, 0), false);
+        history.end_group();
+        history.record(insert(2, 0, "!"), (2, 0), (3, 0), false);
+
+        assert_eq!(history.undo().unwrap().operations, vec![insert(2, 0, "!")]);
+        let change = history.undo().unwrap();
+        assert_eq!(change.operations, vec![remove(0, 0, "x"), insert(0, 0, "yz")]);
+        assert_eq!((change.cursor_before, change.cursor_after), ((1, 0), (2, 0)));
+        assert_eq!(history.undo().unwrap().operations, vec![insert(0, 0, "x")]);
+    }
+
+    #[test]
+    fn redo_is_cleared_by_a_new_edit() {
+        let mut history = History::new();
+        history.record(insert(0, 0, "a"), (0, 0), (1, 0), false);
+        history.undo();
+        history.record(insert(0, 0, "b"), (0, 0), (1, 0), false);
+        assert!(history.redo().is_none());
+    }
+
+    #[test]
+    fn saved_after_undo_then_redo() {
+        let mut history = History::new();
+        assert!(history.is_saved());
+        history.record(insert(0, 0, "a"), (0, 0), (1, 0), false);
+        assert!(!history.is_saved());
+        history.undo();
+        assert!(history.is_saved());
+        history.redo();
+        history.mark_saved();
+        assert!(history.is_saved());
+
+        history.undo();
+        assert!(!history.is_saved());
+        history.redo();
+        assert!(history.is_saved());
+    }
+
+    #[test]
+    fn a_save_ends_the_typing_change() {
+        let mut history = History::new();
+        history.record(insert(0, 0, "a"), (0, 0), (1, 0), true);
+        history.mark_saved();
+        history.record(insert(1, 0, "b"), (1, 0), (2, 0), true);
+        assert!(!history.is_saved());
+        history.undo();
+        assert!(history.is_saved());
+    }
+
+    #[test]
+    fn transform_through_an_insert() {
+        let operation = insert(2, 1, "ab\ncde");
+        // before it, or on an earlier line
+        assert_eq!(operation.transform((1, 1)), (1, 1));
+        assert_eq!(operation.transform((5, 0)), (5, 0));
+        // the rest of its line moves onto the last inserted line
+        assert_eq!(operation.transform((2, 1)), (3, 2));
+        assert_eq!(operation.transform((4, 1)), (5, 2));
+        // later lines move down
+        assert_eq!(operation.transform((4, 3)), (4, 4));
+
+        assert_eq!(insert(0, 0, "xy").transform((3, 0)), (5, 0));
+    }
+
+    #[test]
+    fn transform_through_a_remove() {
+        let operation = remove(2, 1, "ab\ncde");
+        assert_eq!(operation.transform((2, 1)), (2, 1));
+        // inside the removed text
+        assert_eq!(operation.transform((5, 1)), (2, 1));
+        assert_eq!(operation.transform((3, 2)), (2, 1));
+        // after it on its last line, and on later lines
+        assert_eq!(operation.transform((5, 2)), (4, 1));
+        assert_eq!(operation.transform((1, 4)), (1, 3));
+    }
+
+    #[test]
+    fn inverse_swaps_insert_and_remove() {
+        assert_eq!(insert(1, 2, "é").inverse(), remove(1, 2, "é"));
+        assert_eq!(remove(1, 2, "é").inverse().inverse(), remove(1, 2, "é"));
+        assert_eq!(end_position((1, 2), "é👍"), (3, 2));
+        assert_eq!(end_position((1, 2), "a\n\nbc"), (2, 4));
+    }
+}
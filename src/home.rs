@@ -1,9 +1,6 @@
-use crossterm::{
-    terminal,
-    Result
-};
+use crossterm::style::Color;
 
-use crate::{colour_string::{ColourString}, cursor::Cursor};
+use crate::{colour_string::{ColourString, Info}, cursor::Cursor, theme::Theme};
 
 pub struct Home {
     pub cursor: Cursor,
@@ -16,23 +13,24 @@ impl Home {
         }
     }
 
-    pub fn render(&mut self) -> Result<Vec<ColourString>> {
-        let (terminal_x, terminal_y) = terminal::size()?;
+    pub fn render(&mut self, theme: &Theme, (width, height): (u16, u16)) -> Vec<ColourString> {
+        self.cursor.set_max(width.saturating_sub(1), height.saturating_sub(1));
         let mut output = Vec::new();
+        let colour = Some(Info::new(theme.text, Color::Reset, vec![]));
     
-        for i in 0..terminal_y {
-            let mut line = ColourString::new("~".to_string(), None);
+        for i in 0..height {
+            let mut line = ColourString::new("~".to_string(), colour.clone());
     
-            if i == terminal_y / 3 {
+            if i == height / 3 {
                 let mut welcome = format!("vcte (very cool text editor) v{}", env!("CARGO_PKG_VERSION"));
-                let padding = ((terminal_x - welcome.len() as u16) / 2) - 1;
+                let padding = ((width as usize).saturating_sub(welcome.len()) / 2).saturating_sub(1);
     
-                welcome.truncate(terminal_x as usize);
-                line.push_str(&format!("{}{}", &" ".repeat(padding as usize), welcome), None);
+                welcome.truncate((width as usize).saturating_sub(1));
+                line.push_str(&format!("{}{}", &" ".repeat(padding as usize), welcome), colour.clone());
             }
             output.push(line);
         };
-        Ok(output)
+        output
     }
 }
 
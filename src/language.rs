@@ -0,0 +1,273 @@
+use std::path::Path;
+
+// how a language is tokenised, markdown is prose and gets its own rules
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Grammar {
+    Code,
+    Markdown,
+}
+
+pub struct Language {
+    pub name: &'static str,
+    pub grammar: Grammar,
+    extensions: &'static [&'static str],
+    file_names: &'static [&'static str],
+    interpreters: &'static [&'static str],
+    pub keywords: &'static [&'static str],
+    pub types: &'static [&'static str],
+    pub constants: &'static [&'static str],
+    pub line_comments: &'static [&'static str],
+    pub block_comment: Option<(&'static str, &'static str)>,
+    pub quotes: &'static [char],
+    // quotes whose strings can run over several lines
+    pub multiline_quotes: &'static [char],
+    // three quotes open a string that can run over several lines, as in python
+    pub triple_quotes: bool,
+    // rust's r"..." and r#"..."# where backslashes are not escapes
+    pub raw_strings: bool,
+    pub nested_comments: bool,
+    // `<<EOF` starts a here document
+    pub heredocs: bool,
+    // identifiers and strings followed by `:` or `=` are keys, as in json and toml
+    pub keys: bool,
+    // capitalised identifiers are types
+    pub capitalised_types: bool,
+    // `$name` and `${name}` are variables
+    pub variables: bool,
+}
+
+const DEFAULT: Language = Language {
+    name: "",
+    grammar: Grammar::Code,
+    extensions: &[],
+    file_names: &[],
+    interpreters: &[],
+    keywords: &[],
+    types: &[],
+    constants: &[],
+    line_comments: &[],
+    block_comment: None,
+    quotes: &[],
+    multiline_quotes: &[],
+    triple_quotes: false,
+    raw_strings: false,
+    nested_comments: false,
+    heredocs: false,
+    keys: false,
+    capitalised_types: false,
+    variables: false,
+};
+
+const JS_KEYWORDS: &[&str] = &[
+    "async", "await", "break", "case", "catch", "class", "const", "continue", "debugger", "default", "delete", "do", "else", "export",
+    "extends", "finally", "for", "from", "function", "get", "if", "import", "in", "instanceof", "let", "new", "of", "return", "set",
+    "static", "super", "switch", "this", "throw", "try", "typeof", "var", "void", "while", "with", "yield",
+];
+
+pub const LANGUAGES: &[Language] = &[
+    Language {
+        name: "rust",
+        extensions: &["rs"],
+        keywords: &[
+            "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern", "fn", "for", "if", "impl", "in",
+            "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait",
+            "type", "unsafe", "use", "where", "while",
+        ],
+        types: &[
+            "bool", "char", "str", "u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16", "i32", "i64", "i128", "isize", "f32", "f64",
+        ],
+        constants: &["true", "false", "None", "Some", "Ok", "Err"],
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\''],
+        multiline_quotes: &['"'],
+        raw_strings: true,
+        nested_comments: true,
+        capitalised_types: true,
+        ..DEFAULT
+    },
+    Language {
+        name: "python",
+        extensions: &["py", "pyw", "pyi"],
+        interpreters: &["python"],
+        keywords: &[
+            "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del", "elif", "else", "except", "finally", "for",
+            "from", "global", "if", "import", "in", "is", "lambda", "match", "case", "nonlocal", "not", "or", "pass", "raise", "return",
+            "try", "while", "with", "yield", "self",
+        ],
+        types: &["int", "float", "str", "bytes", "bool", "list", "dict", "set", "tuple", "object", "complex"],
+        constants: &["True", "False", "None"],
+        line_comments: &["#"],
+        quotes: &['"', '\''],
+        triple_quotes: true,
+        capitalised_types: true,
+        ..DEFAULT
+    },
+    Language {
+        name: "javascript",
+        extensions: &["js", "mjs", "cjs", "jsx"],
+        interpreters: &["node", "deno", "bun"],
+        keywords: JS_KEYWORDS,
+        constants: &["true", "false", "null", "undefined", "NaN", "Infinity"],
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\'', '`'],
+        multiline_quotes: &['`'],
+        capitalised_types: true,
+        ..DEFAULT
+    },
+    Language {
+        name: "typescript",
+        extensions: &["ts", "tsx", "mts", "cts"],
+        interpreters: &["ts-node", "tsx"],
+        keywords: &[
+            "abstract", "as", "async", "await", "break", "case", "catch", "class", "const", "continue", "declare", "default", "delete", "do",
+            "else", "enum", "export", "extends", "finally", "for", "from", "function", "get", "if", "implements", "import", "in",
+            "instanceof", "interface", "keyof", "let", "namespace", "new", "of", "private", "protected", "public", "readonly", "return",
+            "satisfies", "set", "static", "super", "switch", "this", "throw", "try", "type", "typeof", "var", "void", "while", "yield",
+        ],
+        types: &["any", "boolean", "never", "number", "object", "string", "symbol", "unknown", "bigint"],
+        constants: &["true", "false", "null", "undefined", "NaN", "Infinity"],
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\'', '`'],
+        multiline_quotes: &['`'],
+        capitalised_types: true,
+        ..DEFAULT
+    },
+    Language {
+        name: "c",
+        extensions: &["c", "h"],
+        keywords: &[
+            "auto", "break", "case", "const", "continue", "default", "do", "else", "enum", "extern", "for", "goto", "if", "inline",
+            "register", "restrict", "return", "sizeof", "static", "struct", "switch", "typedef", "union", "volatile", "while",
+        ],
+        types: &[
+            "char", "double", "float", "int", "long", "short", "signed", "unsigned", "void", "bool", "size_t", "ssize_t", "int8_t",
+            "int16_t", "int32_t", "int64_t", "uint8_t", "uint16_t", "uint32_t", "uint64_t", "FILE",
+        ],
+        constants: &["NULL", "true", "false"],
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\''],
+        ..DEFAULT
+    },
+    Language {
+        name: "markdown",
+        grammar: Grammar::Markdown,
+        extensions: &["md", "markdown", "mdown", "mkd"],
+        ..DEFAULT
+    },
+    Language {
+        name: "toml",
+        extensions: &["toml"],
+        file_names: &["Cargo.lock", "Pipfile"],
+        constants: &["true", "false", "inf", "nan"],
+        line_comments: &["#"],
+        quotes: &['"', '\''],
+        triple_quotes: true,
+        keys: true,
+        ..DEFAULT
+    },
+    Language {
+        name: "json",
+        extensions: &["json", "jsonc", "json5"],
+        file_names: &[".babelrc", ".eslintrc"],
+        constants: &["true", "false", "null"],
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"'],
+        keys: true,
+        ..DEFAULT
+    },
+    Language {
+        name: "shell",
+        extensions: &["sh", "bash", "zsh", "ksh"],
+        file_names: &[".bashrc", ".bash_profile", ".bash_aliases", ".zshrc", ".zprofile", ".profile", "PKGBUILD"],
+        interpreters: &["sh", "bash", "zsh", "ksh", "dash"],
+        keywords: &[
+            "if", "then", "else", "elif", "fi", "for", "while", "until", "do", "done", "case", "esac", "in", "function", "select", "return",
+            "break", "continue", "local", "export", "readonly", "declare", "unset", "shift", "exit", "source", "alias",
+        ],
+        constants: &["true", "false"],
+        line_comments: &["#"],
+        quotes: &['"', '\''],
+        multiline_quotes: &['"', '\''],
+        heredocs: true,
+        variables: true,
+        ..DEFAULT
+    },
+];
+
+impl Language {
+    // by file name, then extension, then the interpreter named in a `#!` line
+    pub fn detect(path: &str, first_line: &str) -> Option<&'static Language> {
+        let path = Path::new(path);
+        let file_name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+        let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or_default().to_lowercase();
+
+        LANGUAGES.iter()
+            .find(|language| language.file_names.contains(&file_name))
+            .or_else(|| LANGUAGES.iter().find(|language| language.extensions.contains(&extension.as_str())))
+            .or_else(|| {
+                let interpreter = interpreter(first_line)?;
+                LANGUAGES.iter().find(|language| language.interpreters.iter().any(|name| interpreter == *name || interpreter.strip_prefix(name).is_some_and(|version| version.starts_with(|c: char| c.is_ascii_digit()))))
+            })
+    }
+}
+
+// `#!/usr/bin/env -S python3 -u` gives python3
+fn interpreter(first_line: &str) -> Option<&str> {
+    let mut words = first_line.strip_prefix("#!")?.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        program = words.find(|word| !word.starts_with('-') && !word.contains('='))?;
+    }
+    Some(program)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detect(path: &str, first_line: &str) -> Option<&'static str> {
+        Language::detect(path, first_line).map(|language| language.name)
+    }
+
+    #[test]
+    fn by_extension_or_file_name() {
+        assert_eq!(detect("src/main.rs", ""), Some("rust"));
+        assert_eq!(detect("tool.PY", ""), Some("python"));
+        assert_eq!(detect("index.mjs", ""), Some("javascript"));
+        assert_eq!(detect("app.tsx", ""), Some("typescript"));
+        assert_eq!(detect("stdio.h", ""), Some("c"));
+        assert_eq!(detect("README.md", ""), Some("markdown"));
+        assert_eq!(detect("Cargo.toml", ""), Some("toml"));
+        assert_eq!(detect("Cargo.lock", ""), Some("toml"));
+        assert_eq!(detect("package.json", ""), Some("json"));
+        assert_eq!(detect("/home/me/.bashrc", ""), Some("shell"));
+        assert_eq!(detect("notes.txt", ""), None);
+    }
+
+    #[test]
+    fn by_the_interpreter_in_a_shebang() {
+        assert_eq!(detect("script", "#!/usr/bin/env python3"), Some("python"));
+        assert_eq!(detect("script", "#!/usr/bin/env -S python3 -u"), Some("python"));
+        assert_eq!(detect("script", "#!/bin/sh"), Some("shell"));
+        assert_eq!(detect("script", "#!/usr/bin/env bash"), Some("shell"));
+        assert_eq!(detect("script", "#!/usr/bin/env node"), Some("javascript"));
+        // only a version can follow the name
+        assert_eq!(detect("script", "#!/usr/bin/pythonista"), None);
+        assert_eq!(detect("script", "# not a shebang"), None);
+        // the extension comes first
+        assert_eq!(detect("build.py", "#!/bin/sh"), Some("python"));
+    }
+
+    #[test]
+    fn interpreter_skips_env_and_its_options() {
+        assert_eq!(interpreter("#!/bin/sh -e"), Some("sh"));
+        assert_eq!(interpreter("#!/usr/bin/env -S LANG=C python3 -u"), Some("python3"));
+        assert_eq!(interpreter("#!/usr/bin/env"), None);
+        assert_eq!(interpreter("print('hi')"), None);
+    }
+}
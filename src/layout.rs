@@ -0,0 +1,199 @@
+// a part of the screen, components draw into one and keep their cursors relative to its top left corner
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Rect {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl Rect {
+    pub fn new(x: u16, y: u16, width: u16, height: u16) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn contains(&self, x: u16, y: u16) -> bool {
+        x >= self.x && y >= self.y && x - self.x < self.width && y - self.y < self.height
+    }
+
+    // screen coordinates inside the rect as coordinates relative to it
+    pub fn to_local(self, x: u16, y: u16) -> Option<(u16, u16)> {
+        self.contains(x, y).then(|| (x - self.x, y - self.y))
+    }
+
+    pub fn to_screen(self, x: u16, y: u16) -> (u16, u16) {
+        (self.x.saturating_add(x), self.y.saturating_add(y))
+    }
+}
+
+// how much of its parent a part of the layout takes, along the direction the parent splits in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Size {
+    Fixed(u16),
+    Percent(u16),
+    // an equal share of whatever the fixed and percentage sizes leave
+    Fill,
+}
+
+// what is drawn in a leaf of the layout
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Area {
+    Tabs,
+    Explorer,
+    // where the panes go, before they are split up
+    Panes,
+    Pane(usize),
+    // the line between two panes, `vertical` when they are side by side
+    Divider { vertical: bool },
+    Home,
+    StatusBar,
+}
+
+pub enum Layout {
+    Area(Area),
+    // children side by side, left to right
+    Row(Vec<(Size, Layout)>),
+    // children stacked, top to bottom
+    Column(Vec<(Size, Layout)>),
+}
+
+impl Layout {
+    // the rect of every area when the layout fills `rect`
+    pub fn resolve(&self, rect: Rect) -> Vec<(Area, Rect)> {
+        let mut areas = vec![];
+        self.resolve_into(rect, &mut areas);
+        areas
+    }
+
+    fn resolve_into(&self, rect: Rect, areas: &mut Vec<(Area, Rect)>) {
+        match self {
+            Layout::Area(area) => areas.push((*area, rect)),
+            Layout::Row(children) => {
+                let mut x = rect.x;
+                for ((_, child), width) in children.iter().zip(sizes(rect.width, children)) {
+                    child.resolve_into(Rect::new(x, rect.y, width, rect.height), areas);
+                    x += width;
+                }
+            },
+            Layout::Column(children) => {
+                let mut y = rect.y;
+                for ((_, child), height) in children.iter().zip(sizes(rect.height, children)) {
+                    child.resolve_into(Rect::new(rect.x, y, rect.width, height), areas);
+                    y += height;
+                }
+            },
+        }
+    }
+}
+
+// fixed and percentage sizes are handed out first, in order, and never more than what is left
+pub fn sizes<T>(total: u16, children: &[(Size, T)]) -> Vec<u16> {
+    let mut left = total;
+    let mut sizes: Vec<u16> = children.iter().map(|(size, _)| {
+        let wanted = match size {
+            Size::Fixed(size) => *size,
+            Size::Percent(percent) => (total as u32 * (*percent).min(100) as u32 / 100) as u16,
+            Size::Fill => 0,
+        };
+        let size = wanted.min(left);
+        left -= size;
+        size
+    }).collect();
+
+    let fills = children.iter().filter(|(size, _)| *size == Size::Fill).count() as u16;
+    let share = left.checked_div(fills).unwrap_or(0);
+    // the last one also gets whatever doesn't divide evenly
+    let mut remainder = left - share * fills;
+    for (size, (kind, _)) in sizes.iter_mut().zip(children).rev() {
+        if *kind == Size::Fill {
+            *size = share + remainder;
+            remainder = 0;
+        }
+    }
+    sizes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sizes_of(total: u16, kinds: &[Size]) -> Vec<u16> {
+        sizes(total, &kinds.iter().map(|kind| (*kind, ())).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn fixed_and_percentage_sizes() {
+        assert_eq!(sizes_of(10, &[Size::Fixed(3), Size::Fill, Size::Fixed(2)]), [3, 5, 2]);
+        assert_eq!(sizes_of(50, &[Size::Percent(20), Size::Fill]), [10, 40]);
+        // rounded down, and never more than everything
+        assert_eq!(sizes_of(9, &[Size::Percent(50), Size::Fill]), [4, 5]);
+        assert_eq!(sizes_of(20, &[Size::Percent(150), Size::Fill]), [20, 0]);
+        // without a fill what is left over stays empty
+        assert_eq!(sizes_of(10, &[Size::Fixed(3), Size::Percent(10)]), [3, 1]);
+    }
+
+    #[test]
+    fn the_last_fill_gets_the_remainder() {
+        assert_eq!(sizes_of(10, &[Size::Fill, Size::Fill, Size::Fill]), [3, 3, 4]);
+        assert_eq!(sizes_of(12, &[Size::Fill, Size::Fixed(1), Size::Fill, Size::Fill, Size::Fixed(1)]), [3, 1, 3, 4, 1]);
+        assert_eq!(sizes_of(2, &[Size::Fill, Size::Fill, Size::Fill]), [0, 0, 2]);
+    }
+
+    #[test]
+    fn too_little_room_goes_to_the_first_children() {
+        assert_eq!(sizes_of(5, &[Size::Fixed(3), Size::Fixed(4), Size::Fill]), [3, 2, 0]);
+        assert_eq!(sizes_of(5, &[Size::Fill, Size::Percent(80), Size::Fixed(4)]), [0, 4, 1]);
+        assert_eq!(sizes_of(0, &[Size::Fixed(3), Size::Percent(50), Size::Fill]), [0, 0, 0]);
+        assert_eq!(sizes_of(u16::MAX, &[Size::Fixed(u16::MAX), Size::Fixed(u16::MAX), Size::Percent(100)]), [u16::MAX, 0, 0]);
+        assert_eq!(sizes_of(7, &[]), Vec::<u16>::new());
+    }
+
+    fn editor() -> Layout {
+        Layout::Column(vec![
+            (Size::Fixed(1), Layout::Area(Area::Tabs)),
+            (Size::Fill, Layout::Row(vec![
+                (Size::Fixed(4), Layout::Area(Area::Explorer)),
+                (Size::Fill, Layout::Area(Area::Panes)),
+            ])),
+            (Size::Fixed(1), Layout::Area(Area::StatusBar)),
+        ])
+    }
+
+    #[test]
+    fn resolve_nests_rows_and_columns() {
+        assert_eq!(editor().resolve(Rect::new(2, 3, 20, 10)), [
+            (Area::Tabs, Rect::new(2, 3, 20, 1)),
+            (Area::Explorer, Rect::new(2, 4, 4, 8)),
+            (Area::Panes, Rect::new(6, 4, 16, 8)),
+            (Area::StatusBar, Rect::new(2, 12, 20, 1)),
+        ]);
+    }
+
+    #[test]
+    fn resolve_into_a_tiny_rect_leaves_areas_empty() {
+        assert_eq!(editor().resolve(Rect::new(0, 0, 3, 1)), [
+            (Area::Tabs, Rect::new(0, 0, 3, 1)),
+            (Area::Explorer, Rect::new(0, 1, 3, 0)),
+            (Area::Panes, Rect::new(3, 1, 0, 0)),
+            (Area::StatusBar, Rect::new(0, 1, 3, 0)),
+        ]);
+        assert!(editor().resolve(Rect::default()).iter().all(|(_, rect)| rect.width == 0 || rect.height == 0));
+    }
+
+    #[test]
+    fn rect_coordinates() {
+        let rect = Rect::new(2, 3, 4, 5);
+        assert!(rect.contains(2, 3) && rect.contains(5, 7));
+        assert!(!rect.contains(6, 3) && !rect.contains(2, 8) && !rect.contains(1, 3) && !rect.contains(2, 2));
+        assert!(!Rect::new(2, 3, 0, 0).contains(2, 3));
+        assert_eq!(rect.to_local(3, 7), Some((1, 4)));
+        assert_eq!(rect.to_local(6, 7), None);
+        assert_eq!(rect.to_screen(1, 4), (3, 7));
+        assert_eq!(Rect::new(u16::MAX, 1, 1, 1).to_screen(5, 0), (u16::MAX, 1));
+    }
+}
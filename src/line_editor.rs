@@ -0,0 +1,110 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::buffer::text_width;
+
+// one line of text with a cursor in it, for the command palette and any other prompt
+pub struct LineEditor {
+    text: String,
+    // a byte index, always between graphemes
+    cursor: usize,
+}
+
+impl LineEditor {
+    pub fn new() -> Self {
+        Self {
+            text: String::new(),
+            cursor: 0,
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    // replaces the text, with the cursor after it
+    pub fn set(&mut self, text: &str) {
+        self.text = text.to_string();
+        self.cursor = self.text.len();
+    }
+
+    pub fn clear(&mut self) {
+        self.set("");
+    }
+
+    // columns before the cursor
+    pub fn cursor_width(&self) -> usize {
+        text_width(&self.text[..self.cursor])
+    }
+
+    // a single line, so anything after a line break goes on the same line
+    pub fn insert(&mut self, text: &str) {
+        let text = text.trim_end_matches('\n').replace('\n', " ");
+        self.text.insert_str(self.cursor, &text);
+        self.cursor += text.len();
+    }
+
+    // the keys every prompt shares, returning whether the key was one of them
+    pub fn handle(&mut self, key: KeyEvent) -> bool {
+        match (key.modifiers, key.code) {
+            (KeyModifiers::NONE | KeyModifiers::SHIFT, KeyCode::Char(c)) => self.insert(c.encode_utf8(&mut [0; 4])),
+            (KeyModifiers::NONE, KeyCode::Backspace) => self.delete(self.previous(self.cursor), self.cursor),
+            (KeyModifiers::NONE, KeyCode::Delete) => self.delete(self.cursor, self.next(self.cursor)),
+            (KeyModifiers::NONE, KeyCode::Left) => self.cursor = self.previous(self.cursor),
+            (KeyModifiers::NONE, KeyCode::Right) => self.cursor = self.next(self.cursor),
+            (KeyModifiers::NONE, KeyCode::Home) | (KeyModifiers::CONTROL, KeyCode::Char('a')) => self.cursor = 0,
+            (KeyModifiers::NONE, KeyCode::End) | (KeyModifiers::CONTROL, KeyCode::Char('e')) => self.cursor = self.text.len(),
+            (KeyModifiers::CONTROL, KeyCode::Left) | (KeyModifiers::ALT, KeyCode::Char('b')) => self.cursor = self.word_start(),
+            (KeyModifiers::CONTROL, KeyCode::Right) | (KeyModifiers::ALT, KeyCode::Char('f')) => self.cursor = self.word_end(),
+            (KeyModifiers::CONTROL, KeyCode::Char('w')) => self.delete(self.word_start(), self.cursor),
+            (KeyModifiers::CONTROL, KeyCode::Char('u')) => self.delete(0, self.cursor),
+            (KeyModifiers::CONTROL, KeyCode::Char('k')) => self.delete(self.cursor, self.text.len()),
+            _ => return false,
+        }
+        true
+    }
+
+    fn delete(&mut self, start: usize, end: usize) {
+        self.text.replace_range(start..end, "");
+        self.cursor = start;
+    }
+
+    fn previous(&self, index: usize) -> usize {
+        self.text[..index].grapheme_indices(true).next_back().map_or(0, |(i, _)| i)
+    }
+
+    fn next(&self, index: usize) -> usize {
+        self.text[index..].graphemes(true).next().map_or(index, |grapheme| index + grapheme.len())
+    }
+
+    // words are letters, digits and underscores, anything else between them is skipped over
+    fn word_start(&self) -> usize {
+        let mut index = self.cursor;
+        while index > 0 && !is_word(&self.text[self.previous(index)..index]) {
+            index = self.previous(index);
+        }
+        while index > 0 && is_word(&self.text[self.previous(index)..index]) {
+            index = self.previous(index);
+        }
+        index
+    }
+
+    fn word_end(&self) -> usize {
+        let mut index = self.cursor;
+        while index < self.text.len() && !is_word(&self.text[index..self.next(index)]) {
+            index = self.next(index);
+        }
+        while index < self.text.len() && is_word(&self.text[index..self.next(index)]) {
+            index = self.next(index);
+        }
+        index
+    }
+}
+
+fn is_word(grapheme: &str) -> bool {
+    grapheme.chars().next().is_some_and(|c| c.is_alphanumeric() || c == '_')
+}
@@ -1,4 +1,5 @@
 pub(crate) mod window;
+pub(crate) mod backend;
 pub(crate) mod colour_string;
 pub(crate) mod home;
 pub(crate) mod command_palette;
@@ -8,6 +9,24 @@ pub(crate) mod editor;
 pub(crate) mod status_bar;
 pub(crate) mod tab;
 pub(crate) mod syntax_highlighter;
+pub(crate) mod language;
+pub(crate) mod syntaxes;
+pub(crate) mod theme;
+pub(crate) mod screen;
+pub(crate) mod layout;
+pub(crate) mod pane;
+pub(crate) mod history;
+pub(crate) mod buffer;
+pub(crate) mod encoding;
+pub(crate) mod hex_view;
+pub(crate) mod search;
+pub(crate) mod selection;
+pub(crate) mod register;
+pub(crate) mod substitute;
+pub(crate) mod commands;
+pub(crate) mod completion;
+pub(crate) mod command_history;
+pub(crate) mod line_editor;
 
 use crossterm::{
     terminal::{ disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen}, 
@@ -16,8 +35,9 @@ use crossterm::{
 };
 
 use window::Window;
+use backend::{CrosstermBackend, TerminalEvents};
 
-use std::{io::{stdout}, fs::File};
+use std::{io::{stdout}, fs::File, env, path::PathBuf};
 
 #[macro_use] extern crate log;
 extern crate simplelog;
@@ -32,12 +52,34 @@ impl Drop for DisableRawMode {
     }
 }
 
+// $XDG_CONFIG_HOME/vcte/<name>, falling back to ~/.config/vcte/<name>
+pub fn config_path(name: &str) -> Option<PathBuf> {
+    Some(xdg_home("XDG_CONFIG_HOME", &[".config"])?.join("vcte").join(name))
+}
+
+// $XDG_STATE_HOME/vcte/<name>, falling back to ~/.local/state/vcte/<name>
+pub fn state_path(name: &str) -> Option<PathBuf> {
+    Some(xdg_home("XDG_STATE_HOME", &[".local", "state"])?.join("vcte").join(name))
+}
+
+#[cfg(not(test))]
+fn xdg_home(variable: &str, fallback: &[&str]) -> Option<PathBuf> {
+    env::var_os(variable).filter(|path| !path.is_empty()).map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| fallback.iter().fold(PathBuf::from(home), |path, part| path.join(part))))
+}
+
+// tests get an empty directory of their own, so the user's themes, grammars and history don't change what is drawn
+#[cfg(test)]
+fn xdg_home(variable: &str, _: &[&str]) -> Option<PathBuf> {
+    Some(env::temp_dir().join(format!("vcte-xdg-{}", std::process::id())).join(variable))
+}
+
 fn main() -> Result<()> {
     enable_raw_mode()?;
 
     let _disable_raw_mode = DisableRawMode;
     let _ = WriteLogger::init(LevelFilter::Debug, Config::default(), File::create("debug.log").unwrap());
-    let mut window = Window::new();
+    let mut window = Window::new(CrosstermBackend::new(), Box::new(TerminalEvents));
 
     execute!(stdout(), EnableMouseCapture, EnterAlternateScreen)?;
     if let Err(e) = window.render() {
@@ -0,0 +1,179 @@
+use crate::cursor::Cursor;
+use crate::layout::{self, Area, Layout, Rect, Size};
+
+// a view of one of the open files. the focused pane's view is the file's own cursor, so `view` is only kept for the others
+pub struct Pane {
+    pub file: usize,
+    pub view: Cursor,
+}
+
+impl Pane {
+    pub fn new(file: usize, view: Cursor) -> Self {
+        Self {
+            file,
+            view,
+        }
+    }
+}
+
+// how the panes share the editor area, leaves are indices into the panes
+pub enum Split {
+    Pane(usize),
+    // side by side, left to right
+    Row(Vec<(Size, Split)>),
+    // stacked, top to bottom
+    Column(Vec<(Size, Split)>),
+}
+
+impl Split {
+    pub fn contains(&self, pane: usize) -> bool {
+        match self {
+            Split::Pane(id) => *id == pane,
+            Split::Row(children) | Split::Column(children) => children.iter().any(|(_, child)| child.contains(pane)),
+        }
+    }
+
+    // `new` goes where `pane` was and `pane` moves right or down, a split the same way as the parent shares it equally with its siblings
+    pub fn split(&mut self, pane: usize, new: usize, side_by_side: bool) {
+        let row = matches!(self, Split::Row(_));
+        match self {
+            Split::Pane(id) if *id == pane => {
+                let children = vec![(Size::Fill, Split::Pane(new)), (Size::Fill, Split::Pane(pane))];
+                *self = if side_by_side { Split::Row(children) } else { Split::Column(children) };
+            },
+            Split::Pane(_) => {},
+            Split::Row(children) | Split::Column(children) => {
+                let index = children.iter().position(|(_, child)| matches!(child, Split::Pane(id) if *id == pane));
+                match index {
+                    Some(i) if row == side_by_side => {
+                        children.insert(i, (Size::Fill, Split::Pane(new)));
+                        for (size, _) in children.iter_mut() {
+                            *size = Size::Fill;
+                        }
+                    },
+                    _ => for (_, child) in children.iter_mut() {
+                        child.split(pane, new, side_by_side);
+                    },
+                }
+            },
+        }
+    }
+
+    // the panes after the removed one move down an index, and a split left with one child becomes that child
+    pub fn remove(&mut self, pane: usize) {
+        match self {
+            Split::Pane(id) => {
+                if *id > pane {
+                    *id -= 1;
+                }
+            },
+            Split::Row(children) | Split::Column(children) => {
+                children.retain(|(_, child)| !matches!(child, Split::Pane(id) if *id == pane));
+                for (_, child) in children.iter_mut() {
+                    child.remove(pane);
+                }
+                if !children.iter().any(|(size, _)| *size == Size::Fill) {
+                    if let Some((size, _)) = children.last_mut() {
+                        *size = Size::Fill;
+                    }
+                }
+                if children.len() == 1 {
+                    let (_, only) = children.remove(0);
+                    *self = only;
+                }
+            },
+        }
+    }
+
+    // the panes with a divider between each of them
+    pub fn layout(&self) -> Layout {
+        let with_dividers = |children: &Vec<(Size, Split)>, vertical: bool| {
+            let mut result = vec![];
+            for (i, (size, child)) in children.iter().enumerate() {
+                if i > 0 {
+                    result.push((Size::Fixed(1), Layout::Area(Area::Divider { vertical })));
+                }
+                result.push((*size, child.layout()));
+            }
+            result
+        };
+        match self {
+            Split::Pane(id) => Layout::Area(Area::Pane(*id)),
+            Split::Row(children) => Layout::Row(with_dividers(children, true)),
+            Split::Column(children) => Layout::Column(with_dividers(children, false)),
+        }
+    }
+
+    // grows `pane` by `delta` rows, or columns when `side_by_side`, taking them from its neighbour in the nearest split that way
+    pub fn resize(&mut self, rect: Rect, pane: usize, side_by_side: bool, delta: i32) -> bool {
+        let row = matches!(self, Split::Row(_));
+        let (Split::Row(children) | Split::Column(children)) = self else { return false };
+        let parts = parts(children, rect, row);
+        let Some(i) = children.iter().position(|(_, child)| child.contains(pane)) else { return false };
+        if children[i].1.resize(parts[2 * i], pane, side_by_side, delta) {
+            return true;
+        }
+        if row != side_by_side || children.len() < 2 {
+            return false;
+        }
+        // the last pane grows by moving the divider before it instead of the one after it
+        let moved = if i + 1 < children.len() { move_divider(children, &parts, row, i, delta) } else { move_divider(children, &parts, row, i - 1, -delta) };
+        moved != 0
+    }
+
+    // moves the divider at `from` by `delta`, returning where it ended up
+    pub fn drag(&mut self, rect: Rect, (x, y): (u16, u16), (dx, dy): (i32, i32)) -> Option<(u16, u16)> {
+        let row = matches!(self, Split::Row(_));
+        let (Split::Row(children) | Split::Column(children)) = self else { return None };
+        let parts = parts(children, rect, row);
+        match parts.iter().position(|part| part.contains(x, y)) {
+            Some(i) if i % 2 == 1 => {
+                let moved = move_divider(children, &parts, row, i / 2, if row { dx } else { dy });
+                Some(if row { ((x as i32 + moved) as u16, y) } else { (x, (y as i32 + moved) as u16) })
+            },
+            Some(i) => children[i / 2].1.drag(parts[i], (x, y), (dx, dy)),
+            None => None,
+        }
+    }
+}
+
+// the rect of every child with the rect of the divider after it in between, so child i is at 2 * i
+fn parts(children: &[(Size, Split)], rect: Rect, row: bool) -> Vec<Rect> {
+    let mut sizes = vec![];
+    for (i, (size, _)) in children.iter().enumerate() {
+        if i > 0 {
+            sizes.push((Size::Fixed(1), ()));
+        }
+        sizes.push((*size, ()));
+    }
+    let mut start = if row { rect.x } else { rect.y };
+    layout::sizes(if row { rect.width } else { rect.height }, &sizes).into_iter().map(|size| {
+        let part = if row { Rect::new(start, rect.y, size, rect.height) } else { Rect::new(rect.x, start, rect.width, size) };
+        start += size;
+        part
+    }).collect()
+}
+
+// every child keeps the size it has now except the last one, which fills what is left when the terminal is resized.
+// both sides of the divider keep at least a row or column, and how far it actually moved is returned
+fn move_divider(children: &mut [(Size, Split)], parts: &[Rect], row: bool, divider: usize, delta: i32) -> i32 {
+    let length = |rect: Rect| (if row { rect.width } else { rect.height }) as i32;
+    let (before, after) = (length(parts[2 * divider]), length(parts[2 * divider + 2]));
+    if before + after < 2 {
+        return 0;
+    }
+    let delta = delta.clamp(1 - before, after - 1);
+    if delta == 0 {
+        return 0;
+    }
+
+    for ((size, _), part) in children.iter_mut().zip(parts.iter().step_by(2)) {
+        *size = Size::Fixed(length(*part) as u16);
+    }
+    children[divider].0 = Size::Fixed((before + delta) as u16);
+    children[divider + 1].0 = Size::Fixed((after - delta) as u16);
+    if let Some((size, _)) = children.last_mut() {
+        *size = Size::Fill;
+    }
+    delta
+}
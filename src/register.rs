@@ -0,0 +1,335 @@
+use std::collections::{HashMap, VecDeque};
+use std::env;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::selection::{VisualMode, Yank};
+
+// the "+ and "* registers go through one of these
+pub trait ClipboardProvider {
+    fn name(&self) -> &str;
+    fn copy(&mut self, text: &str) -> Result<(), String>;
+    fn paste(&mut self) -> Result<String, String>;
+    // text to send to the terminal for it to set the clipboard, for providers that go through the terminal
+    fn take_terminal_copy(&mut self) -> Option<String> {
+        None
+    }
+}
+
+// pipes text through a clipboard program such as xclip or wl-copy
+pub struct CommandClipboard {
+    name: &'static str,
+    copy: &'static [&'static str],
+    paste: &'static [&'static str],
+}
+
+impl ClipboardProvider for CommandClipboard {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn copy(&mut self, text: &str) -> Result<(), String> {
+        let mut child = Command::new(self.copy[0])
+            .args(&self.copy[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("{}: {}", self.copy[0], e))?;
+
+        child.stdin.take().unwrap().write_all(text.as_bytes()).map_err(|e| format!("{}: {}", self.copy[0], e))?;
+        match child.wait() {
+            Ok(status) if status.success() => Ok(()),
+            Ok(status) => Err(format!("{} exited with {}", self.copy[0], status)),
+            Err(e) => Err(format!("{}: {}", self.copy[0], e)),
+        }
+    }
+
+    fn paste(&mut self) -> Result<String, String> {
+        let output = Command::new(self.paste[0])
+            .args(&self.paste[1..])
+            .stderr(Stdio::null())
+            .output()
+            .map_err(|e| format!("{}: {}", self.paste[0], e))?;
+
+        if !output.status.success() {
+            return Err(format!("{} exited with {}", self.paste[0], output.status));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+// asks the terminal to set the clipboard, which also works over ssh. reading it back is rarely allowed
+#[derive(Default)]
+pub struct Osc52Clipboard {
+    // copied but not sent yet, the window writes it through its backend
+    pending: Option<String>,
+}
+
+impl ClipboardProvider for Osc52Clipboard {
+    fn name(&self) -> &str {
+        "osc 52"
+    }
+
+    fn copy(&mut self, text: &str) -> Result<(), String> {
+        self.pending = Some(text.to_string());
+        Ok(())
+    }
+
+    fn paste(&mut self) -> Result<String, String> {
+        Err(String::from("The osc 52 clipboard can only be copied to"))
+    }
+
+    fn take_terminal_copy(&mut self) -> Option<String> {
+        self.pending.take()
+    }
+}
+
+// a clipboard in memory, sharing its text so a test can still look at it once the registers own it
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct FakeClipboard {
+    pub text: std::rc::Rc<std::cell::RefCell<String>>,
+}
+
+#[cfg(test)]
+impl ClipboardProvider for FakeClipboard {
+    fn name(&self) -> &str {
+        "fake"
+    }
+
+    fn copy(&mut self, text: &str) -> Result<(), String> {
+        *self.text.borrow_mut() = text.to_string();
+        Ok(())
+    }
+
+    fn paste(&mut self) -> Result<String, String> {
+        Ok(self.text.borrow().clone())
+    }
+}
+
+const PROGRAMS: [CommandClipboard; 4] = [
+    CommandClipboard { name: "wl-copy", copy: &["wl-copy"], paste: &["wl-paste", "--no-newline"] },
+    CommandClipboard { name: "xclip", copy: &["xclip", "-selection", "clipboard"], paste: &["xclip", "-selection", "clipboard", "-o"] },
+    CommandClipboard { name: "xsel", copy: &["xsel", "--clipboard", "--input"], paste: &["xsel", "--clipboard", "--output"] },
+    CommandClipboard { name: "pbcopy", copy: &["pbcopy"], paste: &["pbpaste"] },
+];
+
+fn installed(program: &str) -> bool {
+    env::var_os("PATH").is_some_and(|path| env::split_paths(&path).any(|directory| Path::new(&directory).join(program).is_file()))
+}
+
+// wl-copy under wayland, xclip or xsel under x11, pbcopy on macos and osc 52 everywhere else
+pub fn detect_clipboard() -> Box<dyn ClipboardProvider> {
+    let wayland = env::var_os("WAYLAND_DISPLAY").is_some();
+    let x11 = env::var_os("DISPLAY").is_some();
+    for program in PROGRAMS {
+        let usable = match program.name {
+            "wl-copy" => wayland,
+            "xclip" | "xsel" => x11,
+            _ => cfg!(target_os = "macos"),
+        };
+        if usable && installed(program.copy[0]) && installed(program.paste[0]) {
+            return Box::new(program);
+        }
+    }
+    Box::new(Osc52Clipboard::default())
+}
+
+const HISTORY: usize = 9;
+
+// "" the unnamed register, "a to "z named ones ("A appends), "0 the last yank, "1 to "9 older deletes,
+// "/ the last search, ": the last command and "+ or "* the system clipboard
+pub struct Registers {
+    unnamed: Option<Yank>,
+    named: HashMap<char, Yank>,
+    yanked: Option<Yank>,
+    deleted: VecDeque<Yank>,
+    pub last_search: String,
+    pub last_command: String,
+    clipboard: Box<dyn ClipboardProvider>,
+}
+
+impl Registers {
+    pub fn new(clipboard: Box<dyn ClipboardProvider>) -> Self {
+        Self {
+            unnamed: None,
+            named: HashMap::new(),
+            yanked: None,
+            deleted: VecDeque::new(),
+            last_search: String::new(),
+            last_command: String::new(),
+            clipboard,
+        }
+    }
+
+    pub fn take_terminal_copy(&mut self) -> Option<String> {
+        self.clipboard.take_terminal_copy()
+    }
+
+    pub fn is_valid(name: char) -> bool {
+        name.is_ascii_alphanumeric() || "\"/:+*".contains(name)
+    }
+
+    // one line listing of the registers asked for, or of every register that holds something
+    pub fn summary(&mut self, names: &str) -> String {
+        let names: Vec<char> = if names.is_empty() { "\"0123456789abcdefghijklmnopqrstuvwxyz/:".chars().collect() } else { names.chars().collect() };
+        let mut summary = vec![];
+        for name in names {
+            if name == '+' || name == '*' {
+                summary.push(format!("\"{} ({})", name, self.clipboard.name()));
+            }
+            else if let Ok(Some(yank)) = self.get(Some(name)) {
+                summary.push(format!("\"{} {}", name, yank.text.replace('\n', "^J").replace('\t', "^I")));
+            }
+        }
+        summary.join("  ")
+    }
+
+    fn store(&mut self, name: char, yank: Yank) -> Result<(), String> {
+        match name {
+            'a'..='z' => {
+                self.named.insert(name, yank);
+            },
+            'A'..='Z' => {
+                let register = self.named.entry(name.to_ascii_lowercase()).or_insert(Yank::new(String::new(), yank.mode));
+                if register.mode == VisualMode::Line || yank.mode == VisualMode::Line {
+                    if !register.text.is_empty() && !register.text.ends_with('\n') {
+                        register.text.push('\n');
+                    }
+                    register.mode = VisualMode::Line;
+                }
+                register.text.push_str(&yank.text);
+            },
+            '+' | '*' => self.clipboard.copy(&yank.text)?,
+            '"' => {},
+            _ => return Err(format!("Register {} is read only", name)),
+        }
+        Ok(())
+    }
+
+    // yanks go to "0, or only to the register that was asked for
+    pub fn yank(&mut self, name: Option<char>, yank: Yank) -> Result<(), String> {
+        match name {
+            Some(name) if name != '"' => self.store(name, yank.clone())?,
+            _ => self.yanked = Some(yank.clone()),
+        }
+        self.unnamed = Some(yank);
+        Ok(())
+    }
+
+    // deletes shift the numbered registers along, "1 being the newest
+    pub fn delete(&mut self, name: Option<char>, yank: Yank) -> Result<(), String> {
+        match name {
+            Some(name) if name != '"' => self.store(name, yank.clone())?,
+            _ => {
+                self.deleted.push_front(yank.clone());
+                self.deleted.truncate(HISTORY);
+            },
+        }
+        self.unnamed = Some(yank);
+        Ok(())
+    }
+
+    pub fn get(&mut self, name: Option<char>) -> Result<Option<Yank>, String> {
+        let text = |text: &str| (!text.is_empty()).then(|| Yank::new(text.to_string(), VisualMode::Char));
+        Ok(match name.unwrap_or('"') {
+            '"' => self.unnamed.clone(),
+            '0' => self.yanked.clone(),
+            digit @ '1'..='9' => self.deleted.get(digit as usize - '1' as usize).cloned(),
+            letter @ ('a'..='z' | 'A'..='Z') => self.named.get(&letter.to_ascii_lowercase()).cloned(),
+            '/' => text(&self.last_search),
+            ':' => text(&self.last_command),
+            '+' | '*' => {
+                let pasted = self.clipboard.paste()?;
+                let mode = if pasted.ends_with('\n') { VisualMode::Line } else { VisualMode::Char };
+                (!pasted.is_empty()).then(|| Yank::new(pasted, mode))
+            },
+            name => return Err(format!("Invalid register: {}", name)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(registers: &mut Registers, name: char) -> Option<String> {
+        registers.get(Some(name)).unwrap().map(|yank| yank.text)
+    }
+
+    fn char_yank(text: &str) -> Yank {
+        Yank::new(text.to_string(), VisualMode::Char)
+    }
+
+    #[test]
+    fn clipboard_registers_go_through_the_provider() {
+        let clipboard = FakeClipboard::default();
+        let mut registers = Registers::new(Box::new(clipboard.clone()));
+        registers.yank(Some('+'), char_yank("copied")).unwrap();
+        assert_eq!(*clipboard.text.borrow(), "copied");
+        // the yank still goes to the unnamed register, but not to "0
+        assert_eq!(text(&mut registers, '"').as_deref(), Some("copied"));
+        assert_eq!(text(&mut registers, '0'), None);
+
+        *clipboard.text.borrow_mut() = String::from("from elsewhere\n");
+        let pasted = registers.get(Some('*')).unwrap().unwrap();
+        assert_eq!((pasted.text.as_str(), pasted.mode), ("from elsewhere\n", VisualMode::Line));
+        assert!(registers.summary("+").contains("(fake)"));
+    }
+
+    #[test]
+    fn uppercase_appends() {
+        let mut registers = Registers::new(Box::new(FakeClipboard::default()));
+        registers.yank(Some('a'), char_yank("one")).unwrap();
+        registers.yank(Some('A'), char_yank(" two")).unwrap();
+        assert_eq!(text(&mut registers, 'a').as_deref(), Some("one two"));
+
+        // appending lines makes it a line register with each on its own line
+        registers.yank(Some('A'), Yank::new(String::from("three\n"), VisualMode::Line)).unwrap();
+        let yank = registers.get(Some('A')).unwrap().unwrap();
+        assert_eq!((yank.text.as_str(), yank.mode), ("one two\nthree\n", VisualMode::Line));
+
+        registers.yank(Some('B'), char_yank("new")).unwrap();
+        assert_eq!(text(&mut registers, 'b').as_deref(), Some("new"));
+    }
+
+    #[test]
+    fn deletes_shift_the_numbered_registers() {
+        let mut registers = Registers::new(Box::new(FakeClipboard::default()));
+        registers.yank(None, char_yank("yanked")).unwrap();
+        for i in 1..=10 {
+            registers.delete(None, char_yank(&format!("delete {}", i))).unwrap();
+        }
+        assert_eq!(text(&mut registers, '1').as_deref(), Some("delete 10"));
+        assert_eq!(text(&mut registers, '9').as_deref(), Some("delete 2"));
+        assert_eq!(text(&mut registers, '0').as_deref(), Some("yanked"));
+        assert_eq!(text(&mut registers, '"').as_deref(), Some("delete 10"));
+
+        // a delete into a named register leaves them where they are
+        registers.delete(Some('x'), char_yank("named")).unwrap();
+        assert_eq!(text(&mut registers, '1').as_deref(), Some("delete 10"));
+        assert_eq!(text(&mut registers, 'x').as_deref(), Some("named"));
+    }
+
+    #[test]
+    fn last_search_and_command() {
+        let mut registers = Registers::new(Box::new(FakeClipboard::default()));
+        assert_eq!(text(&mut registers, '/'), None);
+        registers.last_search = String::from("needle");
+        registers.last_command = String::from("set ts=2");
+        assert_eq!(text(&mut registers, '/').as_deref(), Some("needle"));
+        assert_eq!(text(&mut registers, ':').as_deref(), Some("set ts=2"));
+        assert_eq!(registers.yank(Some('/'), char_yank("x")), Err(String::from("Register / is read only")));
+    }
+
+    #[test]
+    fn osc_52_waits_for_the_terminal() {
+        let mut registers = Registers::new(Box::new(Osc52Clipboard::default()));
+        registers.yank(Some('+'), char_yank("over ssh")).unwrap();
+        assert_eq!(registers.take_terminal_copy().as_deref(), Some("over ssh"));
+        assert_eq!(registers.take_terminal_copy(), None);
+        assert!(registers.get(Some('+')).is_err());
+    }
+}
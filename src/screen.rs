@@ -0,0 +1,127 @@
+use crossterm::{style::Color, Result};
+
+use crate::backend::Backend;
+use crate::colour_string::{Char, ColourString, Info};
+
+// what is on the terminal, so each draw only writes the cells that changed since the last one
+pub struct Screen {
+    width: usize,
+    height: usize,
+    cells: Vec<Char>,
+}
+
+impl Screen {
+    pub fn new() -> Self {
+        Self {
+            width: 0,
+            height: 0,
+            cells: Vec::new(),
+        }
+    }
+
+    // the next draw clears the terminal and writes everything again
+    pub fn invalidate(&mut self) {
+        self.cells.clear();
+    }
+
+    pub fn draw(&mut self, frame: Vec<ColourString>, (width, height): (u16, u16), backend: &mut impl Backend) -> Result<()> {
+        let (width, height) = (width as usize, height as usize);
+        let blank = Char { content: String::from(" "), colour: Info::new(Color::White, Color::Reset, vec![]) };
+        let mut cells = vec![blank; width * height];
+        for (y, line) in frame.iter().take(height).enumerate() {
+            for (x, c) in line.get_content().iter().take(width).enumerate() {
+                cells[y * width + x] = c.clone();
+            }
+        }
+
+        // a new size means nothing on the terminal lines up with the last frame any more
+        let full = width != self.width || height != self.height || self.cells.len() != cells.len();
+        if full {
+            backend.clear()?;
+        }
+
+        for (i, cell) in cells.iter().enumerate() {
+            // the second column of a wide grapheme is drawn with the first, which changes whenever it does
+            let unchanged = cell.is_continuation() || if full { is_blank(cell) } else { self.cells[i] == *cell };
+            if !unchanged {
+                backend.draw_cell((i % width) as u16, (i / width) as u16, cell)?;
+            }
+        }
+
+        self.cells = cells;
+        self.width = width;
+        self.height = height;
+        Ok(())
+    }
+}
+
+// what a cleared terminal already shows
+fn is_blank(cell: &Char) -> bool {
+    cell.content == " " && cell.colour.background == Color::Reset && cell.colour.attributes.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::MemoryBackend;
+
+    fn frame(lines: &[&str]) -> Vec<ColourString> {
+        lines.iter().map(|line| ColourString::new(line.to_string(), None)).collect()
+    }
+
+    // draws the frame and returns the cells that were written
+    fn draw(screen: &mut Screen, backend: &mut MemoryBackend, lines: &[&str]) -> Vec<(u16, u16)> {
+        backend.drawn.clear();
+        let size = backend.size().unwrap();
+        screen.draw(frame(lines), size, backend).unwrap();
+        std::mem::take(&mut backend.drawn)
+    }
+
+    #[test]
+    fn the_same_frame_twice_writes_nothing() {
+        let (mut screen, mut backend) = (Screen::new(), MemoryBackend::new(6, 2));
+        // the terminal is cleared first, so blank cells are left alone
+        assert_eq!(draw(&mut screen, &mut backend, &["ab d", "x"]), vec![(0, 0), (1, 0), (3, 0), (0, 1)]);
+        assert_eq!(draw(&mut screen, &mut backend, &["ab d", "x"]), vec![]);
+        assert_eq!(backend.lines(), ["ab d  ", "x     "]);
+    }
+
+    #[test]
+    fn only_the_changed_cell_is_written() {
+        let (mut screen, mut backend) = (Screen::new(), MemoryBackend::new(6, 2));
+        draw(&mut screen, &mut backend, &["hello", "world"]);
+        assert_eq!(draw(&mut screen, &mut backend, &["hello", "wOrld"]), vec![(1, 1)]);
+        // a cell that goes blank has to be written too
+        assert_eq!(draw(&mut screen, &mut backend, &["hell", "wOrld"]), vec![(4, 0)]);
+        assert_eq!(backend.lines(), ["hell  ", "wOrld "]);
+    }
+
+    #[test]
+    fn a_changed_colour_is_a_change() {
+        let (mut screen, mut backend) = (Screen::new(), MemoryBackend::new(3, 1));
+        draw(&mut screen, &mut backend, &["abc"]);
+        let mut line = ColourString::new(String::from("abc"), None);
+        line.set_colour(Info::new(Color::Red, Color::Reset, vec![]), 2, 3);
+        screen.draw(vec![line], (3, 1), &mut backend).unwrap();
+        assert_eq!(backend.drawn, vec![(2, 0)]);
+    }
+
+    #[test]
+    fn wide_graphemes_are_written_from_their_first_column() {
+        let (mut screen, mut backend) = (Screen::new(), MemoryBackend::new(4, 1));
+        assert_eq!(draw(&mut screen, &mut backend, &["a😀b"]), vec![(0, 0), (1, 0), (3, 0)]);
+        assert_eq!(draw(&mut screen, &mut backend, &["a😃b"]), vec![(1, 0)]);
+        assert_eq!(backend.lines(), ["a😃b"]);
+    }
+
+    #[test]
+    fn a_new_size_or_invalidate_writes_everything_again() {
+        let (mut screen, mut backend) = (Screen::new(), MemoryBackend::new(3, 1));
+        draw(&mut screen, &mut backend, &["abc"]);
+        screen.invalidate();
+        assert_eq!(draw(&mut screen, &mut backend, &["abc"]), vec![(0, 0), (1, 0), (2, 0)]);
+
+        backend = MemoryBackend::new(2, 2);
+        assert_eq!(draw(&mut screen, &mut backend, &["abc", "d"]), vec![(0, 0), (1, 0), (0, 1)]);
+    }
+}
@@ -0,0 +1,245 @@
+use regex::{Regex, RegexBuilder};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::buffer::Buffer;
+
+pub struct Search {
+    pub query: String,
+    pub backward: bool,
+    pub smart_case: bool,
+    pub whole_word: bool,
+    pub highlight: bool,
+    pub origin: (usize, usize),
+    // the match the last search moved to
+    pub current: Option<(usize, usize)>,
+    // index of the current match and the total, shown in the status bar and only counted once it is asked for
+    count: Option<(usize, usize)>,
+    // the buffer revision the matches were counted at
+    counted: usize,
+}
+
+impl Search {
+    pub fn new() -> Self {
+        Self {
+            query: String::new(),
+            backward: false,
+            smart_case: true,
+            whole_word: false,
+            highlight: false,
+            origin: (0, 0),
+            current: None,
+            count: None,
+            counted: 0,
+        }
+    }
+
+    // the query is matched literally, smart case ignores case unless the query has an uppercase letter
+    pub fn regex(&self) -> Option<Regex> {
+        if self.query.is_empty() {
+            return None;
+        }
+
+        let mut pattern = regex::escape(&self.query);
+        if self.whole_word {
+            pattern = format!(r"\b{}\b", pattern);
+        }
+        RegexBuilder::new(&pattern)
+            .case_insensitive(self.smart_case && !self.query.chars().any(|c| c.is_uppercase()))
+            .build()
+            .ok()
+    }
+
+    pub fn flags(&self) -> String {
+        let mut flags = vec![];
+        if self.smart_case {
+            flags.push("smart-case");
+        }
+        if self.whole_word {
+            flags.push("whole-word");
+        }
+        flags.join(", ")
+    }
+
+    // grapheme ranges of every match on a line
+    pub fn line_matches(regex: &Regex, line: &str) -> Vec<(usize, usize)> {
+        let mut graphemes = line.grapheme_indices(true).map(|(i, _)| i).enumerate().peekable();
+        let mut index = |byte: usize| {
+            while let Some(&(_, start)) = graphemes.peek() {
+                if start >= byte {
+                    break;
+                }
+                graphemes.next();
+            }
+            graphemes.peek().map(|&(x, _)| x).unwrap_or(line.graphemes(true).count())
+        };
+
+        regex.find_iter(line)
+            .filter(|found| !found.is_empty())
+            .map(|found| (index(found.start()), index(found.end())))
+            .collect()
+    }
+
+    pub fn matches(regex: &Regex, buffer: &Buffer) -> Vec<(usize, usize)> {
+        let mut matches = vec![];
+        for y in 0..buffer.len_lines() {
+            let line = buffer.line(y);
+            if regex.is_match(&line) {
+                matches.extend(Self::line_matches(regex, &line).into_iter().map(|(x, _)| (x, y)));
+            }
+        }
+        matches
+    }
+
+    // the nearest match after `from` (or before it when searching backwards), wrapping around the file. lines are
+    // searched outwards from `from` so a match nearby is found without going through the rest of the file
+    pub fn find(&mut self, buffer: &Buffer, from: (usize, usize), backward: bool, include_from: bool) -> Option<(usize, usize)> {
+        self.current = None;
+        self.count = None;
+        let regex = self.regex()?;
+        let (from_x, from_y) = from;
+        let lines = buffer.len_lines();
+
+        // the line of `from` comes up again last, for the matches on the other side of it
+        for i in 0..=lines {
+            let y = if backward { (from_y + lines - i % lines) % lines } else { (from_y + i) % lines };
+            let line = buffer.line(y);
+            if !regex.is_match(&line) {
+                continue;
+            }
+            let mut matches = Self::line_matches(&regex, &line).into_iter().map(|(x, _)| x);
+            let x = match (i, backward) {
+                (0, false) => matches.find(|&x| x > from_x || (include_from && x == from_x)),
+                (0, true) => matches.rfind(|&x| x < from_x),
+                (_, false) => matches.min(),
+                (_, true) => matches.max(),
+            };
+            if let Some(x) = x {
+                self.current = Some((x, y));
+                return self.current;
+            }
+        }
+        None
+    }
+
+    // which match the last search moved to and how many there are, counting them again after the buffer is edited.
+    // once the cursor moves off the match there is nothing to show
+    pub fn position(&mut self, buffer: &Buffer, cursor: (usize, usize)) -> Option<(usize, usize)> {
+        if self.current != Some(cursor) {
+            self.current = None;
+        }
+        if self.current.is_none() || self.counted != buffer.revision() {
+            self.count = None;
+        }
+        let current = self.current?;
+        if self.count.is_none() {
+            let matches = Self::matches(&self.regex()?, buffer);
+            let index = matches.iter().position(|&found| found == current)?;
+            self.count = Some((index + 1, matches.len()));
+            self.counted = buffer.revision();
+        }
+        self.count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn search(query: &str) -> Search {
+        let mut search = Search::new();
+        search.query = query.to_string();
+        search
+    }
+
+    #[test]
+    fn smart_case() {
+        let buffer = Buffer::new("Word word WORD\n");
+        assert_eq!(Search::matches(&search("word").regex().unwrap(), &buffer), vec![(0, 0), (5, 0), (10, 0)]);
+        assert_eq!(Search::matches(&search("Word").regex().unwrap(), &buffer), vec![(0, 0)]);
+
+        let mut exact = search("word");
+        exact.smart_case = false;
+        assert_eq!(Search::matches(&exact.regex().unwrap(), &buffer), vec![(5, 0)]);
+    }
+
+    #[test]
+    fn whole_word() {
+        let buffer = Buffer::new("cat concat cat_ cat.\n");
+        let mut search = search("cat");
+        assert_eq!(Search::matches(&search.regex().unwrap(), &buffer).len(), 4);
+        search.whole_word = true;
+        assert_eq!(Search::matches(&search.regex().unwrap(), &buffer), vec![(0, 0), (16, 0)]);
+    }
+
+    #[test]
+    fn the_query_is_literal() {
+        let buffer = Buffer::new("a.b axb (a)\n");
+        assert_eq!(Search::matches(&search("a.b").regex().unwrap(), &buffer), vec![(0, 0)]);
+        assert_eq!(Search::matches(&search("(a)").regex().unwrap(), &buffer), vec![(8, 0)]);
+        assert!(search("").regex().is_none());
+    }
+
+    #[test]
+    fn matches_are_counted_by_grapheme() {
+        let buffer = Buffer::new("字é x 👍🏽x\n");
+        assert_eq!(Search::matches(&search("x").regex().unwrap(), &buffer), vec![(3, 0), (6, 0)]);
+    }
+
+    #[test]
+    fn find_wraps_around() {
+        let buffer = Buffer::new("one x\ntwo\nx three x\n");
+        let mut search = search("x");
+        assert_eq!(search.find(&buffer, (0, 1), false, false), Some((0, 2)));
+        assert_eq!(search.find(&buffer, (0, 2), false, false), Some((8, 2)));
+        assert_eq!(search.find(&buffer, (8, 2), false, false), Some((4, 0)));
+        assert_eq!(search.find(&buffer, (4, 0), false, true), Some((4, 0)));
+        // and the other way
+        assert_eq!(search.find(&buffer, (8, 2), true, false), Some((0, 2)));
+        assert_eq!(search.find(&buffer, (0, 2), true, false), Some((4, 0)));
+        assert_eq!(search.find(&buffer, (4, 0), true, false), Some((8, 2)));
+        // only match in the file is found from itself
+        let buffer = Buffer::new("a x b\n");
+        assert_eq!(search.find(&buffer, (2, 0), false, false), Some((2, 0)));
+        assert_eq!(search.find(&buffer, (2, 0), true, false), Some((2, 0)));
+        assert_eq!(Search::new().find(&buffer, (0, 0), false, false), None);
+    }
+
+    #[test]
+    fn position_counts_the_matches() {
+        let buffer = Buffer::new("x\nx x\n\nx\n");
+        let mut search = search("x");
+        assert_eq!(search.position(&buffer, (0, 0)), None);
+        search.find(&buffer, (0, 0), false, false);
+        assert_eq!(search.position(&buffer, (0, 1)), Some((2, 4)));
+        search.find(&buffer, (0, 0), true, false);
+        assert_eq!(search.position(&buffer, (0, 3)), Some((4, 4)));
+        search.query = String::from("y");
+        search.find(&buffer, (0, 0), false, false);
+        assert_eq!(search.position(&buffer, (0, 0)), None);
+    }
+
+    #[test]
+    fn position_is_counted_again_after_an_edit() {
+        let mut buffer = Buffer::new("x\nx x\n");
+        let mut search = search("x");
+        search.find(&buffer, (0, 0), false, false);
+        assert_eq!(search.position(&buffer, (0, 1)), Some((2, 3)));
+        buffer.insert((0, 0), "x ");
+        assert_eq!(search.position(&buffer, (0, 1)), Some((3, 4)));
+        buffer.remove((0, 0), (2, 0));
+        assert_eq!(search.position(&buffer, (0, 1)), Some((2, 3)));
+        // the match under the cursor edited away
+        buffer.insert((0, 1), "y");
+        assert_eq!(search.position(&buffer, (0, 1)), None);
+    }
+
+    #[test]
+    fn position_is_forgotten_once_the_cursor_moves_off_the_match() {
+        let buffer = Buffer::new("x\nx x\n");
+        let mut search = search("x");
+        search.find(&buffer, (0, 0), false, false);
+        assert_eq!(search.position(&buffer, (1, 1)), None);
+        assert_eq!(search.current, None);
+        assert_eq!(search.position(&buffer, (0, 1)), None);
+    }
+}
@@ -0,0 +1,343 @@
+use crate::editor::File;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VisualMode {
+    Char,
+    Line,
+    Block,
+}
+
+impl VisualMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            VisualMode::Char => "visual",
+            VisualMode::Line => "visual line",
+            VisualMode::Block => "visual block",
+        }
+    }
+}
+
+// yanked or deleted text, always stored with '\n' line endings and pasted the way it was selected
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Yank {
+    pub text: String,
+    pub mode: VisualMode,
+}
+
+impl Yank {
+    pub fn new(text: String, mode: VisualMode) -> Self {
+        Self {
+            text: text.replace("\r\n", "\n"),
+            mode,
+        }
+    }
+
+    // p pastes after the cursor (or below the line), P before it
+    pub fn paste(&self, file: &mut File, before: bool) {
+        let (x, y) = file.clamp(file.cursor_index());
+        let line_ending = file.line_ending.as_str();
+        let len = file.buffer.line_len(y);
+
+        match self.mode {
+            VisualMode::Char => {
+                let position = (if before { x } else { (x + 1).min(len) }, y);
+                file.replace(position, "", &self.text.replace('\n', line_ending));
+                file.set_cursor_index(position);
+            },
+            VisualMode::Line => {
+                let text = self.text.replace('\n', line_ending);
+                if before {
+                    file.replace((0, y), "", &text);
+                }
+                else if y + 1 < file.buffer.len_lines() {
+                    file.replace((0, y + 1), "", &text);
+                }
+                else {
+                    file.replace((len, y), "", &format!("{}{}", line_ending, text.strip_suffix(line_ending).unwrap_or(&text)));
+                }
+                file.set_cursor_index((0, if before { y } else { y + 1 }));
+            },
+            VisualMode::Block => {
+                let column = file.buffer.display_column((if before || x == len { x } else { x + 1 }, y), file.tab_width);
+                for (i, segment) in self.text.split('\n').enumerate() {
+                    let row = y + i;
+                    if row >= file.buffer.len_lines() {
+                        let last = file.buffer.len_lines() - 1;
+                        file.replace((file.buffer.line_len(last), last), "", line_ending);
+                    }
+
+                    let row_len = file.buffer.line_len(row);
+                    let width = file.buffer.display_column((row_len, row), file.tab_width);
+                    if width < column {
+                        file.replace((row_len, row), "", &" ".repeat(column - width));
+                    }
+                    file.replace((file.buffer.grapheme_index(row, column, file.tab_width), row), "", segment);
+                }
+                file.set_cursor_index((file.buffer.grapheme_index(y, column, file.tab_width), y));
+            },
+        }
+    }
+}
+
+// the other end of the selection is wherever the cursor is
+#[derive(Clone, Copy, Debug)]
+pub struct Selection {
+    pub mode: VisualMode,
+    pub anchor: (usize, usize),
+}
+
+impl Selection {
+    pub fn new(mode: VisualMode, anchor: (usize, usize)) -> Self {
+        Self {
+            mode,
+            anchor,
+        }
+    }
+
+    fn ends(&self, file: &File) -> ((usize, usize), (usize, usize)) {
+        let (anchor, cursor) = (file.clamp(self.anchor), file.clamp(file.cursor_index()));
+        if (anchor.1, anchor.0) <= (cursor.1, cursor.0) { (anchor, cursor) } else { (cursor, anchor) }
+    }
+
+    pub fn rows(&self, file: &File) -> (usize, usize) {
+        let ((_, start), (_, end)) = self.ends(file);
+        (start, end)
+    }
+
+    // display columns covered by a block selection, inclusive
+    fn columns(&self, file: &File) -> (usize, usize) {
+        let anchor = file.buffer.display_column(file.clamp(self.anchor), file.tab_width);
+        let cursor = file.buffer.display_column(file.clamp(file.cursor_index()), file.tab_width);
+        (anchor.min(cursor), anchor.max(cursor))
+    }
+
+    // selected graphemes of every row as (row, start, end), the end is exclusive
+    pub fn spans(&self, file: &File) -> Vec<(usize, usize, usize)> {
+        let ((start_x, start_y), (end_x, end_y)) = self.ends(file);
+        (start_y..=end_y).map(|y| {
+            let len = file.buffer.line_len(y);
+            match self.mode {
+                VisualMode::Char => (y, if y == start_y { start_x } else { 0 }, if y == end_y { (end_x + 1).min(len) } else { len }),
+                VisualMode::Line => (y, 0, len),
+                VisualMode::Block => {
+                    let (first, last) = self.columns(file);
+                    let start = file.buffer.grapheme_index(y, first, file.tab_width).min(len);
+                    (y, start, (file.buffer.grapheme_index(y, last, file.tab_width) + 1).clamp(start, len))
+                },
+            }
+        }).collect()
+    }
+
+    // the buffer range a char or line selection covers, including the newline after a line
+    fn range(&self, file: &File) -> ((usize, usize), (usize, usize)) {
+        let ((start_x, start_y), (end_x, end_y)) = self.ends(file);
+        let last = file.buffer.len_lines() - 1;
+        let end_len = file.buffer.line_len(end_y);
+
+        match self.mode {
+            VisualMode::Line if end_y < last => ((0, start_y), (0, end_y + 1)),
+            VisualMode::Line if start_y > 0 => ((file.buffer.line_len(start_y - 1), start_y - 1), (end_len, end_y)),
+            VisualMode::Line => ((0, 0), (end_len, end_y)),
+            _ if end_x < end_len => ((start_x, start_y), (end_x + 1, end_y)),
+            _ if end_y < last => ((start_x, start_y), (0, end_y + 1)),
+            _ => ((start_x, start_y), (end_len, end_y)),
+        }
+    }
+
+    pub fn yank(&self, file: &File) -> Yank {
+        let (start_y, end_y) = self.rows(file);
+        let text = match self.mode {
+            VisualMode::Char => {
+                let (start, end) = self.range(file);
+                file.buffer.slice(start, end)
+            },
+            VisualMode::Line => (start_y..=end_y).map(|y| format!("{}\n", file.buffer.line(y))).collect(),
+            VisualMode::Block => {
+                self.spans(file).into_iter().map(|(y, start, end)| file.buffer.slice((start, y), (end, y))).collect::<Vec<String>>().join("\n")
+            },
+        };
+        Yank::new(text, self.mode)
+    }
+
+    // removes the selection and returns what was removed, callers group the history
+    pub fn delete(&self, file: &mut File) -> Yank {
+        let yank = self.yank(file);
+        let (start_y, column) = (self.rows(file).0, self.columns(file).0);
+        match self.mode {
+            VisualMode::Block => {
+                for (y, start, end) in self.spans(file).into_iter().rev() {
+                    let text = file.buffer.slice((start, y), (end, y));
+                    file.replace((start, y), &text, "");
+                }
+                file.set_cursor_index((file.buffer.grapheme_index(start_y, column, file.tab_width).min(file.buffer.line_len(start_y)), start_y));
+            },
+            _ => {
+                let (start, end) = self.range(file);
+                let text = file.buffer.slice(start, end);
+                file.replace(start, &text, "");
+                if self.mode == VisualMode::Line {
+                    file.set_cursor_index((0, start_y.min(file.buffer.len_lines() - 1)));
+                }
+            },
+        }
+        yank
+    }
+
+    // > and < shift every selected line by one tab, or tab width spaces with expandtab
+    pub fn indent(&self, file: &mut File, outdent: bool) {
+        let (start_y, end_y) = self.rows(file);
+        let unit = if file.expand_tab { " ".repeat(file.tab_width) } else { String::from("\t") };
+        for y in start_y..=end_y {
+            let line = file.buffer.line(y);
+            if outdent {
+                let indent = if line.starts_with('\t') { String::from("\t") } else { line.chars().take(file.tab_width).take_while(|&c| c == ' ').collect() };
+                if !indent.is_empty() {
+                    file.replace((0, y), &indent, "");
+                }
+            }
+            else if !line.is_empty() {
+                file.replace((0, y), "", &unit);
+            }
+        }
+        file.set_cursor_index((0, start_y));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::*;
+
+    fn file(name: &str, text: &str) -> File {
+        let path = env::temp_dir().join(format!("vcte-selection-{}-{}.txt", name, process::id()));
+        fs::write(&path, text).unwrap();
+        File::new(path.to_string_lossy().to_string()).unwrap()
+    }
+
+    // a selection from `anchor` to the cursor at `cursor`
+    fn select(file: &mut File, mode: VisualMode, anchor: (usize, usize), cursor: (usize, usize)) -> Selection {
+        file.set_cursor_index(cursor);
+        Selection::new(mode, anchor)
+    }
+
+    #[test]
+    fn charwise_selections_go_either_way() {
+        let mut file = file("char", "hello\nworld\n");
+        let selection = select(&mut file, VisualMode::Char, (1, 0), (2, 1));
+        assert_eq!(selection.spans(&file), vec![(0, 1, 5), (1, 0, 3)]);
+        assert_eq!(selection.yank(&file), Yank::new(String::from("ello\nwor"), VisualMode::Char));
+
+        let selection = select(&mut file, VisualMode::Char, (2, 1), (1, 0));
+        assert_eq!(selection.yank(&file).text, "ello\nwor");
+        // the end of a line takes its newline with it
+        let selection = select(&mut file, VisualMode::Char, (3, 0), (9, 0));
+        assert_eq!(selection.yank(&file).text, "lo\n");
+    }
+
+    #[test]
+    fn linewise_selections_take_whole_lines() {
+        let mut file = file("line", "a\nb\nc\n");
+        let selection = select(&mut file, VisualMode::Line, (1, 0), (0, 1));
+        assert_eq!(selection.spans(&file), vec![(0, 0, 1), (1, 0, 1)]);
+        assert_eq!(selection.yank(&file).text, "a\nb\n");
+        let yank = selection.delete(&mut file);
+        assert_eq!(yank.text, "a\nb\n");
+        assert_eq!(file.buffer.text(), "c\n");
+        assert_eq!(file.cursor_index(), (0, 0));
+
+        // the last line without a newline takes the one before it
+        let mut file = self::file("line_last", "a\nb");
+        let selection = select(&mut file, VisualMode::Line, (0, 1), (0, 1));
+        assert_eq!(selection.delete(&mut file).text, "b\n");
+        assert_eq!(file.buffer.text(), "a");
+    }
+
+    #[test]
+    fn block_selections_are_columns() {
+        let mut file = file("block", "abcd\nef\nghij\n");
+        let selection = select(&mut file, VisualMode::Block, (2, 2), (1, 0));
+        assert_eq!(selection.spans(&file), vec![(0, 1, 3), (1, 1, 2), (2, 1, 3)]);
+        assert_eq!(selection.yank(&file).text, "bc\nf\nhi");
+        selection.delete(&mut file);
+        assert_eq!(file.buffer.text(), "ad\ne\ngj\n");
+        assert_eq!(file.cursor_index(), (1, 0));
+    }
+
+    #[test]
+    fn deleting_then_pasting_puts_the_text_back() {
+        let mut file = file("round_trip", "hello world\nsecond\n");
+        let yank = select(&mut file, VisualMode::Char, (0, 0), (5, 0)).delete(&mut file);
+        assert_eq!(file.buffer.text(), "world\nsecond\n");
+        yank.paste(&mut file, true);
+        assert_eq!(file.buffer.text(), "hello world\nsecond\n");
+
+        let yank = select(&mut file, VisualMode::Line, (0, 1), (0, 1)).delete(&mut file);
+        assert_eq!(file.buffer.text(), "hello world\n");
+        file.set_cursor_index((0, 0));
+        yank.paste(&mut file, false);
+        assert_eq!(file.buffer.text(), "hello world\nsecond\n");
+        assert_eq!(file.cursor_index(), (0, 1));
+        yank.paste(&mut file, true);
+        assert_eq!(file.buffer.text(), "hello world\nsecond\nsecond\n");
+    }
+
+    #[test]
+    fn lines_pasted_after_the_last_line_start_a_new_one() {
+        let mut file = file("last_line", "a\nb");
+        let yank = select(&mut file, VisualMode::Line, (0, 0), (0, 0)).yank(&file);
+        file.set_cursor_index((0, 1));
+        yank.paste(&mut file, false);
+        assert_eq!(file.buffer.text(), "a\nb\na");
+    }
+
+    #[test]
+    fn blocks_pasted_past_the_end_of_lines_are_padded() {
+        let mut file = file("block_paste", "abc\nx\n");
+        let yank = Yank::new(String::from("12\n34"), VisualMode::Block);
+        file.set_cursor_index((2, 0));
+        yank.paste(&mut file, false);
+        assert_eq!(file.buffer.text(), "abc12\nx  34\n");
+
+        // and go on past the last line
+        let mut file = self::file("block_paste_end", "abc\nx");
+        file.set_cursor_index((0, 1));
+        yank.paste(&mut file, true);
+        assert_eq!(file.buffer.text(), "abc\n12x\n34");
+    }
+
+    #[test]
+    fn crlf_files_yank_newlines_and_paste_their_own() {
+        let mut file = file("crlf", "one\r\ntwo\r\n");
+        let yank = select(&mut file, VisualMode::Char, (1, 0), (0, 1)).delete(&mut file);
+        assert_eq!(yank.text, "ne\nt");
+        assert_eq!(file.buffer.text(), "owo\r\n");
+        file.set_cursor_index((0, 0));
+        yank.paste(&mut file, false);
+        assert_eq!(file.buffer.text(), "one\r\ntwo\r\n");
+
+        let yank = select(&mut file, VisualMode::Line, (0, 0), (0, 0)).yank(&file);
+        assert_eq!(yank.text, "one\n");
+        yank.paste(&mut file, false);
+        assert_eq!(file.buffer.text(), "one\r\none\r\ntwo\r\n");
+    }
+
+    #[test]
+    fn indent_and_outdent() {
+        let mut file = file("indent", "a\n\n  b\n");
+        select(&mut file, VisualMode::Line, (0, 0), (0, 2)).indent(&mut file, false);
+        // empty lines are left alone
+        assert_eq!(file.buffer.text(), "    a\n\n      b\n");
+        assert_eq!(file.cursor_index(), (0, 0));
+        select(&mut file, VisualMode::Line, (0, 0), (0, 2)).indent(&mut file, true);
+        select(&mut file, VisualMode::Line, (0, 0), (0, 2)).indent(&mut file, true);
+        assert_eq!(file.buffer.text(), "a\n\nb\n");
+
+        let mut file = self::file("indent_tabs", "\tx\n");
+        assert!(!file.expand_tab);
+        select(&mut file, VisualMode::Char, (0, 0), (1, 0)).indent(&mut file, false);
+        assert_eq!(file.buffer.text(), "\t\tx\n");
+        select(&mut file, VisualMode::Char, (0, 0), (1, 0)).indent(&mut file, true);
+        assert_eq!(file.buffer.text(), "\tx\n");
+    }
+}
@@ -1,5 +1,3 @@
-use crossterm::{terminal};
-
 use crate::colour_string::{ColourString};
 
 pub struct StatusBar {
@@ -23,12 +21,9 @@ impl StatusBar {
         self.command_output = Some(command_output);
     }
 
-    pub fn render(&self) -> Vec<ColourString> {
-        let (terminal_x, terminal_y) = terminal::size().unwrap();
-        let mut frame: Vec<ColourString> = vec![ColourString::new(String::from(""), None); terminal_y as usize];
-
-        frame.last_mut().unwrap().push_colour_string(self.command_output.clone().unwrap_or(self.message.clone()));
-        frame.last_mut().unwrap().truncate(terminal_x as usize);
-        frame
+    pub fn render(&self, width: u16) -> Vec<ColourString> {
+        let mut line = self.command_output.clone().unwrap_or(self.message.clone());
+        line.truncate(width as usize);
+        vec![line]
     }
 }
\ No newline at end of file
@@ -0,0 +1,430 @@
+use regex::{Regex, RegexBuilder};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::editor::File;
+
+// a match waiting to be replaced, `start` and `end` are byte offsets into its line
+pub struct Match {
+    pub position: (usize, usize),
+    start: usize,
+    end: usize,
+    replacement: String,
+}
+
+pub struct Substitute {
+    pub regex: Regex,
+    replacement: String,
+    global: bool,
+    pub confirm: bool,
+    row: usize,
+    end: usize,
+    column: usize,
+    previous_end: Option<(usize, usize)>,
+    last_line: Option<usize>,
+    pub matches: usize,
+    pub count: usize,
+    pub lines: usize,
+    pub current: Option<Match>,
+}
+
+impl Substitute {
+    // `[range]s/pattern/replacement/[flags]`, returns None when the command is something else.
+    // ranges are `%`, `'<,'>`, `n` or `n,m` where n and m can also be `.` or `$`
+    pub fn parse(command: &str, file: &File, last_search: Option<Regex>) -> Option<Result<Self, String>> {
+        let syntax = Regex::new(r"^(?:(%)|('<,'>)|([.$]|\d+)(?:,([.$]|\d+))?)?s(?:ubstitute)?([^\w\s\\])(.*)$").unwrap();
+        let captures = syntax.captures(command.trim())?;
+
+        let lines = file.buffer.len_lines();
+        let current = file.cursor_index().1.min(lines - 1);
+        let address = |address: &str| -> Result<usize, String> {
+            match address {
+                "." => Ok(current),
+                "$" => Ok(lines - 1),
+                number => match number.parse::<usize>() {
+                    Ok(number) if number > 0 && number <= lines => Ok(number - 1),
+                    _ => Err(format!("Invalid range: {}", number)),
+                },
+            }
+        };
+
+        let range = if captures.get(1).is_some() {
+            Ok((0, lines - 1))
+        }
+        else if captures.get(2).is_some() {
+            file.last_visual.map(|(start, end)| (start.min(lines - 1), end.min(lines - 1))).ok_or(String::from("No visual selection"))
+        }
+        else {
+            match (captures.get(3), captures.get(4)) {
+                (Some(start), Some(end)) => address(start.as_str()).and_then(|start| Ok((start, address(end.as_str())?))),
+                (Some(start), None) => address(start.as_str()).map(|start| (start, start)),
+                _ => Ok((current, current)),
+            }
+        };
+
+        let delimiter = captures[5].chars().next().unwrap();
+        Some(range.and_then(|range| Self::new(&captures[6], delimiter, range, last_search)))
+    }
+
+    fn new(rest: &str, delimiter: char, range: (usize, usize), last_search: Option<Regex>) -> Result<Self, String> {
+        let parts = split(rest, delimiter);
+        let pattern = parts.first().cloned().unwrap_or_default();
+        let replacement = parts.get(1).cloned().unwrap_or_default();
+        let flags = parts.get(2).cloned().unwrap_or_default();
+        if parts.len() > 3 {
+            return Err(format!("Trailing characters: {}", parts[3..].join(&delimiter.to_string())));
+        }
+
+        let (mut global, mut confirm, mut ignore_case) = (false, false, false);
+        for flag in flags.chars() {
+            match flag {
+                'g' => global = true,
+                'c' => confirm = true,
+                'i' => ignore_case = true,
+                'I' => ignore_case = false,
+                _ => return Err(format!("Invalid flag: {}", flag)),
+            }
+        }
+
+        // an empty pattern reuses the last search
+        let regex = if pattern.is_empty() {
+            last_search.ok_or(String::from("No previous search pattern"))?
+        }
+        else {
+            RegexBuilder::new(&pattern)
+                .case_insensitive(ignore_case)
+                .build()
+                .map_err(|e| format!("Invalid pattern: {}", e.to_string().lines().last().unwrap_or_default().trim_start_matches("error: ")))?
+        };
+
+        let (start, end) = if range.0 <= range.1 { range } else { (range.1, range.0) };
+        Ok(Self {
+            regex,
+            replacement: expansion(&replacement),
+            global,
+            confirm,
+            row: start,
+            end,
+            column: 0,
+            previous_end: None,
+            last_line: None,
+            matches: 0,
+            count: 0,
+            lines: 0,
+            current: None,
+        })
+    }
+
+    fn find(&mut self, file: &File) -> Option<Match> {
+        while self.row <= self.end && self.row < file.buffer.len_lines() {
+            let line = file.buffer.line(self.row);
+            let mut from = self.column;
+            while from <= line.len() {
+                let Some(captures) = self.regex.captures_at(&line, from) else { break };
+                let found = captures.get(0).unwrap();
+
+                // an empty match right after the previous one would repeat it forever
+                if found.is_empty() && self.previous_end == Some((self.row, found.start())) {
+                    match line[found.start()..].chars().next() {
+                        Some(c) => from = found.start() + c.len_utf8(),
+                        None => break,
+                    }
+                    continue;
+                }
+
+                let mut replacement = String::new();
+                captures.expand(&self.replacement, &mut replacement);
+                self.matches += 1;
+                return Some(Match {
+                    position: (line[..found.start()].graphemes(true).count(), self.row),
+                    start: found.start(),
+                    end: found.end(),
+                    replacement: replacement.replace('\n', file.line_ending.as_str()),
+                });
+            }
+            self.next_line();
+        }
+        None
+    }
+
+    fn next_line(&mut self) {
+        self.row += 1;
+        self.column = 0;
+    }
+
+    fn replace(&mut self, file: &mut File, found: Match) {
+        let matched = file.buffer.line(found.position.1)[found.start..found.end].to_string();
+        file.replace(found.position, &matched, &found.replacement);
+
+        self.count += 1;
+        if self.last_line != Some(found.position.1) {
+            self.lines += 1;
+        }
+
+        let newlines = found.replacement.matches('\n').count();
+        self.end += newlines;
+        self.row += newlines;
+        self.column = match found.replacement.rsplit_once('\n') {
+            Some((_, last)) => last.len(),
+            None => found.start + found.replacement.len(),
+        };
+        self.last_line = Some(self.row);
+        self.previous_end = Some((self.row, self.column));
+        if !self.global {
+            self.next_line();
+        }
+    }
+
+    fn skip(&mut self, found: Match) {
+        self.column = found.end;
+        self.previous_end = Some((self.row, self.column));
+        if !self.global {
+            self.next_line();
+        }
+    }
+
+    pub fn replace_all(&mut self, file: &mut File) {
+        while let Some(found) = self.find(file) {
+            self.replace(file, found);
+        }
+    }
+
+    // moves the cursor to the next match to confirm, returns false once there are none left
+    pub fn next(&mut self, file: &mut File) -> bool {
+        self.current = self.find(file);
+        if let Some(found) = &self.current {
+            file.set_cursor_index(found.position);
+        }
+        self.current.is_some()
+    }
+
+    // y replaces, n skips, a replaces the rest without asking and q stops. anything else, undo included, is ignored so
+    // the substitution stays one change. returns whether to keep asking
+    pub fn answer(&mut self, file: &mut File, answer: char) -> bool {
+        let Some(found) = self.current.take() else { return false };
+        match answer {
+            'y' => self.replace(file, found),
+            'n' => self.skip(found),
+            'a' => {
+                self.replace(file, found);
+                self.replace_all(file);
+                return false;
+            },
+            'q' => return false,
+            _ => {
+                self.current = Some(found);
+                return true;
+            },
+        }
+        self.next(file)
+    }
+
+    pub fn prompt(&self) -> String {
+        match &self.current {
+            Some(found) => format!("replace with {} (y/n/a/q)?", found.replacement.escape_debug()),
+            None => String::new(),
+        }
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "{} substitution{} on {} line{}",
+            self.count,
+            if self.count == 1 { "" } else { "s" },
+            self.lines,
+            if self.lines == 1 { "" } else { "s" }
+        )
+    }
+}
+
+// splits on unescaped delimiters, `\<delimiter>` becomes the delimiter itself
+fn split(text: &str, delimiter: char) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(next) if next == delimiter => parts.last_mut().unwrap().push(next),
+                Some(next) => {
+                    parts.last_mut().unwrap().push(c);
+                    parts.last_mut().unwrap().push(next);
+                },
+                None => parts.last_mut().unwrap().push(c),
+            },
+            _ if c == delimiter => parts.push(String::new()),
+            _ => parts.last_mut().unwrap().push(c),
+        }
+    }
+    parts
+}
+
+// turns vim style `\1` and `&` into the `${1}` syntax regex expects, a `$` of its own is kept as it is
+fn expansion(replacement: &str) -> String {
+    let mut expanded = String::new();
+    let mut chars = replacement.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '&' => expanded.push_str("${0}"),
+            '$' => expanded.push_str("$$"),
+            '\\' => match chars.next() {
+                Some(digit @ '0'..='9') => expanded.push_str(&format!("${{{}}}", digit)),
+                Some('n' | 'r') => expanded.push('\n'),
+                Some('t') => expanded.push('\t'),
+                Some('$') => expanded.push_str("$$"),
+                Some(next) => expanded.push(next),
+                None => expanded.push('\\'),
+            },
+            _ => expanded.push(c),
+        }
+    }
+    expanded
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::*;
+
+    fn file(name: &str, text: &str) -> File {
+        let path = env::temp_dir().join(format!("vcte-substitute-{}-{}.txt", name, process::id()));
+        fs::write(&path, text).unwrap();
+        File::new(path.to_string_lossy().to_string()).unwrap()
+    }
+
+    fn parse(command: &str, file: &File) -> Result<Substitute, String> {
+        Substitute::parse(command, file, None).expect("not a substitution")
+    }
+
+    // runs the command without confirming and returns the text it leaves
+    fn substitute(name: &str, text: &str, command: &str) -> String {
+        let mut file = file(name, text);
+        parse(command, &file).unwrap().replace_all(&mut file);
+        file.buffer.text()
+    }
+
+    #[test]
+    fn other_commands_are_not_substitutions() {
+        let file = file("other", "text\n");
+        for command in ["set ff=unix", "saveas a.txt", "s", "split", "s a b"] {
+            assert!(Substitute::parse(command, &file, None).is_none(), "{}", command);
+        }
+        assert!(Substitute::parse("substitute/a/b/", &file, None).is_some());
+        assert!(Substitute::parse("s#a#b#", &file, None).is_some());
+    }
+
+    #[test]
+    fn parse_errors() {
+        let file = file("errors", "one\ntwo\nthree\n");
+        let error = |command: &str| parse(command, &file).err().unwrap();
+        assert_eq!(error("s/a/b/x"), "Invalid flag: x");
+        assert_eq!(error("s/a/b/g/c"), "Trailing characters: c");
+        assert!(error("s/(/b/").starts_with("Invalid pattern"));
+        assert_eq!(error("s//b/"), "No previous search pattern");
+        assert_eq!(error("'<,'>s/a/b/"), "No visual selection");
+        assert_eq!(error("4s/a/b/"), "Invalid range: 4");
+        assert_eq!(error("0,2s/a/b/"), "Invalid range: 0");
+    }
+
+    #[test]
+    fn flags_and_delimiters() {
+        let file = file("flags", "text\n");
+        let substitute = parse("s/A/b/gci", &file).unwrap();
+        assert!(substitute.global && substitute.confirm && substitute.regex.is_match("a"));
+        assert!(!parse("s/A/b/iI", &file).unwrap().regex.is_match("a"));
+        // an escaped delimiter is part of the pattern
+        assert_eq!(parse(r"s/a\/b/c/", &file).unwrap().regex.as_str(), "a/b");
+        assert_eq!(parse("s#a/b#c#", &file).unwrap().regex.as_str(), "a/b");
+        // the last search is used for an empty pattern
+        let last = Regex::new("last").unwrap();
+        assert_eq!(Substitute::parse("s//b/", &file, Some(last)).unwrap().unwrap().regex.as_str(), "last");
+    }
+
+    #[test]
+    fn ranges() {
+        let text = "a\na\na\na\n";
+        assert_eq!(substitute("range_current", text, "s/a/b/"), "b\na\na\na\n");
+        assert_eq!(substitute("range_all", text, "%s/a/b/"), "b\nb\nb\nb\n");
+        assert_eq!(substitute("range_lines", text, "2,3s/a/b/"), "a\nb\nb\na\n");
+        assert_eq!(substitute("range_backwards", text, "3,2s/a/b/"), "a\nb\nb\na\n");
+        assert_eq!(substitute("range_line", text, "3s/a/b/"), "a\na\nb\na\n");
+        assert_eq!(substitute("range_symbols", text, ".,$s/a/b/"), "b\nb\nb\nb\n");
+        assert_eq!(substitute("range_last", text, "$s/a/b/"), "a\na\na\nb\n");
+
+        let mut file = file("range_visual", text);
+        file.last_visual = Some((1, 2));
+        parse("'<,'>s/a/b/", &file).unwrap().replace_all(&mut file);
+        assert_eq!(file.buffer.text(), "a\nb\nb\na\n");
+    }
+
+    #[test]
+    fn only_the_first_match_on_a_line_without_g() {
+        assert_eq!(substitute("first", "aaa\naaa\n", "%s/a/b/"), "baa\nbaa\n");
+        assert_eq!(substitute("global", "aaa\naaa\n", "%s/a/b/g"), "bbb\nbbb\n");
+    }
+
+    #[test]
+    fn captures_and_the_whole_match() {
+        assert_eq!(substitute("captures", "hello world\n", r"s/(\w+) (\w+)/\2 \1 [&]/"), "world hello [hello world]\n");
+        assert_eq!(substitute("escaped", "a\n", r"s/a/\&\\/"), "&\\\n");
+        // a dollar sign isn't a group
+        assert_eq!(substitute("dollar", "price\n", r"s/(price)/$1 \$2/"), "$1 $2\n");
+    }
+
+    #[test]
+    fn empty_matches_replace_between_every_character() {
+        assert_eq!(substitute("empty", "abc\nxx\n", "%s/x*/-/g"), "-a-b-c-\n-\n");
+        assert_eq!(substitute("empty_start", "abc\n", "s/^/> /g"), "> abc\n");
+    }
+
+    #[test]
+    fn line_breaks_in_the_replacement() {
+        assert_eq!(substitute("newline", "a, b, c\nd\n", r"s/, /\n/g"), "a\nb\nc\nd\n");
+        assert_eq!(substitute("crlf", "a, b\r\nc\r\n", r"s/, /\n/g"), "a\r\nb\r\nc\r\n");
+        // the lines the replacement added are still in the range
+        assert_eq!(substitute("range_grows", "a b\nc d\ne f\n", r"1,2s/ /\n/"), "a\nb\nc\nd\ne f\n");
+    }
+
+    #[test]
+    fn one_undo_reverts_the_whole_substitution() {
+        let mut file = file("undo", "one two\nthree two\n");
+        file.history.begin_group();
+        parse(r"%s/two/2\n/g", &file).unwrap().replace_all(&mut file);
+        file.history.end_group();
+        assert_eq!(file.buffer.text(), "one 2\n\nthree 2\n\n");
+        assert!(file.undo());
+        assert_eq!(file.buffer.text(), "one two\nthree two\n");
+        assert!(!file.modified);
+        assert!(!file.undo());
+    }
+
+    #[test]
+    fn confirming_each_match() {
+        let mut file = file("confirm", "a a a a\n");
+        let mut substitute = parse("s/a/b/gc", &file).unwrap();
+        assert!(substitute.next(&mut file));
+        assert_eq!(file.cursor_index(), (0, 0));
+        assert!(substitute.answer(&mut file, 'y'));
+        assert_eq!(file.cursor_index(), (2, 0));
+        assert!(substitute.answer(&mut file, 'n'));
+        // other keys are ignored and ask again
+        assert!(substitute.answer(&mut file, 'u'));
+        assert_eq!(file.cursor_index(), (4, 0));
+        assert!(substitute.answer(&mut file, 'y'));
+        assert!(!substitute.answer(&mut file, 'q'));
+        assert_eq!(file.buffer.text(), "b a b a\n");
+        assert_eq!(substitute.summary(), "2 substitutions on 1 line");
+
+        let mut substitute = parse("s/a/c/gc", &file).unwrap();
+        substitute.next(&mut file);
+        assert!(!substitute.answer(&mut file, 'a'));
+        assert_eq!(file.buffer.text(), "b c b c\n");
+    }
+
+    #[test]
+    fn expansions() {
+        assert_eq!(expansion(r"\1-&-\0"), "${1}-${0}-${0}");
+        assert_eq!(expansion(r"a\tb\nc\rd"), "a\tb\nc\nd");
+        assert_eq!(expansion(r"$1 \$ \/ \"), "$$1 $$ / \\");
+        assert_eq!(split(r"a/b\/c/d\e", '/'), vec!["a", "b/c", r"d\e"]);
+    }
+}
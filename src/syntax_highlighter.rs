@@ -1,87 +1,749 @@
-use std::collections::{HashMap, HashSet};
-use crossterm::style::Color;
-use rand::{self, seq::SliceRandom};
-use crate::colour_string::{ColourString, Info, Char};
+use syntect::parsing::{ParseState, ScopeStack, SyntaxReference};
+use unicode_segmentation::UnicodeSegmentation;
 
-pub struct SyntaxHighlighter {
-    pub colour_key: HashMap<String, Color>,
+use crate::buffer::Buffer;
+use crate::colour_string::ColourString;
+use crate::language::{Grammar, Language};
+use crate::syntaxes::{Syntax, Syntaxes};
+use crate::theme::Theme;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Token {
+    Plain,
+    Keyword,
+    Type,
+    String,
+    Comment,
+    Number,
+    Constant,
+    Operator,
+    Punctuation,
+    Function,
+    Property,
+    Variable,
+    Heading,
+    Emphasis,
+    Link,
 }
 
-pub fn rgb(r: u8, g: u8, b: u8) -> Color {
-    Color::Rgb { r, g, b }
+impl Token {
+    pub const ALL: [Token; 15] = [
+        Token::Plain, Token::Keyword, Token::Type, Token::String, Token::Comment, Token::Number, Token::Constant, Token::Operator,
+        Token::Punctuation, Token::Function, Token::Property, Token::Variable, Token::Heading, Token::Emphasis, Token::Link,
+    ];
+
+    // the key it has in the [syntax] table of a theme
+    pub fn name(&self) -> &'static str {
+        match self {
+            Token::Plain => "plain",
+            Token::Keyword => "keyword",
+            Token::Type => "type",
+            Token::String => "string",
+            Token::Comment => "comment",
+            Token::Number => "number",
+            Token::Constant => "constant",
+            Token::Operator => "operator",
+            Token::Punctuation => "punctuation",
+            Token::Function => "function",
+            Token::Property => "property",
+            Token::Variable => "variable",
+            Token::Heading => "heading",
+            Token::Emphasis => "emphasis",
+            Token::Link => "link",
+        }
+    }
 }
 
-impl SyntaxHighlighter {
-    pub fn new() -> Self {
+const OPERATORS: &str = "+-*/%=<>!&|^~?:";
+const PUNCTUATION: &str = "()[]{},;.";
+
+fn is_identifier(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// what a line leaves open for the next one
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum State {
+    #[default]
+    Normal,
+    // how deeply block comments are nested
+    Comment(usize),
+    String { close: String, escapes: bool },
+    Heredoc { end: String, indented: bool },
+    // a markdown code block and the fence that closes it
+    Fence(String),
+    // where a loaded grammar's parser is up to
+    Grammar(Box<(ParseState, ScopeStack)>),
+}
+
+// splits one line into byte ranges tagged with a token, anything not covered is plain text
+struct Lexer<'a> {
+    language: &'static Language,
+    line: &'a str,
+    i: usize,
+    tokens: Vec<(usize, usize, Token)>,
+    state: State,
+    heredoc: Option<State>,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(language: &'static Language, line: &'a str, state: State) -> Self {
         Self {
-            colour_key: HashMap::new(),
+            language,
+            line,
+            i: 0,
+            tokens: Vec::new(),
+            state,
+            heredoc: None,
         }
     }
 
-    pub fn init(&mut self, lines: Vec<String>) {
-        self.colour_key = HashMap::new();
-        for line in lines {
-            for word in HashSet::<&str>::from_iter(line.split(&[' ', '.', ':'])) {
-                self.colour_key.entry(word.trim().to_string()).or_insert_with(|| {
-                    if word.contains("()") {
-                        Color::AnsiValue(120)
-                    }
-                    else if word.contains(|c: char| c.is_numeric()) {
-                        Color::AnsiValue(222)
-                    }
-                    else if ["(", ")", "{", "}", "[", "]"].contains(&word) {
-                        Color::AnsiValue(103)
-                    }
-                    else if ["+", "-", "*", "/", "%", "=", ">", "<", "!"].iter().any(|&c| word.contains(c)) {
-                        Color::AnsiValue(212)
-                    }
-                    else {
-                        let mut rng = rand::thread_rng();
-                        *[rgb(248, 248, 242), rgb(139, 233, 253), rgb(80, 250, 123),rgb(255, 184, 108),rgb(255, 121, 198),rgb(189, 147, 249),rgb(255, 85, 85),rgb(241, 250, 140)].choose(&mut rng).unwrap()
-                    }
-                });
-            }
+    fn rest(&self) -> &'a str {
+        &self.line[self.i..]
+    }
+
+    fn push(&mut self, end: usize, token: Token) {
+        if end > self.i {
+            self.tokens.push((self.i, end, token));
         }
+        self.i = end;
+    }
+
+    // the end of a run of characters matching `f`, starting at `from`
+    fn run(&self, from: usize, f: impl Fn(char) -> bool) -> usize {
+        self.line[from..].find(|c| !f(c)).map(|end| from + end).unwrap_or(self.line.len())
     }
 
-    pub fn highlight(&mut self, line: String) -> ColourString {
-        let mut highlighted_line = ColourString::new(line.clone(), None);
-        
-        let mut words: Vec<&str> = HashSet::<&str>::from_iter(line.split(&[' ', '.', ':'])).into_iter().collect();
-        words.sort_by(|a, b| a.len().cmp(&b.len()));
-        for word in words {
-            if let Some(colour) = self.colour_key.get(word.trim()) {
-                highlighted_line.set_colour_pattern(word.to_string(), Info::new(*colour, Color::Reset, vec![]));
+    fn next_char(&self, from: usize) -> Option<char> {
+        self.line[from..].chars().find(|c| !c.is_whitespace())
+    }
+
+    // continues whatever the previous line left open
+    fn resume(&mut self) {
+        match self.state.clone() {
+            State::Comment(depth) => self.comment(0, depth),
+            State::String { close, escapes } => self.string(&close, escapes, true),
+            State::Heredoc { end, indented } => {
+                let line = if indented { self.line.trim_start_matches('\t') } else { self.line };
+                if line == end {
+                    self.push(self.line.len(), Token::Keyword);
+                    self.state = State::Normal;
+                }
+                else {
+                    self.push(self.line.len(), Token::String);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    // a block comment from `self.i`, scanning for its end from `from`
+    fn comment(&mut self, from: usize, mut depth: usize) {
+        let (open, close) = self.language.block_comment.unwrap_or_default();
+        let mut end = from;
+        while end < self.line.len() {
+            let rest = &self.line[end..];
+            if rest.starts_with(close) {
+                end += close.len();
+                depth -= 1;
+                if depth == 0 {
+                    self.push(end, Token::Comment);
+                    self.state = State::Normal;
+                    return;
+                }
+            }
+            else if self.language.nested_comments && rest.starts_with(open) {
+                end += open.len();
+                depth += 1;
             }
             else {
-                let mut rng = rand::thread_rng();
-                self.colour_key.insert(word.trim().to_string(), *[rgb(248, 248, 242), rgb(139, 233, 253), rgb(80, 250, 123),rgb(255, 184, 108),rgb(255, 121, 198),rgb(189, 147, 249),rgb(255, 85, 85),rgb(241, 250, 140)].choose(&mut rng).unwrap());
-                highlighted_line.set_colour_pattern(word.to_string(), Info::new(self.colour_key.get(word.trim()).unwrap().clone(), Color::Reset, vec![]));
+                end += rest.chars().next().unwrap().len_utf8();
+            }
+        }
+        self.push(self.line.len(), Token::Comment);
+        self.state = State::Comment(depth);
+    }
+
+    // a string from `self.i` whose opening quote has already been skipped, up to `close`.
+    // unterminated strings run to the end of the line and carry on if they can span lines
+    fn string(&mut self, close: &str, escapes: bool, multiline: bool) {
+        let mut chars = self.line[self.i..].char_indices().map(|(i, c)| (self.i + i, c));
+        while let Some((i, c)) = chars.next() {
+            if c == '\\' && escapes {
+                chars.next();
             }
+            else if self.line[i..].starts_with(close) {
+                self.push(i + close.len(), Token::String);
+                self.state = State::Normal;
+                return;
+            }
+        }
+        self.push(self.line.len(), Token::String);
+        self.state = if multiline || (escapes && self.line.ends_with('\\')) {
+            State::String { close: close.to_string(), escapes }
         }
-        let mut start = None;
-        let mut index = 0;
+        else {
+            State::Normal
+        };
+    }
 
-        let mut indices = Vec::new();
+    // a quoted string opened at `self.i`, with any prefix starting at `start`
+    fn quoted(&mut self, start: usize, prefix: &str) {
+        let language = self.language;
+        let raw = language.raw_strings && prefix.contains(['r', 'R']);
+        let hashes = if raw { self.rest().len() - self.rest().trim_start_matches('#').len() } else { 0 };
+        let rest = &self.rest()[hashes..];
+        let quote = rest.chars().next().unwrap();
 
-        for char in highlighted_line.get_content() {
-            if char.content == "\"" {
-                if start.is_none() {
-                    start = Some(index);
+        let (open, close, escapes, multiline) = if raw {
+            (hashes + 1, format!("\"{}", "#".repeat(hashes)), false, true)
+        }
+        else if language.triple_quotes && rest.starts_with(&quote.to_string().repeat(3)) {
+            (3, quote.to_string().repeat(3), true, true)
+        }
+        else {
+            (1, quote.to_string(), !(language.variables && quote == '\''), language.multiline_quotes.contains(&quote))
+        };
+
+        self.i += open;
+        let tokens = self.tokens.len();
+        self.string(&close, escapes, multiline);
+        match self.tokens.get_mut(tokens) {
+            Some(token) => token.0 = start,
+            None => self.tokens.push((start, self.i, Token::String)),
+        }
+        // `"key": value` in json
+        if language.keys && self.state == State::Normal && self.next_char(self.i) == Some(':') {
+            self.tokens[tokens].2 = Token::Property;
+        }
+    }
+
+    // `<<EOF`, `<<-EOF` and `<<'EOF'`, the document starts on the next line
+    fn heredoc(&mut self) -> bool {
+        let rest = self.rest();
+        let Some(word) = rest.strip_prefix("<<").filter(|word| !word.starts_with('<')) else { return false };
+        let indented = word.starts_with('-');
+        let word = word.trim_start_matches('-').trim_start();
+        let quote = word.starts_with(['\'', '"']);
+        let end: String = word.trim_start_matches(['\'', '"']).chars().take_while(|&c| is_identifier(c)).collect();
+        if end.is_empty() {
+            return false;
+        }
+
+        let operator = self.i + rest.len() - word.len();
+        self.push(operator, Token::Operator);
+        let word_end = operator + end.len() + if quote { 2 } else { 0 };
+        self.push(word_end.min(self.line.len()), Token::String);
+        self.heredoc = Some(State::Heredoc { end, indented });
+        true
+    }
+
+    fn code(mut self) -> (Vec<(usize, usize, Token)>, State) {
+        let language = self.language;
+        let directive = language.name == "c" && self.line.trim_start().starts_with('#');
+        let table = language.name == "toml" && self.line.trim_start().starts_with('[');
+        self.resume();
+
+        while self.i < self.line.len() {
+            let rest = self.rest();
+            let c = rest.chars().next().unwrap();
+            let previous = self.line[..self.i].chars().next_back();
+
+            if language.line_comments.iter().any(|comment| rest.starts_with(comment)) && !(language.variables && previous.is_some_and(|c| !c.is_whitespace())) {
+                self.push(self.line.len(), Token::Comment);
+            }
+            else if let Some((open, _)) = language.block_comment.filter(|(open, _)| rest.starts_with(open)) {
+                self.comment(self.i + open.len(), 1);
+            }
+            else if table && self.line[..self.i].trim().is_empty() && c == '[' {
+                let end = self.line.rfind(']').map(|end| end + 1).filter(|&end| end > self.i).unwrap_or(self.line.len());
+                self.push(end, Token::Type);
+            }
+            else if directive && c == '#' {
+                let end = self.run(self.run(self.i + 1, char::is_whitespace), is_identifier);
+                self.push(end, Token::Keyword);
+                if self.rest().trim_start().starts_with('<') {
+                    let start = self.run(self.i, char::is_whitespace);
+                    self.i = start;
+                    let end = self.line[start..].find('>').map(|end| start + end + 1).unwrap_or(self.line.len());
+                    self.push(end, Token::String);
+                }
+            }
+            else if c == '\'' && language.name == "rust" && !(rest.starts_with("'\\") || rest.chars().nth(2) == Some('\'')) {
+                // a lifetime rather than a char
+                let end = self.run(self.i + 1, is_identifier);
+                self.push(end, Token::Type);
+            }
+            else if language.quotes.contains(&c) {
+                self.quoted(self.i, "");
+            }
+            else if language.heredocs && self.heredoc() {
+                continue;
+            }
+            else if c == '$' && language.variables {
+                let end = if rest.starts_with("${") {
+                    rest.find('}').map(|end| self.i + end + 1).unwrap_or(self.line.len())
+                }
+                else {
+                    let end = self.run(self.i + 1, is_identifier);
+                    if end == self.i + 1 { self.i + 1 + rest[1..].chars().next().map(char::len_utf8).unwrap_or(0) } else { end }
+                };
+                self.push(end, Token::Variable);
+            }
+            else if c == '@' && rest[1..].starts_with(|c: char| c.is_alphabetic()) {
+                let end = self.run(self.i + 1, |c| is_identifier(c) || c == '.');
+                self.push(end, Token::Function);
+            }
+            else if c.is_ascii_digit() {
+                let mut end = self.i;
+                while let Some(c) = self.line[end..].chars().next() {
+                    let decimal = c == '.' && self.line[end + 1..].starts_with(|c: char| c.is_ascii_digit());
+                    if !(is_identifier(c) || decimal) {
+                        break;
+                    }
+                    end += c.len_utf8();
+                }
+                self.push(end, Token::Number);
+            }
+            else if is_identifier(c) || (c == '$' && language.quotes.contains(&'`')) {
+                let end = self.run(self.i, |c| is_identifier(c) || (c == '$' && language.quotes.contains(&'`')));
+                let word = &self.line[self.i..end];
+                let next = self.next_char(end);
+
+                // string prefixes like r"", b'', f"" and r#""#
+                let quote = self.line[end..].trim_start_matches(|c| c == '#' && language.raw_strings && word.contains(['r', 'R']));
+                if word.len() <= 2 && word.chars().all(|c| "rbfuRBFU".contains(c)) && quote.starts_with(|c| language.quotes.contains(&c)) {
+                    let start = self.i;
+                    self.i = end;
+                    self.quoted(start, word);
+                    continue;
+                }
+
+                let token = if language.keys && (next == Some('=') || next == Some(':')) && !self.line[end..].trim_start().starts_with("==") {
+                    Token::Property
+                }
+                else if language.keywords.contains(&word) {
+                    Token::Keyword
+                }
+                else if language.types.contains(&word) {
+                    Token::Type
+                }
+                else if language.constants.contains(&word) {
+                    Token::Constant
+                }
+                else if next == Some('(') || (language.name == "rust" && self.line[end..].starts_with('!') && !self.line[end..].starts_with("!=")) {
+                    Token::Function
+                }
+                else if language.capitalised_types && word.starts_with(char::is_uppercase) {
+                    if word.len() > 1 && word.chars().all(|c| !c.is_lowercase()) { Token::Constant } else { Token::Type }
                 }
                 else {
-                    indices.push((start.unwrap(), index+1));
-                    start = None;
+                    Token::Plain
+                };
+                self.push(end, token);
+            }
+            else if OPERATORS.contains(c) {
+                let end = self.run(self.i, |c| OPERATORS.contains(c));
+                self.push(end, Token::Operator);
+            }
+            else if PUNCTUATION.contains(c) {
+                self.push(self.i + 1, Token::Punctuation);
+            }
+            else {
+                self.i += c.len_utf8();
+            }
+        }
+
+        let state = match self.heredoc {
+            Some(heredoc) if self.state == State::Normal => heredoc,
+            _ => self.state,
+        };
+        (self.tokens, state)
+    }
+
+    fn markdown(mut self) -> (Vec<(usize, usize, Token)>, State) {
+        let trimmed = self.line.trim_start();
+        let indent = self.line.len() - trimmed.len();
+
+        // everything between the fences of a code block is left as it is
+        if let State::Fence(fence) = &self.state {
+            if trimmed.starts_with(fence.as_str()) {
+                self.state = State::Normal;
+                self.push(self.line.len(), Token::Comment);
+            }
+            else {
+                self.push(self.line.len(), Token::String);
+            }
+            return (self.tokens, self.state);
+        }
+
+        if trimmed.starts_with('#') && trimmed.trim_start_matches('#').starts_with(' ') {
+            self.push(self.line.len(), Token::Heading);
+            return (self.tokens, self.state);
+        }
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            let fence = trimmed.chars().take_while(|&c| c == '`' || c == '~').collect();
+            self.push(self.line.len(), Token::Comment);
+            return (self.tokens, State::Fence(fence));
+        }
+        if trimmed.starts_with('>') {
+            self.push(self.line.len(), Token::Comment);
+            return (self.tokens, self.state);
+        }
+        if trimmed.len() >= 3 && (trimmed.chars().all(|c| c == '-') || trimmed.chars().all(|c| c == '*')) {
+            self.push(self.line.len(), Token::Operator);
+            return (self.tokens, self.state);
+        }
+
+        // list markers
+        let digits = self.run(indent, |c| c.is_ascii_digit());
+        if ["- ", "* ", "+ "].iter().any(|marker| trimmed.starts_with(marker)) {
+            self.i = indent;
+            self.push(indent + 1, Token::Operator);
+        }
+        else if digits > indent && self.line[digits..].starts_with(". ") {
+            self.i = indent;
+            self.push(digits + 1, Token::Operator);
+        }
+
+        while self.i < self.line.len() {
+            let rest = self.rest();
+            let c = rest.chars().next().unwrap();
+
+            if c == '`' {
+                let end = rest[1..].find('`').map(|end| self.i + end + 2).unwrap_or(self.line.len());
+                self.push(end, Token::String);
+            }
+            else if rest.starts_with("**") || rest.starts_with("__") {
+                let end = rest[2..].find(&rest[..2]).map(|end| self.i + end + 4).unwrap_or(self.i + 2);
+                self.push(end, Token::Emphasis);
+            }
+            else if (c == '*' || c == '_') && rest[1..].starts_with(|c: char| !c.is_whitespace()) && self.line[..self.i].chars().next_back().is_none_or(|c| !is_identifier(c)) {
+                let end = rest[1..].find(c).map(|end| self.i + end + 2).unwrap_or(self.i + 1);
+                self.push(end, Token::Emphasis);
+            }
+            else if c == '[' {
+                match rest.find("](").and_then(|text| rest[text..].find(')').map(|url| (self.i + text + 1, self.i + text + url + 1))) {
+                    Some((text, url)) => {
+                        self.push(text, Token::Link);
+                        self.push(url, Token::String);
+                    },
+                    None => self.i += 1,
                 }
             }
-            index += 1;
+            else if c == '<' && (rest.starts_with("<http://") || rest.starts_with("<https://")) {
+                let end = rest.find('>').map(|end| self.i + end + 1).unwrap_or(self.line.len());
+                self.push(end, Token::Link);
+            }
+            else {
+                self.i += c.len_utf8();
+            }
         }
+        (self.tokens, self.state)
+    }
+}
+
+// textmate scopes and the token they're drawn as, the first one that prefixes a scope wins
+const SCOPES: &[(&str, Token)] = &[
+    ("comment", Token::Comment),
+    ("punctuation.definition.comment", Token::Comment),
+    ("string", Token::String),
+    ("punctuation.definition.string", Token::String),
+    ("constant.numeric", Token::Number),
+    ("constant", Token::Constant),
+    ("keyword.operator", Token::Operator),
+    ("keyword", Token::Keyword),
+    ("storage.type", Token::Type),
+    ("storage", Token::Keyword),
+    ("entity.name.function", Token::Function),
+    ("support.function", Token::Function),
+    ("variable.function", Token::Function),
+    ("support.type.property-name", Token::Property),
+    ("entity.other.attribute-name", Token::Property),
+    ("variable.other.member", Token::Property),
+    ("meta.mapping.key", Token::Property),
+    ("entity.name", Token::Type),
+    ("entity.other.inherited-class", Token::Type),
+    ("support.type", Token::Type),
+    ("support.class", Token::Type),
+    ("variable", Token::Variable),
+    ("punctuation", Token::Punctuation),
+    ("markup.heading", Token::Heading),
+    ("markup.bold", Token::Emphasis),
+    ("markup.italic", Token::Emphasis),
+    ("markup.underline.link", Token::Link),
+    ("markup.raw", Token::String),
+    ("markup.quote", Token::Comment),
+];
+
+// the innermost scope on the stack that maps to a token
+fn scope_token(stack: &ScopeStack) -> Token {
+    stack.as_slice().iter().rev().find_map(|scope| {
+        let scope = scope.build_string();
+        SCOPES.iter().find(|(prefix, _)| scope == *prefix || scope.strip_prefix(prefix).is_some_and(|rest| rest.starts_with('.'))).map(|&(_, token)| token)
+    }).unwrap_or(Token::Plain)
+}
+
+// runs a loaded grammar over one line
+fn parse(grammar: &'static SyntaxReference, line: &str, state: State) -> (Vec<(usize, usize, Token)>, State) {
+    let (mut parser, mut stack) = match state {
+        State::Grammar(state) => *state,
+        _ => (ParseState::new(grammar), ScopeStack::new()),
+    };
+    // grammars are written expecting the newline at the end of each line
+    let Ok(operations) = parser.parse_line(&format!("{}\n", line), &Syntaxes::get().set) else { return (vec![], State::Normal) };
 
-        for (start, end) in indices {
-            highlighted_line.set_colour(Info::new(Color::AnsiValue(229), Color::Reset, vec![]), start, end)
+    let mut tokens = vec![];
+    let mut start = 0;
+    for (i, operation) in operations {
+        let i = i.min(line.len());
+        if i > start {
+            tokens.push((start, i, scope_token(&stack)));
         }
+        if stack.apply(&operation).is_err() {
+            return (tokens, State::Normal);
+        }
+        start = start.max(i);
+    }
+    if line.len() > start {
+        tokens.push((start, line.len(), scope_token(&stack)));
+    }
+    (tokens, State::Grammar(Box::new((parser, stack))))
+}
+
+pub struct SyntaxHighlighter {
+    pub syntax: Option<Syntax>,
+    // the state every line ends in, known for the first `states.len()` lines
+    states: Vec<State>,
+}
+
+impl SyntaxHighlighter {
+    pub fn new(syntax: Option<Syntax>) -> Self {
+        Self {
+            syntax,
+            states: Vec::new(),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.states.clear();
+    }
+
+    fn lex(&self, line: &str, state: State) -> (Vec<(usize, usize, Token)>, State) {
+        match self.syntax {
+            Some(Syntax::Language(language)) if language.grammar == Grammar::Markdown => Lexer::new(language, line, state).markdown(),
+            Some(Syntax::Language(language)) => Lexer::new(language, line, state).code(),
+            Some(Syntax::Grammar(grammar)) => parse(grammar, line, state),
+            None => (vec![], State::Normal),
+        }
+    }
+
+    // the state line `y` starts in, lexing any lines before it that haven't been yet
+    fn start_state(&mut self, buffer: &Buffer, y: usize) -> State {
+        while self.states.len() < y {
+            let row = self.states.len();
+            let (_, state) = self.lex(&buffer.line(row), self.end_state(row));
+            self.states.push(state);
+        }
+        self.end_state(y)
+    }
+
+    fn end_state(&self, y: usize) -> State {
+        y.checked_sub(1).map(|y| self.states[y].clone()).unwrap_or_default()
+    }
 
+    // rows `first..=first + removed` were replaced by `first..=first + inserted`. lexes again from `first` until a
+    // line ends in the same state it did before, and returns the last row whose highlighting may have changed
+    pub fn edit(&mut self, buffer: &Buffer, first: usize, removed: usize, inserted: usize) -> usize {
+        if first + removed >= self.states.len() {
+            self.states.truncate(first);
+            return first + inserted;
+        }
+
+        self.states.splice(first..first + removed, std::iter::repeat_n(State::Normal, inserted));
+        self.states.truncate(buffer.len_lines());
+        let mut y = first;
+        while y < self.states.len() {
+            let (_, state) = self.lex(&buffer.line(y), self.end_state(y));
+            let converged = y >= first + inserted && state == self.states[y];
+            self.states[y] = state;
+            if converged {
+                return y;
+            }
+            y += 1;
+        }
+        y
+    }
+
+    pub fn highlight(&mut self, buffer: &Buffer, y: usize, theme: &Theme) -> ColourString {
+        let line = buffer.line(y);
+        let state = self.start_state(buffer, y);
+        let (tokens, state) = self.lex(&line, state);
+        if self.states.len() == y {
+            self.states.push(state);
+        }
+
+        let mut highlighted_line = ColourString::new(String::new(), None);
+        let mut tokens = tokens.iter().peekable();
+        for (start, grapheme) in line.grapheme_indices(true) {
+            while tokens.peek().is_some_and(|&&(_, end, _)| end <= start) {
+                tokens.next();
+            }
+            let token = match tokens.peek() {
+                Some(&&(token_start, _, token)) if token_start <= start => token,
+                _ => Token::Plain,
+            };
+            highlighted_line.push_str(grapheme, Some(theme.token(token)));
+        }
         highlighted_line
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Token::{Comment, Constant, Emphasis, Function, Heading, Keyword, Link, Number, Operator, Property, Punctuation, String as Str, Type, Variable};
+
+    // the token each line starts with, using the states the highlighter kept from before
+    fn first_tokens(highlighter: &mut SyntaxHighlighter, buffer: &Buffer) -> Vec<Token> {
+        (0..buffer.len_lines()).map(|y| {
+            let state = highlighter.start_state(buffer, y);
+            let (tokens, state) = highlighter.lex(&buffer.line(y), state);
+            if highlighter.states.len() == y {
+                highlighter.states.push(state);
+            }
+            tokens.first().map_or(Token::Plain, |&(_, _, token)| token)
+        }).collect()
+    }
+
+    // everything on the lines that isn't plain text, with the state carried from one line to the next
+    fn lexed(name: &str, text: &str) -> Vec<(String, Token)> {
+        let language = crate::language::LANGUAGES.iter().find(|language| language.name == name).unwrap();
+        let highlighter = SyntaxHighlighter::new(Some(Syntax::Language(language)));
+        let mut state = State::Normal;
+        let mut lexed = vec![];
+        for line in text.lines() {
+            let (tokens, next) = highlighter.lex(line, state);
+            lexed.extend(tokens.into_iter().filter(|&(_, _, token)| token != Token::Plain).map(|(start, end, token)| (line[start..end].to_string(), token)));
+            state = next;
+        }
+        lexed
+    }
+
+    fn expected(tokens: &[(&str, Token)]) -> Vec<(String, Token)> {
+        tokens.iter().map(|&(text, token)| (text.to_string(), token)).collect()
+    }
+
+    #[test]
+    fn rust() {
+        assert_eq!(lexed("rust", r#"let s = "a\"b"; // done"#), expected(&[
+            ("let", Keyword), ("=", Operator), (r#""a\"b""#, Str), (";", Punctuation), ("// done", Comment),
+        ]));
+        assert_eq!(lexed("rust", "fn f() -> u8 { 0x1f + 1.5 }"), expected(&[
+            ("fn", Keyword), ("f", Function), ("(", Punctuation), (")", Punctuation), ("->", Operator), ("u8", Type), ("{", Punctuation),
+            ("0x1f", Number), ("+", Operator), ("1.5", Number), ("}", Punctuation),
+        ]));
+        assert_eq!(lexed("rust", r##"&'a str = r#"no \ "escape"#; 'x' Vec None println!"##), expected(&[
+            ("&", Operator), ("'a", Type), ("str", Type), ("=", Operator), (r##"r#"no \ "escape"#"##, Str), (";", Punctuation),
+            ("'x'", Str), ("Vec", Type), ("None", Constant), ("println", Function), ("!", Operator),
+        ]));
+    }
 
-}
\ No newline at end of file
+    #[test]
+    fn python() {
+        assert_eq!(lexed("python", "def f(x=None): return \"\"\"doc\nmore\"\"\" # c"), expected(&[
+            ("def", Keyword), ("f", Function), ("(", Punctuation), ("=", Operator), ("None", Constant), (")", Punctuation), (":", Operator),
+            ("return", Keyword), ("\"\"\"doc", Str), ("more\"\"\"", Str), ("# c", Comment),
+        ]));
+        assert_eq!(lexed("python", "f'{x}' + b\"y\""), expected(&[("f'{x}'", Str), ("+", Operator), ("b\"y\"", Str)]));
+    }
+
+    #[test]
+    fn javascript_and_typescript() {
+        assert_eq!(lexed("javascript", "const t = `a\n${x}`; // c"), expected(&[
+            ("const", Keyword), ("=", Operator), ("`a", Str), ("${x}`", Str), (";", Punctuation), ("// c", Comment),
+        ]));
+        assert_eq!(lexed("typescript", "let n: number = 42; /* c */"), expected(&[
+            ("let", Keyword), (":", Operator), ("number", Type), ("=", Operator), ("42", Number), (";", Punctuation), ("/* c */", Comment),
+        ]));
+    }
+
+    #[test]
+    fn c() {
+        assert_eq!(lexed("c", "#include <stdio.h>\nint x = 'a'; /* c */"), expected(&[
+            ("#include", Keyword), ("<stdio.h>", Str), ("int", Type), ("=", Operator), ("'a'", Str), (";", Punctuation), ("/* c */", Comment),
+        ]));
+    }
+
+    #[test]
+    fn toml_and_json() {
+        assert_eq!(lexed("toml", "[package]\nname = \"vcte\" # c\n[[bin]]\nok = true\nv = 1.5"), expected(&[
+            ("[package]", Type), ("name", Property), ("=", Operator), ("\"vcte\"", Str), ("# c", Comment), ("[[bin]]", Type),
+            ("ok", Property), ("=", Operator), ("true", Constant), ("v", Property), ("=", Operator), ("1.5", Number),
+        ]));
+        assert_eq!(lexed("json", r#"{"a": [1, true, null, "b"]}"#), expected(&[
+            ("{", Punctuation), (r#""a""#, Property), (":", Operator), ("[", Punctuation), ("1", Number), (",", Punctuation), ("true", Constant),
+            (",", Punctuation), ("null", Constant), (",", Punctuation), (r#""b""#, Str), ("]", Punctuation), ("}", Punctuation),
+        ]));
+    }
+
+    #[test]
+    fn shell() {
+        assert_eq!(lexed("shell", "if [ -f \"$HOME\" ]; then echo $1 ${x}; fi # c"), expected(&[
+            ("if", Keyword), ("[", Punctuation), ("-", Operator), ("\"$HOME\"", Str), ("]", Punctuation), (";", Punctuation), ("then", Keyword),
+            ("$1", Variable), ("${x}", Variable), (";", Punctuation), ("fi", Keyword), ("# c", Comment),
+        ]));
+        assert_eq!(lexed("shell", "cat <<EOF\n# not a comment\nEOF\necho a#b"), expected(&[
+            ("<<", Operator), ("EOF", Str), ("# not a comment", Str), ("EOF", Keyword),
+        ]));
+    }
+
+    #[test]
+    fn markdown() {
+        let text = "# Title\n```rust\nlet x = 1; # no\n```\n- **bold** and `code` [link](url)\n> quote";
+        assert_eq!(lexed("markdown", text), expected(&[
+            ("# Title", Heading), ("```rust", Comment), ("let x = 1; # no", Str), ("```", Comment), ("-", Operator), ("**bold**", Emphasis),
+            ("`code`", Str), ("[link]", Link), ("(url)", Str), ("> quote", Comment),
+        ]));
+    }
+
+    #[test]
+    fn opening_and_closing_a_block_comment_relexes_the_lines_below() {
+        let mut buffer = Buffer::new("fn a() {}\nfn b() {}\nfn c() {}\nfn d() {}\n");
+        let mut highlighter = SyntaxHighlighter::new(Syntax::find("rust"));
+        assert_eq!(first_tokens(&mut highlighter, &buffer), vec![Token::Keyword; 4]);
+
+        // never back to the state it was in, so down to the end of the file
+        buffer.insert((0, 0), "/* ");
+        assert_eq!(highlighter.edit(&buffer, 0, 0, 0), 4);
+        assert_eq!(first_tokens(&mut highlighter, &buffer), vec![Token::Comment; 4]);
+
+        // closed again on the second line, the lines after it go back to how they were
+        buffer.insert((9, 1), " */");
+        assert_eq!(highlighter.edit(&buffer, 1, 0, 0), 4);
+        assert_eq!(first_tokens(&mut highlighter, &buffer), vec![Token::Comment, Token::Comment, Token::Keyword, Token::Keyword]);
+    }
+
+    #[test]
+    fn an_edit_that_leaves_the_state_alone_stops_at_its_line() {
+        let mut buffer = Buffer::new("let a = 1;\n/* one\ntwo */\nlet b = 2;\n");
+        let mut highlighter = SyntaxHighlighter::new(Syntax::find("rust"));
+        first_tokens(&mut highlighter, &buffer);
+
+        buffer.insert((0, 0), "x");
+        assert_eq!(highlighter.edit(&buffer, 0, 0, 0), 0);
+
+        // a new line in the middle of the comment ends in the state the line it was split from did
+        buffer.insert((6, 1), "\nstill");
+        assert_eq!(highlighter.edit(&buffer, 1, 0, 1), 2);
+        assert_eq!(first_tokens(&mut highlighter, &buffer), vec![Token::Plain, Token::Comment, Token::Comment, Token::Comment, Token::Keyword]);
+    }
+
+    #[test]
+    fn nested_comments_close_one_level_at_a_time() {
+        let buffer = Buffer::new("/* /* */\nstill\n*/ fn\n");
+        let mut highlighter = SyntaxHighlighter::new(Syntax::find("rust"));
+        assert_eq!(first_tokens(&mut highlighter, &buffer), vec![Token::Comment, Token::Comment, Token::Comment]);
+        assert_eq!(highlighter.states, vec![State::Comment(1), State::Comment(1), State::Normal]);
+    }
+}
@@ -0,0 +1,395 @@
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use plist::{Dictionary, Value};
+use syntect::parsing::{SyntaxDefinition, SyntaxReference, SyntaxSet, SyntaxSetBuilder};
+
+use crate::language::{Language, LANGUAGES};
+
+const BUNDLED: [&str; 4] = [
+    include_str!("../syntaxes/protobuf.sublime-syntax"),
+    include_str!("../syntaxes/terraform.sublime-syntax"),
+    include_str!("../syntaxes/dockerfile.sublime-syntax"),
+    include_str!("../syntaxes/ini.sublime-syntax"),
+];
+
+// grammars that are bundled or loaded from ~/.config/vcte/syntaxes, the bundled ones come first so user ones win
+pub struct Syntaxes {
+    pub set: SyntaxSet,
+    // grammar files that couldn't be loaded and why
+    pub errors: Vec<String>,
+}
+
+static SYNTAXES: OnceLock<Syntaxes> = OnceLock::new();
+
+impl Syntaxes {
+    pub fn get() -> &'static Syntaxes {
+        SYNTAXES.get_or_init(|| Self::load(crate::config_path("syntaxes")))
+    }
+
+    // the bundled grammars and the ones in `directory`
+    fn load(directory: Option<PathBuf>) -> Self {
+        let mut builder = SyntaxSetBuilder::new();
+        for grammar in BUNDLED {
+            builder.add(SyntaxDefinition::load_from_str(grammar, true, None).expect("bundled grammars are valid"));
+        }
+
+        let mut errors = vec![];
+        if let Some(directory) = directory {
+            let mut paths: Vec<_> = fs::read_dir(directory).into_iter().flatten().flatten().map(|entry| entry.path()).collect();
+            paths.sort();
+            for path in paths {
+                match load_grammar(&path) {
+                    Some(Ok(grammar)) => builder.add(grammar),
+                    Some(Err(e)) => errors.push(format!("{}: {}", path.file_name().unwrap_or_default().to_string_lossy(), e)),
+                    None => {},
+                }
+            }
+        }
+
+        Self {
+            set: builder.build(),
+            errors,
+        }
+    }
+
+    // one line listing every syntax by where it came from, and any grammar that failed to load
+    pub fn summary(&self) -> String {
+        let names = |grammars: &[SyntaxReference]| grammars.iter().map(|grammar| grammar.name.as_str()).collect::<Vec<&str>>().join(", ");
+        let (bundled, user) = self.set.syntaxes().split_at(BUNDLED.len());
+        let mut summary = format!(
+            "built in: {} | bundled: {} | user: {}",
+            LANGUAGES.iter().map(|language| language.name).collect::<Vec<&str>>().join(", "),
+            names(bundled),
+            if user.is_empty() { String::from("none") } else { names(user) },
+        );
+        if !self.errors.is_empty() {
+            summary.push_str(&format!(" | failed: {}", self.errors.join(", ")));
+        }
+        summary
+    }
+}
+
+// None for files that aren't grammars
+fn load_grammar(path: &Path) -> Option<Result<SyntaxDefinition, String>> {
+    let name = path.file_stem().and_then(|name| name.to_str());
+    let text = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("sublime-syntax") => fs::read_to_string(path).map_err(|e| e.to_string()),
+        Some("tmLanguage") => fs::read(path).map_err(|e| e.to_string()).and_then(|bytes| tm_language(&bytes)),
+        _ => return None,
+    };
+    Some(text.and_then(|text| SyntaxDefinition::load_from_str(&text, true, name).map_err(|e| e.to_string())))
+}
+
+// syntect only reads .sublime-syntax, so a .tmLanguage plist is rewritten as one. it's written as json, which is also yaml
+fn tm_language(bytes: &[u8]) -> Result<String, String> {
+    let value = Value::from_reader(Cursor::new(bytes)).map_err(|e| e.to_string())?;
+    let grammar = value.as_dictionary().ok_or("Not a grammar")?;
+    let string = |key: &str| grammar.get(key).and_then(Value::as_string);
+
+    let mut syntax = Dictionary::new();
+    syntax.insert(String::from("name"), Value::from(string("name").unwrap_or("Unnamed")));
+    syntax.insert(String::from("scope"), Value::from(string("scopeName").ok_or("Missing scopeName")?));
+    if let Some(file_types) = grammar.get("fileTypes") {
+        syntax.insert(String::from("file_extensions"), file_types.clone());
+    }
+    if let Some(first_line) = string("firstLineMatch") {
+        syntax.insert(String::from("first_line_match"), Value::from(first_line));
+    }
+
+    let mut contexts = Dictionary::new();
+    contexts.insert(String::from("main"), Value::Array(patterns(grammar)));
+    for (name, rule) in grammar.get("repository").and_then(Value::as_dictionary).into_iter().flatten() {
+        let rule = rule.as_dictionary().ok_or(format!("Invalid rule: {}", name))?;
+        let is_group = ["match", "begin", "include"].iter().all(|key| !rule.contains_key(key));
+        let context = if is_group { patterns(rule) } else { pattern(rule) };
+        contexts.insert(name.clone(), Value::Array(context));
+    }
+    syntax.insert(String::from("contexts"), Value::Dictionary(contexts));
+    Ok(json(&Value::Dictionary(syntax)))
+}
+
+// the rules in a "patterns" list
+fn patterns(rule: &Dictionary) -> Vec<Value> {
+    rule.get("patterns").and_then(Value::as_array).into_iter().flatten().filter_map(Value::as_dictionary).flat_map(pattern).collect()
+}
+
+// one textmate rule as sublime-syntax rules
+fn pattern(rule: &Dictionary) -> Vec<Value> {
+    let string = |key: &str| rule.get(key).and_then(Value::as_string);
+    let captures = |key: &str| {
+        let captures = rule.get(key).or(rule.get("captures")).and_then(Value::as_dictionary)?;
+        let mut scopes = Dictionary::new();
+        for (group, capture) in captures {
+            if let Some(name) = capture.as_dictionary().and_then(|capture| capture.get("name")).and_then(Value::as_string) {
+                scopes.insert(group.clone(), Value::from(name));
+            }
+        }
+        Some(Value::Dictionary(scopes))
+    };
+
+    if let Some(include) = string("include") {
+        let include = match include {
+            "$self" | "$base" => String::from("main"),
+            include if include.starts_with('#') => include[1..].to_string(),
+            include => format!("scope:{}", include),
+        };
+        return vec![Value::Dictionary(Dictionary::from_iter([(String::from("include"), Value::from(include))]))];
+    }
+
+    let mut result = Dictionary::new();
+    if let Some(regex) = string("match") {
+        result.insert(String::from("match"), Value::from(regex));
+        if let Some(name) = string("name") {
+            result.insert(String::from("scope"), Value::from(name));
+        }
+        if let Some(captures) = captures("captures") {
+            result.insert(String::from("captures"), captures);
+        }
+    }
+    else if let Some(begin) = string("begin") {
+        result.insert(String::from("match"), Value::from(begin));
+        if let Some(captures) = captures("beginCaptures") {
+            result.insert(String::from("captures"), captures);
+        }
+
+        // a while rule carries on for as long as each line starts with a match
+        let end = match (string("end"), string("while")) {
+            (Some(end), _) => end.to_string(),
+            (None, Some(condition)) => format!("^(?!{})", condition),
+            (None, None) => String::from("$^"),
+        };
+        let mut pop = Dictionary::new();
+        pop.insert(String::from("match"), Value::from(end));
+        if let Some(captures) = captures("endCaptures") {
+            pop.insert(String::from("captures"), captures);
+        }
+        pop.insert(String::from("pop"), Value::Boolean(true));
+
+        // the meta scope covers the begin and end matches as well
+        let mut context = vec![];
+        if let Some(name) = string("name") {
+            context.push(Value::Dictionary(Dictionary::from_iter([(String::from("meta_scope"), Value::from(name))])));
+        }
+        if let Some(name) = string("contentName") {
+            context.push(Value::Dictionary(Dictionary::from_iter([(String::from("meta_content_scope"), Value::from(name))])));
+        }
+        let end_last = rule.get("applyEndPatternLast").is_some_and(|last| last.as_boolean() == Some(true) || last.as_unsigned_integer() == Some(1));
+        if !end_last {
+            context.push(Value::Dictionary(pop.clone()));
+        }
+        context.extend(patterns(rule));
+        if end_last {
+            context.push(Value::Dictionary(pop));
+        }
+        result.insert(String::from("push"), Value::Array(context));
+    }
+    else {
+        return patterns(rule);
+    }
+    vec![Value::Dictionary(result)]
+}
+
+// capture groups have to stay numbers for the yaml loader to see them
+fn json(value: &Value) -> String {
+    match value {
+        Value::Dictionary(dictionary) => {
+            let entries: Vec<String> = dictionary.iter().map(|(key, value)| {
+                let key = if !key.is_empty() && key.chars().all(|c| c.is_ascii_digit()) { key.clone() } else { json(&Value::from(key.as_str())) };
+                format!("{}: {}", key, json(value))
+            }).collect();
+            format!("{{{}}}", entries.join(", "))
+        },
+        Value::Array(array) => format!("[{}]", array.iter().map(json).collect::<Vec<String>>().join(", ")),
+        Value::Boolean(boolean) => boolean.to_string(),
+        Value::Integer(integer) => integer.to_string(),
+        Value::String(string) => {
+            let mut escaped = String::from("\"");
+            for c in string.chars() {
+                match c {
+                    '"' => escaped.push_str("\\\""),
+                    '\\' => escaped.push_str("\\\\"),
+                    '\n' => escaped.push_str("\\n"),
+                    '\t' => escaped.push_str("\\t"),
+                    c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+                    c => escaped.push(c),
+                }
+            }
+            escaped.push('"');
+            escaped
+        },
+        _ => String::from("null"),
+    }
+}
+
+// what a file is highlighted with, one of the built in languages or a loaded grammar
+#[derive(Clone, Copy)]
+pub enum Syntax {
+    Language(&'static Language),
+    Grammar(&'static SyntaxReference),
+}
+
+impl Syntax {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Syntax::Language(language) => language.name,
+            Syntax::Grammar(grammar) => &grammar.name,
+        }
+    }
+
+    // grammars claiming the file name or extension win over the built in languages, which win over first line matches
+    pub fn detect(path: &str, first_line: &str) -> Option<Self> {
+        let set = &Syntaxes::get().set;
+        let path = Path::new(path);
+        let file_name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+        let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or_default();
+
+        set.find_syntax_by_extension(file_name)
+            .or_else(|| set.find_syntax_by_extension(extension).filter(|_| !extension.is_empty()))
+            .map(Syntax::Grammar)
+            .or_else(|| Language::detect(&path.to_string_lossy(), first_line).map(Syntax::Language))
+            .or_else(|| set.find_syntax_by_first_line(first_line).map(Syntax::Grammar))
+    }
+
+    // by name or extension, ignoring case
+    pub fn find(name: &str) -> Option<Self> {
+        let set = &Syntaxes::get().set;
+        set.syntaxes().iter().rev().find(|grammar| grammar.name.eq_ignore_ascii_case(name))
+            .map(Syntax::Grammar)
+            .or_else(|| LANGUAGES.iter().find(|language| language.name.eq_ignore_ascii_case(name)).map(Syntax::Language))
+            .or_else(|| set.find_syntax_by_extension(name).map(Syntax::Grammar))
+            .or_else(|| Language::detect(&format!("file.{}", name), "").map(Syntax::Language))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use syntect::parsing::{ParseState, ScopeStack};
+
+    use super::*;
+
+    const FIXTURE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>name</key>
+    <string>Fixture</string>
+    <key>scopeName</key>
+    <string>source.fixture</string>
+    <key>fileTypes</key>
+    <array>
+        <string>fixture</string>
+    </array>
+    <key>patterns</key>
+    <array>
+        <dict>
+            <key>match</key>
+            <string>\b(let)\s+(\w+)</string>
+            <key>captures</key>
+            <dict>
+                <key>1</key>
+                <dict><key>name</key><string>keyword.other.fixture</string></dict>
+                <key>2</key>
+                <dict><key>name</key><string>variable.other.fixture</string></dict>
+            </dict>
+        </dict>
+        <dict>
+            <key>include</key>
+            <string>#strings</string>
+        </dict>
+    </array>
+    <key>repository</key>
+    <dict>
+        <key>strings</key>
+        <dict>
+            <key>name</key>
+            <string>string.quoted.double.fixture</string>
+            <key>begin</key>
+            <string>"</string>
+            <key>end</key>
+            <string>"</string>
+            <key>patterns</key>
+            <array>
+                <dict>
+                    <key>match</key>
+                    <string>\\.</string>
+                    <key>name</key>
+                    <string>constant.character.escape.fixture</string>
+                </dict>
+            </array>
+        </dict>
+    </dict>
+</dict>
+</plist>
+"#;
+
+    // an empty directory of its own for each test
+    fn directory(name: &str) -> PathBuf {
+        let directory = env::temp_dir().join(format!("vcte-syntaxes-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    // the scopes each piece of the line is in, innermost last
+    fn scopes(set: &SyntaxSet, grammar: &SyntaxReference, line: &str) -> Vec<(String, String)> {
+        let mut parser = ParseState::new(grammar);
+        let mut stack = ScopeStack::new();
+        let operations = parser.parse_line(line, set).unwrap();
+        let mut scopes = vec![];
+        let mut start = 0;
+        for (i, operation) in operations.into_iter().chain([(line.len(), syntect::parsing::ScopeStackOp::Noop)]) {
+            if i > start {
+                let scope = stack.as_slice().last().map(|scope| scope.build_string()).unwrap_or_default();
+                scopes.push((line[start..i].to_string(), scope));
+                start = i;
+            }
+            stack.apply(&operation).unwrap();
+        }
+        scopes
+    }
+
+    #[test]
+    fn tm_language_is_converted() {
+        let directory = directory("tm_language");
+        fs::write(directory.join("fixture.tmLanguage"), FIXTURE).unwrap();
+        fs::write(directory.join("notes.txt"), "not a grammar").unwrap();
+        let syntaxes = Syntaxes::load(Some(directory));
+        assert!(syntaxes.errors.is_empty(), "{:?}", syntaxes.errors);
+
+        let grammar = syntaxes.set.find_syntax_by_extension("fixture").unwrap();
+        assert_eq!(grammar.name, "Fixture");
+        assert!(syntaxes.summary().contains("user: Fixture"));
+        let scopes = scopes(&syntaxes.set, grammar, r#"let x = "a\"b""#);
+        let scope = |text: &str| scopes.iter().find(|(found, _)| found == text).map(|(_, scope)| scope.as_str());
+        assert_eq!(scope("let"), Some("keyword.other.fixture"));
+        assert_eq!(scope("x"), Some("variable.other.fixture"));
+        assert_eq!(scope("\"a"), Some("string.quoted.double.fixture"));
+        assert_eq!(scope("\\\""), Some("constant.character.escape.fixture"));
+    }
+
+    #[test]
+    fn broken_grammars_are_listed() {
+        let directory = directory("broken");
+        fs::write(directory.join("broken.tmLanguage"), FIXTURE.replace("scopeName", "scope")).unwrap();
+        fs::write(directory.join("invalid.sublime-syntax"), "not: [yaml").unwrap();
+        let syntaxes = Syntaxes::load(Some(directory));
+        assert_eq!(syntaxes.errors.len(), 2);
+        assert!(syntaxes.errors[0].starts_with("broken.tmLanguage: Missing scopeName"));
+        assert!(syntaxes.summary().contains("| failed: broken.tmLanguage"));
+    }
+
+    #[test]
+    fn bundled_grammars_are_detected() {
+        assert_eq!(Syntax::detect("service.proto", "").map(|syntax| syntax.name()), Some("Protocol Buffers"));
+        assert_eq!(Syntax::detect("main.tf", "").map(|syntax| syntax.name()), Some("Terraform"));
+        assert_eq!(Syntax::detect("Dockerfile", "").map(|syntax| syntax.name()), Some("Dockerfile"));
+        // built in languages are still found, and a grammar can be asked for by name
+        assert_eq!(Syntax::detect("main.rs", "").map(|syntax| syntax.name()), Some("rust"));
+        assert!(matches!(Syntax::find("terraform"), Some(Syntax::Grammar(_))));
+    }
+}
@@ -1,6 +1,6 @@
-use crossterm::{style::Color, terminal};
+use crossterm::style::Color;
 
-use crate::{editor::File, colour_string::{ColourString, Info}};
+use crate::{editor::File, colour_string::{ColourString, Info}, theme::Theme};
 
 pub struct Tab;
 
@@ -9,21 +9,20 @@ impl Tab {
         Self {}
     }
 
-    pub fn render(&mut self, files: &Vec<File>, file_index: usize) -> Vec<ColourString> {
-        let terminal_x = terminal::size().unwrap().0;
+    pub fn render(&mut self, files: &[File], file_index: usize, theme: &Theme, width: u16) -> Vec<ColourString> {
         let mut tabs = Vec::new(); 
 
         for (i, file) in files.iter().enumerate() {
-            let tab = ColourString::new(format!(" {}{} ", file.name.clone(), if file.modified { " ●" } else { "" }), if i == file_index { Some(Info::new(Color::White, Color::DarkGrey, vec![])) } else { Some(Info::new(Color::White, Color::Reset, vec![])) });
+            let tab = ColourString::new(format!(" {}{} ", file.name.clone(), if file.modified { " ●" } else { "" }), if i == file_index { Some(Info::new(theme.text, theme.tab, vec![])) } else { Some(Info::new(theme.text, Color::Reset, vec![])) });
             tabs.push(tab);
         }
 
-        let mut frame = ColourString::join(tabs, ColourString::new("▕".to_string(), Some(Info::new(Color::Black, Color::Reset, vec![]))));
+        let mut frame = ColourString::join(tabs, ColourString::new("▕".to_string(), Some(Info::new(theme.tab_separator, Color::Reset, vec![]))));
 
-        frame.truncate(terminal_x as usize);
+        frame.truncate(width as usize);
 
-        if frame.get_content().len() < terminal_x as usize {
-            frame.push_str(" ".repeat((terminal_x as usize) - frame.get_content().len()).as_str(), None);
+        if frame.get_content().len() < width as usize {
+            frame.push_str(" ".repeat((width as usize) - frame.get_content().len()).as_str(), None);
         }
 
         vec![frame]
@@ -0,0 +1,303 @@
+use std::collections::HashMap;
+use std::fs;
+
+use crossterm::style::{Attribute, Color};
+use toml::{Table, Value};
+
+use crate::colour_string::Info;
+use crate::syntax_highlighter::Token;
+
+// dracula is the default and every other theme is laid over it
+const BUNDLED: [(&str, &str); 3] = [
+    ("dracula", include_str!("../themes/dracula.toml")),
+    ("gruvbox", include_str!("../themes/gruvbox.toml")),
+    ("basic", include_str!("../themes/basic.toml")),
+];
+
+// what the 16 terminal colours usually look like, to pick the closest one to an rgb colour
+const ANSI: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::DarkRed, (205, 0, 0)),
+    (Color::DarkGreen, (0, 205, 0)),
+    (Color::DarkYellow, (205, 205, 0)),
+    (Color::DarkBlue, (0, 0, 238)),
+    (Color::DarkMagenta, (205, 0, 205)),
+    (Color::DarkCyan, (0, 205, 205)),
+    (Color::Grey, (229, 229, 229)),
+    (Color::DarkGrey, (127, 127, 127)),
+    (Color::Red, (255, 0, 0)),
+    (Color::Green, (0, 255, 0)),
+    (Color::Yellow, (255, 255, 0)),
+    (Color::Blue, (92, 92, 255)),
+    (Color::Magenta, (255, 0, 255)),
+    (Color::Cyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+#[derive(Clone)]
+pub struct Theme {
+    pub name: String,
+    pub text: Color,
+    pub dimmed: Color,
+    pub shade: Color,
+    pub border: Color,
+    pub background: Color,
+    pub gutter: Color,
+    pub search: Color,
+    pub selection: Color,
+    pub cursor: Color,
+    pub tab: Color,
+    pub tab_separator: Color,
+    pub view_mode: Color,
+    pub insert_mode: Color,
+    pub visual_mode: Color,
+    pub error: Color,
+    pub warning: Color,
+    pub success: Color,
+    pub hex_null: Color,
+    pub hex_whitespace: Color,
+    pub hex_printable: Color,
+    pub hex_other: Color,
+    syntax: HashMap<Token, Info>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        let (name, text) = BUNDLED[0];
+        let blank = Self {
+            name: String::new(),
+            text: Color::Reset,
+            dimmed: Color::Reset,
+            shade: Color::Reset,
+            border: Color::Reset,
+            background: Color::Reset,
+            gutter: Color::Reset,
+            search: Color::Reset,
+            selection: Color::Reset,
+            cursor: Color::Reset,
+            tab: Color::Reset,
+            tab_separator: Color::Reset,
+            view_mode: Color::Reset,
+            insert_mode: Color::Reset,
+            visual_mode: Color::Reset,
+            error: Color::Reset,
+            warning: Color::Reset,
+            success: Color::Reset,
+            hex_null: Color::Reset,
+            hex_whitespace: Color::Reset,
+            hex_printable: Color::Reset,
+            hex_other: Color::Reset,
+            syntax: HashMap::new(),
+        };
+        Self::parse(name, text, &blank).expect("bundled themes are valid")
+    }
+}
+
+impl Theme {
+    // ~/.config/vcte/themes/<name>.toml, or one of the bundled themes
+    pub fn load(name: &str, truecolor: bool) -> Result<Self, String> {
+        let user = crate::config_path("themes").map(|directory| directory.join(format!("{}.toml", name))).filter(|path| path.is_file());
+        let text = match user {
+            Some(path) => fs::read_to_string(path).map_err(|e| e.to_string())?,
+            None => BUNDLED.iter().find(|(bundled, _)| *bundled == name).ok_or(format!("{}: Unknown theme", name))?.1.to_string(),
+        };
+        let base = Self::default();
+        Ok(Self::parse(name, &text, &base).map_err(|e| format!("{}: {}", name, e))?.for_terminal(truecolor))
+    }
+
+    // the bundled themes and any in ~/.config/vcte/themes
+    pub fn list() -> Vec<String> {
+        let mut names: Vec<String> = BUNDLED.iter().map(|(name, _)| name.to_string()).collect();
+        if let Some(directory) = crate::config_path("themes") {
+            let mut user: Vec<String> = fs::read_dir(directory).into_iter().flatten().flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|extension| extension == "toml"))
+                .filter_map(|path| path.file_stem().map(|name| name.to_string_lossy().to_string()))
+                .filter(|name| !names.contains(name))
+                .collect();
+            user.sort();
+            names.extend(user);
+        }
+        names
+    }
+
+    pub fn token(&self, token: Token) -> Info {
+        self.syntax.get(&token).or(self.syntax.get(&Token::Plain)).cloned().unwrap_or(Info::new(self.text, Color::Reset, vec![]))
+    }
+
+    // every ui colour by its key in the [ui] table
+    fn ui(&mut self) -> [(&'static str, &mut Color); 21] {
+        [
+            ("text", &mut self.text),
+            ("dimmed", &mut self.dimmed),
+            ("shade", &mut self.shade),
+            ("border", &mut self.border),
+            ("background", &mut self.background),
+            ("gutter", &mut self.gutter),
+            ("search", &mut self.search),
+            ("selection", &mut self.selection),
+            ("cursor", &mut self.cursor),
+            ("tab", &mut self.tab),
+            ("tab_separator", &mut self.tab_separator),
+            ("view_mode", &mut self.view_mode),
+            ("insert_mode", &mut self.insert_mode),
+            ("visual_mode", &mut self.visual_mode),
+            ("error", &mut self.error),
+            ("warning", &mut self.warning),
+            ("success", &mut self.success),
+            ("hex_null", &mut self.hex_null),
+            ("hex_whitespace", &mut self.hex_whitespace),
+            ("hex_printable", &mut self.hex_printable),
+            ("hex_other", &mut self.hex_other),
+        ]
+    }
+
+    // a theme file only has to set the colours it changes, the rest come from `base`
+    fn parse(name: &str, text: &str, base: &Self) -> Result<Self, String> {
+        let table: Table = text.parse().map_err(|e: toml::de::Error| e.message().trim().replace('\n', ", "))?;
+        let mut theme = base.clone();
+        theme.name = name.to_string();
+
+        for (section, values) in &table {
+            let values = values.as_table().ok_or(format!("{} is not a table", section))?;
+            match section.as_str() {
+                "ui" => for (key, value) in values {
+                    let (_, colour) = theme.ui().into_iter().find(|(name, _)| name == key).ok_or(format!("Unknown ui colour {}", key))?;
+                    *colour = parse_colour(value).map_err(|e| format!("{}: {}", key, e))?;
+                },
+                "syntax" => for (key, value) in values {
+                    let token = Token::ALL.into_iter().find(|token| token.name() == key).ok_or(format!("Unknown token {}", key))?;
+                    theme.syntax.insert(token, parse_style(value).map_err(|e| format!("{}: {}", key, e))?);
+                },
+                _ => return Err(format!("Unknown table {}", section)),
+            }
+        }
+        Ok(theme)
+    }
+
+    // terminals without truecolor get the closest of the 16 terminal colours instead
+    pub fn for_terminal(mut self, truecolor: bool) -> Self {
+        if truecolor {
+            return self;
+        }
+        for (_, colour) in self.ui() {
+            *colour = nearest_ansi(*colour);
+        }
+        for info in self.syntax.values_mut() {
+            info.foreground = nearest_ansi(info.foreground);
+        }
+        self
+    }
+}
+
+fn nearest_ansi(colour: Color) -> Color {
+    let Color::Rgb { r, g, b } = colour else { return colour };
+    let distance = |(red, green, blue): (u8, u8, u8)| {
+        (r as i32 - red as i32).pow(2) + (g as i32 - green as i32).pow(2) + (b as i32 - blue as i32).pow(2)
+    };
+    ANSI.iter().min_by_key(|(_, rgb)| distance(*rgb)).map(|(ansi, _)| *ansi).unwrap_or(colour)
+}
+
+// "#rrggbb", a terminal colour name or "reset"
+fn parse_colour(value: &Value) -> Result<Color, String> {
+    let name = value.as_str().ok_or("Colours are written as strings")?;
+    if let Some(hex) = name.strip_prefix('#') {
+        let channel = |i: usize| hex.get(i..i + 2).and_then(|channel| u8::from_str_radix(channel, 16).ok());
+        return match (hex.len(), channel(0), channel(2), channel(4)) {
+            (6, Some(r), Some(g), Some(b)) => Ok(Color::Rgb { r, g, b }),
+            _ => Err(format!("{}: Invalid colour", name)),
+        };
+    }
+    match name {
+        "reset" | "default" => Ok(Color::Reset),
+        _ => Color::try_from(name).map_err(|_| format!("{}: Unknown colour", name)),
+    }
+}
+
+// a colour, or a table like `{ foreground = "#6272a4", italic = true }`
+fn parse_style(value: &Value) -> Result<Info, String> {
+    let Some(table) = value.as_table() else { return Ok(Info::new(parse_colour(value)?, Color::Reset, vec![])) };
+    let mut info = Info::new(Color::Reset, Color::Reset, vec![]);
+    for (key, value) in table {
+        let attribute = match key.as_str() {
+            "foreground" => {
+                info.foreground = parse_colour(value)?;
+                continue;
+            },
+            "bold" => Attribute::Bold,
+            "italic" => Attribute::Italic,
+            "underline" => Attribute::Underlined,
+            _ => return Err(format!("Unknown style {}", key)),
+        };
+        if value.as_bool().ok_or(format!("{} is true or false", key))? {
+            info.attributes.push(attribute);
+        }
+    }
+    Ok(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(text: &str) -> String {
+        Theme::parse("test", text, &Theme::default()).err().unwrap()
+    }
+
+    #[test]
+    fn mistakes_are_named() {
+        assert_eq!(error("[colours]\ntext = \"red\""), "Unknown table colours");
+        assert_eq!(error("ui = 1"), "ui is not a table");
+        assert_eq!(error("[ui]\nforeground = \"red\""), "Unknown ui colour foreground");
+        assert_eq!(error("[syntax]\nlabel = \"red\""), "Unknown token label");
+        assert_eq!(error("[syntax]\nkeyword = { blink = true }"), "keyword: Unknown style blink");
+        assert_eq!(error("[syntax]\nkeyword = { bold = 1 }"), "keyword: bold is true or false");
+        assert_eq!(error("[ui]\ntext = 1"), "text: Colours are written as strings");
+        assert_eq!(error("[ui]\ntext = \"purple\""), "text: purple: Unknown colour");
+    }
+
+    #[test]
+    fn hex_colours_need_six_digits() {
+        assert_eq!(error("[ui]\ntext = \"#abc\""), "text: #abc: Invalid colour");
+        assert_eq!(error("[ui]\ntext = \"#gggggg\""), "text: #gggggg: Invalid colour");
+        assert_eq!(error("[ui]\ntext = \"#1234567\""), "text: #1234567: Invalid colour");
+        let theme = Theme::parse("test", "[ui]\ntext = \"#0a0B0c\"", &Theme::default()).unwrap();
+        assert_eq!(theme.text, Color::Rgb { r: 10, g: 11, b: 12 });
+    }
+
+    #[test]
+    fn themes_are_laid_over_dracula() {
+        let dracula = Theme::default();
+        let theme = Theme::parse("test", "[ui]\ntext = \"red\"\n\n[syntax]\nkeyword = { foreground = \"cyan\", italic = true }", &dracula).unwrap();
+        assert_eq!(theme.name, "test");
+        assert_eq!(theme.text, Color::Red);
+        assert_eq!(theme.token(Token::Keyword), Info::new(Color::Cyan, Color::Reset, vec![Attribute::Italic]));
+        assert_eq!(theme.background, dracula.background);
+        assert_eq!(theme.cursor, dracula.cursor);
+        assert_eq!(theme.token(Token::String), dracula.token(Token::String));
+    }
+
+    #[test]
+    fn every_bundled_theme_parses() {
+        for (name, text) in BUNDLED {
+            assert!(Theme::parse(name, text, &Theme::default()).is_ok(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn rgb_colours_fall_back_to_the_nearest_terminal_colour() {
+        assert_eq!(nearest_ansi(Color::Rgb { r: 250, g: 10, b: 10 }), Color::Red);
+        assert_eq!(nearest_ansi(Color::Rgb { r: 30, g: 30, b: 30 }), Color::Black);
+        assert_eq!(nearest_ansi(Color::Rgb { r: 0, g: 190, b: 200 }), Color::DarkCyan);
+        assert_eq!(nearest_ansi(Color::Reset), Color::Reset);
+        assert_eq!(nearest_ansi(Color::DarkGrey), Color::DarkGrey);
+
+        let is_rgb = |colour: Color| matches!(colour, Color::Rgb { .. });
+        let mut truecolor = Theme::default().for_terminal(true);
+        assert!(truecolor.ui().into_iter().any(|(_, colour)| is_rgb(*colour)));
+        let mut ansi = Theme::default().for_terminal(false);
+        assert!(!ansi.ui().into_iter().any(|(_, colour)| is_rgb(*colour)));
+        assert!(!Token::ALL.into_iter().any(|token| is_rgb(ansi.token(token).foreground)));
+        assert_eq!(ansi.text, Color::White);
+    }
+}
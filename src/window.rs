@@ -1,42 +1,60 @@
-use std::{io::{Stdout, stdout, Write, ErrorKind, Error}, time::Duration, path::Path};
+use std::{env, time::Duration, path::Path};
 use crossterm::{
-    event::{poll, read, Event, KeyCode, KeyModifiers, MouseEventKind, MouseButton},
+    event::{Event, KeyCode, KeyEvent, KeyModifiers, MouseEventKind, MouseButton},
     Result, 
-    queue,
-    cursor, terminal, style::Color
+    style::Color
 };
-use chrono::{self, Timelike};
 use unicode_segmentation::UnicodeSegmentation;
 
 use crate::{colour_string::{ColourString, Info}, editor::File, tab::Tab};
 use crate::home::Home;
-use crate::command_palette::CommandPalette;
+use crate::command_palette::{CommandPalette, PaletteMode};
 use crate::file_explorer::FileExplorer;
 use crate::status_bar::StatusBar;
-
-pub enum PushDirection {
-    Left,
-    Up
-}
+use crate::search::Search;
+use crate::substitute::Substitute;
+use crate::selection::{Selection, VisualMode, Yank};
+use crate::register::{ClipboardProvider, Registers, detect_clipboard};
+use crate::syntaxes::Syntaxes;
+use crate::theme::Theme;
+use crate::screen::Screen;
+use crate::layout::{Area, Layout, Rect, Size};
+use crate::pane::{Pane, Split};
+use crate::cursor::Cursor;
+use crate::backend::{Backend, Events};
+use crate::commands;
+use crate::command_history::Kind;
 
 pub struct Frame {
     pub content: Vec<ColourString>,
-    pub push: Option<PushDirection>,
-    pub ignore_whitespace: bool
+    pub rect: Rect,
+    // uncoloured spaces let what is underneath show through, for things drawn over the rest of the screen
+    pub transparent: bool,
 }
 
 impl Frame {
-    pub fn new(content: Vec<ColourString>, push: Option<PushDirection>, ignore_whitespace: bool) -> Self {
+    pub fn new(content: Vec<ColourString>, rect: Rect, transparent: bool) -> Self {
         Self {
             content,
-            push,
-            ignore_whitespace
+            rect,
+            transparent,
         }
     }
 }
 
-pub struct Window {
-    pub renderer: Stdout,
+// what would be lost without saving
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Closing {
+    // the current file, closed with x
+    File,
+    // every modified file, when quitting
+    All,
+}
+
+pub struct Window<B: Backend> {
+    pub backend: B,
+    pub events: Box<dyn Events>,
+    pub screen: Screen,
     pub command_palette: CommandPalette,
     pub file_explorer: FileExplorer,
     pub status_bar: StatusBar,
@@ -46,12 +64,40 @@ pub struct Window {
     pub frames: Vec<Frame>,
     pub home: Home,
     pub overlay: bool,
+    pub search: Search,
+    pub substitute: Option<Substitute>,
+    pub registers: Registers,
+    pub register: Option<char>,
+    pub awaiting_register: bool,
+    // ctrl + v was pressed in the palette and the next key names the register to paste
+    pub awaiting_paste: bool,
+    // what the save / discard / cancel prompt is about, while it is up
+    pub closing: Option<Closing>,
+    // the render loop stops once this is set
+    pub quit: bool,
+    pub theme: Theme,
+    pub panes: Vec<Pane>,
+    // the focused pane
+    pub pane: usize,
+    pub split: Split,
+    // where the divider being dragged is
+    pub drag: Option<(u16, u16)>,
+    // ctrl + w was pressed and the next key says what to do with the panes
+    pub awaiting_pane: bool,
 }
 
-impl Window {
-    pub fn new() -> Self {
+impl<B: Backend> Window<B> {
+    pub fn new(backend: B, events: Box<dyn Events>) -> Self {
+        Self::with_clipboard(backend, events, detect_clipboard())
+    }
+
+    // the "+ and "* registers go through `clipboard` instead of the one found on the system
+    pub fn with_clipboard(backend: B, events: Box<dyn Events>, clipboard: Box<dyn ClipboardProvider>) -> Self {
         Self {
-            renderer: stdout(),
+            theme: Theme::default().for_terminal(backend.truecolor()),
+            backend,
+            events,
+            screen: Screen::new(),
             frames: Vec::new(),
             files: Vec::new(),
             file_index: 0,
@@ -61,101 +107,139 @@ impl Window {
             file_explorer: FileExplorer::new(),
             status_bar: StatusBar::new(),
             overlay: false,
+            search: Search::new(),
+            substitute: None,
+            registers: Registers::new(clipboard),
+            register: None,
+            awaiting_register: false,
+            awaiting_paste: false,
+            closing: None,
+            quit: false,
+            panes: vec![Pane::new(0, Cursor::new())],
+            pane: 0,
+            split: Split::Pane(0),
+            drag: None,
+            awaiting_pane: false,
         }
     }
 
-    pub fn register(&mut self, frame: Vec<ColourString>, push: Option<PushDirection>, ignore_whitespace: bool) {
-        self.frames.push(Frame::new(frame, push, ignore_whitespace));
+    pub fn register(&mut self, frame: Vec<ColourString>, rect: Rect, transparent: bool) {
+        self.frames.push(Frame::new(frame, rect, transparent));
     }
 
+    // each frame is clipped to its rect, and an overlay dims every frame drawn before it
     fn render_frames(&mut self) -> Result<()> {
-        let (terminal_x, terminal_y) = terminal::size()?;
-        let mut final_frame: Vec<ColourString> = vec![ColourString::new(String::from(" ".repeat(terminal_x as usize)), None); terminal_y as usize];
-        let mut it = self.frames.iter_mut().peekable();
-        while let Some(frame) = it.next() {
-            frame.content.truncate(terminal_y as usize);
-
-            let is_last = it.peek().is_none();
-            for line_index in 0..frame.content.len() {
-                if frame.content[line_index].get_content().is_empty() {
-                    continue;
+        let (terminal_x, terminal_y) = self.backend.size()?;
+        let mut final_frame: Vec<ColourString> = vec![ColourString::new(" ".repeat(terminal_x as usize), None); terminal_y as usize];
+        let last = self.frames.len().saturating_sub(1);
+        for (i, frame) in self.frames.iter().enumerate() {
+            if self.overlay && i == last {
+                for line in &mut final_frame {
+                    line.dim(self.theme.dimmed, self.theme.shade);
                 }
+            }
 
-                let mut line = frame.content[line_index].get_content().clone();
-                line.truncate(terminal_x as usize);
-
-                for (char_index, char) in line.iter_mut().enumerate().rev() {
-                    if self.overlay && !is_last {
-                        if char.content == "█" {
-                            char.colour = Info::new(Color::Black, Color::Reset, vec![]);
-                        }
-                        else {
-                            char.colour = Info::new(Color::DarkGrey, Color::Reset, vec![]);
-                        }
-                    }
-
-                    match &frame.push {
-                        Some(direction) => {
-                            match direction {
-                                PushDirection::Left => {
-                                    final_frame[line_index].l_shift(char.content.clone(), Some(char.colour.clone()));
-                                },
-                                PushDirection::Up => {
-                                    final_frame.insert(0, frame.content[line_index].clone());
-                                    break;
-                                }
-                            }
-                        },
-                        None => {
-                            if char.content != " " || frame.ignore_whitespace {
-                                final_frame[line_index].replace_range(char_index, char_index+1, ColourString::new(char.content.clone(), Some(char.colour.clone())));
-                            }
-                        }
+            for (row, line) in frame.content.iter().take(frame.rect.height as usize).enumerate() {
+                let Some(target) = final_frame.get_mut(frame.rect.y as usize + row) else { break };
+                // clipped first so a wide grapheme cut at the edge doesn't lose only half of itself
+                let mut line = line.clone();
+                line.truncate((frame.rect.width as usize).min((terminal_x as usize).saturating_sub(frame.rect.x as usize)));
+                for (column, char) in line.get_content().iter().enumerate() {
+                    let x = frame.rect.x as usize + column;
+                    // only spaces nothing coloured let what is under them show, so padding inside a box covers it
+                    if !(frame.transparent && char.content == " " && char.colour == Info::default()) {
+                        target.set_char(x, char.clone());
                     }
-
-                    final_frame[line_index].truncate(terminal_x as usize);
                 }
             }
         }
-        final_frame.truncate(terminal_y as usize);
-        queue!(self.renderer, cursor::MoveTo(0,0))?;
-        write!(self.renderer, "{}", ColourString::render_vector(final_frame))?;
+        self.screen.draw(final_frame, (terminal_x, terminal_y), &mut self.backend)?;
         self.overlay = false;
         self.frames.clear();
         Ok(())
     }
 
+    // where everything goes on a screen of this size
+    fn layout(&self) -> Layout {
+        if self.files.is_empty() {
+            return Layout::Column(vec![(Size::Fill, Layout::Area(Area::Home)), (Size::Fixed(1), Layout::Area(Area::StatusBar))]);
+        }
 
-    pub fn parse_input(&mut self) -> Result<()> {
-        if poll(Duration::from_millis(50))? {
-            let (terminal_x, terminal_y) = terminal::size()?;
+        let mut body = vec![];
+        if self.file_explorer.enabled {
+            body.push((Size::Percent(20), Layout::Area(Area::Explorer)));
+        }
+        body.push((Size::Fill, Layout::Area(Area::Panes)));
+        Layout::Column(vec![
+            (Size::Fixed(1), Layout::Area(Area::Tabs)),
+            (Size::Fill, Layout::Row(body)),
+            (Size::Fixed(1), Layout::Area(Area::StatusBar)),
+        ])
+    }
+
+    // the panes are laid out inside the area left for them
+    fn areas(&self) -> Result<Vec<(Area, Rect)>> {
+        let (terminal_x, terminal_y) = self.backend.size()?;
+        let mut areas = vec![];
+        for (area, rect) in self.layout().resolve(Rect::new(0, 0, terminal_x, terminal_y)) {
+            match area {
+                Area::Panes => areas.extend(self.split.layout().resolve(rect)),
+                area => areas.push((area, rect)),
+            }
+        }
+        Ok(areas)
+    }
+
+    fn panes_rect(&self) -> Result<Rect> {
+        let (terminal_x, terminal_y) = self.backend.size()?;
+        Ok(self.layout().resolve(Rect::new(0, 0, terminal_x, terminal_y)).into_iter().find(|&(area, _)| area == Area::Panes).map(|(_, rect)| rect).unwrap_or_default())
+    }
+
+    fn rect(&self, area: Area) -> Result<Rect> {
+        Ok(self.areas()?.into_iter().find(|&(found, _)| found == area).map(|(_, rect)| rect).unwrap_or_default())
+    }
+
+    // the area under a mouse event and the position in it
+    fn area_at(&self, x: u16, y: u16) -> Result<Option<(Area, (u16, u16))>> {
+        Ok(self.areas()?.into_iter().find_map(|(area, rect)| rect.to_local(x, y).map(|position| (area, position))))
+    }
+
+    pub fn parse_input(&mut self, timeout: Duration) -> Result<()> {
+        if self.events.poll(timeout)? {
             self.status_bar.command_output = None;
-            match read()? {
+            match self.events.read()? {
+                // the palette takes every key but ctrl + l while it is open
+                Event::Key(key) if self.command_palette.enabled && !(key.modifiers == KeyModifiers::CONTROL && key.code == KeyCode::Char('l')) => {
+                    self.palette_input(key)?;
+                },
+                // only the answers reach a substitution being confirmed, so nothing else ends up in its undo group
+                Event::Key(key) if self.substitute.is_some() && !matches!(key.modifiers, KeyModifiers::NONE | KeyModifiers::SHIFT) && !(key.modifiers == KeyModifiers::CONTROL && key.code == KeyCode::Char('l')) => {},
                 Event::Key(key) => {
                     match key.modifiers {
                         KeyModifiers::NONE | KeyModifiers::SHIFT => {
-                            if self.command_palette.enabled {
+                            if self.awaiting_pane {
+                                self.awaiting_pane = false;
+                                self.pane_command(key.code)?;
+                            }
+                            else if self.awaiting_register {
+                                self.awaiting_register = false;
                                 match key.code {
-                                    KeyCode::Char(c) => {
-                                        self.command_palette.command.push(c);
-                                    },
-                                    KeyCode::Backspace => {
-                                        self.command_palette.command.pop();
-                                        if self.command_palette.command.is_empty() {
-                                            self.command_palette.enabled = false;
-                                        }
-                                    },
-                                    KeyCode::Enter => {
-                                        self.command_palette.enabled = false;
-                                        self.parse_command()?;
-                                    },
-                                    KeyCode::Esc => {
-                                        self.command_palette.enabled = false;
-                                        self.command_palette.command = String::new();
-                                    },
+                                    KeyCode::Char(c) if Registers::is_valid(c) => self.register = Some(c),
+                                    KeyCode::Char(c) => self.error(format!("Invalid register: {}", c)),
                                     _ => {}
                                 }
                             }
+                            else if let Some(closing) = self.closing.take() {
+                                self.answer_closing(closing, key.code);
+                            }
+                            else if self.substitute.is_some() {
+                                if let KeyCode::Char(c) = key.code {
+                                    self.confirm_substitute(c);
+                                }
+                                else if key.code == KeyCode::Esc {
+                                    self.confirm_substitute('q');
+                                }
+                            }
                             else if !self.files.is_empty() && self.files[self.file_index].insert {
                                 match key.code {
                                     KeyCode::Char(c) => {
@@ -167,108 +251,105 @@ impl Window {
                                     KeyCode::Enter => {
                                         self.files[self.file_index].enter();
                                     },
+                                    KeyCode::Tab => {
+                                        self.files[self.file_index].insert_tab();
+                                    },
                                     KeyCode::Esc => {
                                         self.files[self.file_index].insert = false;
+                                        self.files[self.file_index].history.seal();
                                     },
                                     direction @ (KeyCode::Up | KeyCode::Down | KeyCode::Left | KeyCode::Right) => {
-                                        self.files[self.file_index].cursor.parse_direction(direction);
+                                        self.files[self.file_index].move_cursors(direction);
+                                        self.files[self.file_index].history.seal();
                                     },
                                     _ => {}
                                 }
                             }
+                            else if !self.files.is_empty() && self.files[self.file_index].selection.is_some() {
+                                self.visual_input(key.code);
+                            }
                             else {
                                 match key.code {
                                     KeyCode::Char(':') => {
                                         self.command_palette.enabled = true;
                                     },
-                                    KeyCode::Char('c') => {
-                                        if self.file_explorer.enabled && !self.files.is_empty() {
-                                            self.file_explorer.enabled = false;
-                                            self.file_explorer.selected = false;
-
-                                            for file in &mut self.files {
-                                                    file.cursor.set_min(5, file.cursor.y_min);
-                                                    file.cursor.x -= terminal_x / 5 + 1;
-                                            }
-                                        }
-                                        else if !self.files.is_empty() {
-                                            self.file_explorer.enabled = true;
-
-                                            for file in &mut self.files {
-                                                file.cursor.x += terminal_x / 5 + 1;
-                                                file.cursor.set_min(terminal_x / 5 + 1 + file.cursor.x_min, file.cursor.y_min);
-                                            }
-
-                                        }
+                                    KeyCode::Char(c @ ('/' | '?')) if !self.files.is_empty() && self.files[self.file_index].hex.is_none() => {
+                                        self.command_palette.enabled = true;
+                                        self.command_palette.mode = PaletteMode::Search { backward: c == '?' };
+                                        self.command_palette.hint = self.search.flags();
+                                        self.search.backward = c == '?';
+                                        self.search.origin = self.files[self.file_index].cursor_index();
                                     },
-                                    KeyCode::Char('C') => {
-                                        if self.file_explorer.enabled {
-                                            self.file_explorer.selected = !self.file_explorer.selected;
-                                        }
-                                    }
-                                    KeyCode::Char('i') => {
-                                        if !self.files.is_empty() {
-                                            self.files[self.file_index].insert = true;
-                                        }
+                                    KeyCode::Char('c') if !self.files.is_empty() => {
+                                        self.file_explorer.enabled = !self.file_explorer.enabled;
+                                        self.file_explorer.selected = false;
                                     },
-                                    KeyCode::Char('r') => {
-                                        if !self.files.is_empty() {
-                                            self.files[self.file_index].refresh_highlight();
-                                        }
+                                    KeyCode::Char('C') if self.file_explorer.enabled => {
+                                        self.file_explorer.selected = !self.file_explorer.selected;
                                     },
-                                    KeyCode::Char('l') => {
-                                        if !self.files.is_empty() {
-                                            let file = &mut self.files[self.file_index];
-                                            let end = (file.lines[(file.cursor.y + file.cursor.y_offset - file.cursor.y_min) as usize].graphemes(true).count() as u16 + file.cursor.x_min);
-                                            file.cursor.move_to(end, file.cursor.y);
-                                        }
+                                    KeyCode::Char('i') if !self.files.is_empty() => {
+                                        self.files[self.file_index].insert = true;
+                                        self.files[self.file_index].history.seal();
+                                    },
+                                    KeyCode::Char('v') if !self.files.is_empty() => {
+                                        self.start_visual(VisualMode::Char);
                                     },
-                                    KeyCode::Char('k') => {
-                                        if !self.files.is_empty() {
+                                    KeyCode::Char('V') if !self.files.is_empty() => {
+                                        self.start_visual(VisualMode::Line);
+                                    },
+                                    KeyCode::Char(c @ ('p' | 'P')) if !self.files.is_empty() && self.files[self.file_index].hex.is_none() => {
+                                        if let Some(yank) = self.register_contents() {
                                             let file = &mut self.files[self.file_index];
-                                            let first = file.lines[(file.cursor.y + file.cursor.y_offset - file.cursor.y_min) as usize].graphemes(true).position(|c| c != " ").unwrap_or(0) as u16 + file.cursor.x_min;
-                                            file.cursor.x_offset = first.saturating_sub(terminal_x);
-                                            file.cursor.move_to(first, file.cursor.y);
+                                            file.history.begin_group();
+                                            yank.paste(file, c == 'P');
+                                            file.history.end_group();
                                         }
                                     },
-                                    KeyCode::Char('n') => {
-                                        if !self.files.is_empty() {
-                                            self.file_index += 1;
-                                            self.file_index %= self.files.len();
+                                    KeyCode::Char('"') if !self.files.is_empty() => {
+                                        self.awaiting_register = true;
+                                    },
+                                    KeyCode::Char('u') if !self.files.is_empty() => {
+                                        self.undo();
+                                    },
+                                    KeyCode::Char('l') if !self.files.is_empty() && self.files[self.file_index].hex.is_none() => {
+                                        let file = &mut self.files[self.file_index];
+                                        let y = file.cursor.position().1.min(file.buffer.len_lines() - 1);
+                                        file.set_cursor_index((file.buffer.line_len(y), y));
+                                    },
+                                    KeyCode::Char('k') if !self.files.is_empty() && self.files[self.file_index].hex.is_none() => {
+                                        let file = &mut self.files[self.file_index];
+                                        let y = file.cursor.position().1.min(file.buffer.len_lines() - 1);
+                                        let first = file.buffer.line(y).graphemes(true).position(|c| c != " " && c != "\t").unwrap_or(0);
+                                        file.set_cursor_index((first, y));
+                                    },
+                                    KeyCode::Char('n') if !self.files.is_empty() && !self.search.query.is_empty() => {
+                                        self.next_match(false);
+                                    },
+                                    KeyCode::Char('N') if !self.files.is_empty() && !self.search.query.is_empty() => {
+                                        self.next_match(true);
+                                    },
+                                    KeyCode::Tab if !self.files.is_empty() => {
+                                        self.file_index += 1;
+                                        self.file_index %= self.files.len();
+                                        self.panes[self.pane].file = self.file_index;
+                                    },
+                                    KeyCode::BackTab if !self.files.is_empty() => {
+                                        if self.file_index == 0 {
+                                            self.file_index = self.files.len() - 1;
                                         }
-                                    }
-                                    KeyCode::Char('b') => {
-                                        if !self.files.is_empty() {
-                                            if self.file_index == 0 {
-                                                self.file_index = self.files.len() - 1;
-                                            }
-                                            else {
-                                                self.file_index -= 1;
-                                            }
+                                        else {
+                                            self.file_index -= 1;
                                         }
+                                        self.panes[self.pane].file = self.file_index;
                                     },
-                                    KeyCode::Char('x') => {
-                                        if !self.files.is_empty() {
-                                            self.files.remove(self.file_index);
-
-                                            self.file_index = self.file_index.saturating_sub(1);
-                                        }
+                                    KeyCode::Char('x') if !self.files.is_empty() => {
+                                        self.close_file_or_ask();
                                     },
-                                    KeyCode::Enter => {
-                                        if self.file_explorer.selected {
-                                            match self.file_explorer.parse_input() {
-                                                Some(path) => {
-                                                    let mut file = File::new(path)?;
-                                                    file.cursor.set_min(5 + if self.file_explorer.enabled { terminal_x / 5 + 1 } else { 0 }, 1); // TO CHANGR
-                                                    file.cursor.x = file.cursor.x_min;
-                                                    file.cursor.y = file.cursor.y_min;
-                                                    self.files.push(file);
-                                                    self.file_index = self.files.len() - 1;
-                                                },
-                                                None => ()
-                                            };
+                                    KeyCode::Enter if self.file_explorer.selected => {
+                                        if let Some(path) = self.file_explorer.parse_input() {
+                                            self.open(path)?;
                                         }
-                                    }
+                                    },
                                     direction @ (KeyCode::Up | KeyCode::Down | KeyCode::Left | KeyCode::Right | KeyCode::Char('w') | KeyCode::Char('a') | KeyCode::Char('s') | KeyCode::Char('d')) => {
                                         if self.file_explorer.selected {
                                             self.file_explorer.cursor.parse_direction(direction);
@@ -277,171 +358,881 @@ impl Window {
                                             self.home.cursor.parse_direction(direction);
                                         }
                                         else {
-                                            self.files[self.file_index].cursor.parse_direction(direction);
+                                            self.files[self.file_index].move_cursors(direction);
                                         }
                                     },
+                                    KeyCode::Esc if !self.files.is_empty() => {
+                                        self.files[self.file_index].cursors.clear();
+                                    },
                                     _ => {}
                                 }
                             }
                         },
+                        // draws everything again, for when something else has written to the terminal
+                        KeyModifiers::CONTROL if key.code == KeyCode::Char('l') => {
+                            self.screen.invalidate();
+                        },
+                        KeyModifiers::CONTROL if key.code == KeyCode::Char('d') && !self.command_palette.enabled && !self.files.is_empty() => {
+                            let file = &mut self.files[self.file_index];
+                            file.history.seal();
+                            if !file.add_cursor_at_next_occurrence() {
+                                self.error(String::from("No other occurrence of the word under the cursor"));
+                            }
+                        },
+                        KeyModifiers::CONTROL if !self.command_palette.enabled && !self.files.is_empty() && !self.files[self.file_index].insert => {
+                            match key.code {
+                                // ctrl + w twice goes to the next pane
+                                KeyCode::Char('w') if self.awaiting_pane => {
+                                    self.awaiting_pane = false;
+                                    self.pane_command(KeyCode::Char('w'))?;
+                                },
+                                KeyCode::Char('w') => self.awaiting_pane = true,
+                                KeyCode::Char('r') => self.redo(),
+                                KeyCode::Char('v') => self.start_visual(VisualMode::Block),
+                                _ => {}
+                            }
+                        },
                         _ => {}
                     }
                 },
                 Event::Mouse(event) => {
-                    match event.kind {
-                        MouseEventKind::Down(button) => {
-                            if let MouseButton::Left = button {
-                                if self.file_explorer.selected {
-                                    self.file_explorer.cursor.move_to(event.column as u16, event.row as u16);
-                                    match self.file_explorer.parse_input() {
-                                        Some(path) => {
-                                            let mut file = File::new(path)?;
-                                            file.cursor.set_min(5 + if self.file_explorer.enabled { terminal_x / 5 + 1 } else { 0 }, 1); // TO CHANGR
-                                            file.cursor.x = file.cursor.x_min;
-                                            file.cursor.y = file.cursor.y_min;
-                                            self.files.push(file);
-                                            self.file_index = self.files.len() - 1;
-                                        },
-                                        None => ()
-                                    };
-                                }
-                                else if self.files.is_empty() {
-                                    self.home.cursor.move_to(event.column as u16, event.row as u16)
-                                }
-                                else {
-                                    self.files[self.file_index].cursor.move_to(event.column as u16, event.row as u16)
-                                }
+                    // components get positions relative to their own area
+                    match (event.kind, self.area_at(event.column, event.row)?) {
+                        (MouseEventKind::Down(MouseButton::Left), Some((Area::Divider { .. }, _))) => {
+                            self.drag = Some((event.column, event.row));
+                        },
+                        (MouseEventKind::Drag(MouseButton::Left), _) if self.drag.is_some() => {
+                            if let Some((x, y)) = self.drag {
+                                let delta = (event.column as i32 - x as i32, event.row as i32 - y as i32);
+                                let rect = self.panes_rect()?;
+                                self.drag = self.split.drag(rect, (x, y), delta).or(self.drag);
+                            }
+                        },
+                        (MouseEventKind::Up(MouseButton::Left), _) if self.drag.is_some() => {
+                            self.drag = None;
+                        },
+                        (MouseEventKind::Down(MouseButton::Left), Some((Area::Pane(pane), (x, y)))) if event.modifiers == KeyModifiers::ALT => {
+                            self.focus(pane);
+                            self.files[self.file_index].toggle_cursor(x, y);
+                        },
+                        (MouseEventKind::Down(MouseButton::Left), Some((Area::Explorer, (x, y)))) => {
+                            self.file_explorer.selected = true;
+                            self.file_explorer.cursor.move_to(x, y);
+                            if let Some(path) = self.file_explorer.parse_input() {
+                                self.open(path)?;
+                            }
+                        },
+                        (MouseEventKind::Down(MouseButton::Left), Some((Area::Home, (x, y)))) => {
+                            self.home.cursor.move_to(x, y);
+                        },
+                        (MouseEventKind::Down(MouseButton::Left), Some((Area::Pane(pane), (x, y)))) => {
+                            self.file_explorer.selected = false;
+                            self.focus(pane);
+                            let file = &mut self.files[self.file_index];
+                            if let Some(selection) = file.selection.take() {
+                                file.last_visual = Some(selection.rows(file));
+                            }
+                            file.move_to(x, y);
+                            file.history.seal();
+                        },
+                        // dragging selects from where the button went down, going past the bottom or right edge scrolls
+                        (MouseEventKind::Drag(MouseButton::Left), _) if !self.file_explorer.selected && !self.files.is_empty() && self.files[self.file_index].hex.is_none() => {
+                            let rect = self.rect(Area::Pane(self.pane))?;
+                            let file = &mut self.files[self.file_index];
+                            if file.selection.is_none() {
+                                file.insert = false;
+                                file.selection = Some(Selection::new(VisualMode::Char, file.cursor_index()));
                             }
+                            file.move_to(event.column.saturating_sub(rect.x), event.row.saturating_sub(rect.y));
                         },
-                        MouseEventKind::ScrollUp => {
+                        (MouseEventKind::ScrollUp, _) => {
                             if self.files.is_empty() {
                                 self.home.cursor.parse_direction(KeyCode::Up);
                             }
                             else {
-                                self.files[self.file_index].cursor.parse_direction(KeyCode::Up);
+                                self.files[self.file_index].parse_direction(KeyCode::Up);
                             }
                         },
-                        MouseEventKind::ScrollDown => {
+                        (MouseEventKind::ScrollDown, _) => {
                             if self.files.is_empty() {
                                 self.home.cursor.parse_direction(KeyCode::Down);
                             }
                             else {
-                                self.files[self.file_index].cursor.parse_direction(KeyCode::Down);
+                                self.files[self.file_index].parse_direction(KeyCode::Down);
                             }
                         },
                         _ => {}
                     }
                 },
+                // the layout is worked out again on the next draw, and each component keeps its cursor inside its new area
+                Event::Resize(..) => {
+                    self.screen.invalidate();
+                    self.drag = None;
+                },
                 _ => {}
             }
         };
         Ok(())
     }
 
-    pub fn parse_command(&mut self) -> Result<()> {
-        let args: Vec<&str> = self.command_palette.command.split_whitespace().collect();
-        let terminal_x = terminal::size()?.0;
-        match args[0].to_lowercase().as_str() {
-            "q" | "quit" => {
-                return Err(Error::new(ErrorKind::Other, "Quit").into());
-            },
-            "o" | "open" => {
-                if args.len() > 1 {
-                    if !(Path::new(&args[1]).exists() && Path::new(&args[1]).is_file()) {
-                        self.status_bar.set_command_output(ColourString::new(format!("{}: No such file", args[1]), Some(Info::new(Color::Red, Color::Reset, vec![]))));
-                        self.command_palette.command.clear();
-                        return Ok(())
-                    }
-                    let mut file = File::new(args[1].to_string())?;
-                    file.cursor.set_min(5 + if self.file_explorer.enabled { terminal_x / 5 + 1 } else { 0 }, 1); // TO CHANGR
-                    file.cursor.x = file.cursor.x_min;
-                    file.cursor.y = file.cursor.y_min;
-                    self.files.push(file);
-                    self.file_index = self.files.len() - 1;
-                }
-            },
-            "s" | "save" => {
-                self.status_bar.set_command_output(self.files[self.file_index].save()?);
+    fn undo(&mut self) {
+        if !self.files[self.file_index].undo() {
+            self.status_bar.set_command_output(ColourString::new(String::from("Already at oldest change"), Some(Info::new(self.theme.warning, Color::Reset, vec![]))));
+        }
+    }
+
+    fn redo(&mut self) {
+        if !self.files[self.file_index].redo() {
+            self.status_bar.set_command_output(ColourString::new(String::from("Already at newest change"), Some(Info::new(self.theme.warning, Color::Reset, vec![]))));
+        }
+    }
+
+    // reverse search and pasting get the key first, then the line editor, and what is left is for the palette itself
+    fn palette_input(&mut self, key: KeyEvent) -> Result<()> {
+        let search = self.command_palette.mode != PaletteMode::Command;
+        if self.awaiting_paste {
+            self.awaiting_paste = false;
+            if let KeyCode::Char(c) = key.code {
+                self.paste_into_palette(c);
             }
-            _ => {
-                self.status_bar.set_command_output(ColourString::new(format!("{}: Command not found", args[0]), Some(Info::new(Color::Red, Color::Reset, vec![]))));
+        }
+        else if self.command_palette.search_history(key) {}
+        else if key.code == KeyCode::Backspace && self.command_palette.input.is_empty() {
+            if search {
+                self.cancel_search();
+                return Ok(());
+            }
+            self.close_palette();
+        }
+        else if self.command_palette.edit(key) {}
+        else {
+            match (key.modifiers, key.code) {
+                (KeyModifiers::CONTROL, KeyCode::Char('r')) => self.command_palette.search_older(),
+                (KeyModifiers::CONTROL, KeyCode::Char('v')) => self.awaiting_paste = true,
+                (KeyModifiers::ALT, KeyCode::Char('c')) if search => self.search.smart_case = !self.search.smart_case,
+                (KeyModifiers::ALT, KeyCode::Char('w')) if search => self.search.whole_word = !self.search.whole_word,
+                (_, KeyCode::Tab) => self.command_palette.cycle(true),
+                (_, KeyCode::BackTab) => self.command_palette.cycle(false),
+                (_, KeyCode::Up) => self.command_palette.older(),
+                (_, KeyCode::Down) => self.command_palette.newer(),
+                (_, KeyCode::Enter) if search => {
+                    let found = self.search.current.is_some();
+                    self.registers.last_search = self.search.query.clone();
+                    self.command_palette.history.add(Kind::Search, &self.search.query);
+                    self.close_palette();
+                    if !found {
+                        self.search.highlight = false;
+                        self.error(format!("Pattern not found: {}", self.search.query));
+                    }
+                    return Ok(());
+                },
+                (_, KeyCode::Enter) => {
+                    self.command_palette.enabled = false;
+                    self.command_palette.reset();
+                    return self.parse_command();
+                },
+                (_, KeyCode::Esc) if search => {
+                    self.cancel_search();
+                    return Ok(());
+                },
+                (_, KeyCode::Esc) => self.close_palette(),
+                _ => {}
             }
         }
-        self.command_palette.command.clear();
+        if search && self.command_palette.enabled {
+            self.update_search();
+        }
         Ok(())
     }
 
-    pub fn render(&mut self) -> Result<()> {
-        loop {
-            let (terminal_x, terminal_y) = terminal::size()?;
+    // a register's text goes in where the cursor is, as a single line
+    fn paste_into_palette(&mut self, name: char) {
+        match self.registers.get(Some(name)) {
+            Ok(Some(yank)) => {
+                self.command_palette.input.insert(&yank.text);
+                self.command_palette.changed();
+            },
+            Ok(None) => {},
+            Err(e) => self.error(e),
+        }
+    }
 
-            self.parse_input()?;
-            queue!(self.renderer, cursor::Hide, cursor::DisableBlinking)?;
+    // searches again from where the prompt was opened every time the query or flags change
+    fn update_search(&mut self) {
+        self.search.query = self.command_palette.input.text().to_string();
+        self.command_palette.hint = self.search.flags();
 
-            if !self.files.is_empty() {
-                let dt = chrono::Local::now();
-                let file = &self.files[self.file_index];
+        let file = &mut self.files[self.file_index];
+        let origin = self.search.origin;
+        match self.search.find(&file.buffer, origin, self.search.backward, true) {
+            Some(position) => file.set_cursor_index(position),
+            None => file.set_cursor_index(origin),
+        }
+        self.search.highlight = true;
+    }
 
-                let mut message = if file.insert {
-                    ColourString::new(format!("insert "), Some(Info::new(Color::Red, Color::Reset, vec![])))
-                }
-                else {
-                    ColourString::new(format!("view "), Some(Info::new(Color::Green, Color::Reset, vec![])))
-                };
-                let end_message = format!("{} Ln {}, Col {}", dt.format("%H:%M:%S").to_string(), file.cursor.y - file.cursor.y_min + file.cursor.y_offset + 1, file.cursor.x - file.cursor.x_min + file.cursor.x_offset + 1);
+    fn cancel_search(&mut self) {
+        self.files[self.file_index].set_cursor_index(self.search.origin);
+        self.search.highlight = false;
+        self.close_palette();
+    }
+
+    fn close_palette(&mut self) {
+        self.command_palette.enabled = false;
+        self.command_palette.mode = PaletteMode::Command;
+        self.command_palette.hint.clear();
+        self.command_palette.input.clear();
+        self.command_palette.reset();
+    }
+
+    // n repeats the search in its direction, N goes the other way, highlighting the matches again after :noh
+    fn next_match(&mut self, reverse: bool) {
+        let file = &mut self.files[self.file_index];
+        if file.hex.is_some() {
+            return;
+        }
+
+        let from = file.cursor_index();
+        self.search.highlight = true;
+        match self.search.find(&file.buffer, from, self.search.backward != reverse, false) {
+            Some(position) => file.set_cursor_index(position),
+            None => self.error(format!("Pattern not found: {}", self.search.query)),
+        }
+    }
+
+    // pressing the key of the current visual mode again leaves it
+    fn start_visual(&mut self, mode: VisualMode) {
+        let file = &mut self.files[self.file_index];
+        if file.hex.is_some() {
+            return;
+        }
+
+        match file.selection {
+            Some(selection) if selection.mode == mode => {
+                file.last_visual = Some(selection.rows(file));
+                file.selection = None;
+            },
+            Some(ref mut selection) => selection.mode = mode,
+            None => file.selection = Some(Selection::new(mode, file.cursor_index())),
+        }
+    }
 
-                message.push_str(&format!("{} {}", file.name, format_size(file.lines.join("\n").len() as u64)), None);
-                message.push_str(&format!("{}", " ".repeat(terminal_x as usize - message.get_content().len()-end_message.len())), None);
-                message.push_str(&end_message, None);
+    fn visual_input(&mut self, code: KeyCode) {
+        let file = &mut self.files[self.file_index];
+        let Some(selection) = file.selection else { return };
+        file.last_visual = Some(selection.rows(file));
 
-                self.status_bar.set_message(message);
+        match code {
+            KeyCode::Char('v') => return self.start_visual(VisualMode::Char),
+            KeyCode::Char('V') => return self.start_visual(VisualMode::Line),
+            direction @ (KeyCode::Up | KeyCode::Down | KeyCode::Left | KeyCode::Right | KeyCode::Char('w') | KeyCode::Char('a') | KeyCode::Char('s')) => {
+                return file.parse_direction(direction);
+            },
+            KeyCode::Char('"') => {
+                self.awaiting_register = true;
+                return;
+            },
+            KeyCode::Char('y') => {
+                let (y, x, _) = selection.spans(file)[0];
+                let yank = selection.yank(file);
+                file.set_cursor_index((x, y));
+                let result = self.registers.yank(self.register.take(), yank);
+                self.report(result);
+            },
+            KeyCode::Char(c @ ('d' | 'x' | 'c')) => {
+                file.history.begin_group();
+                let yank = selection.delete(file);
+                file.history.end_group();
+                file.insert = c == 'c';
+                let result = self.registers.delete(self.register.take(), yank);
+                self.report(result);
+            },
+            KeyCode::Char(c @ ('>' | '<')) => {
+                file.history.begin_group();
+                selection.indent(file, c == '<');
+                file.history.end_group();
+            },
+            // pasting over a selection replaces it and keeps what was yanked
+            KeyCode::Char('p' | 'P') => {
+                let Some(yank) = self.register_contents() else { return };
+                let file = &mut self.files[self.file_index];
+                file.history.begin_group();
+                selection.delete(file);
+                yank.paste(file, true);
+                file.history.end_group();
+            },
+            KeyCode::Char(':') => {
+                self.command_palette.enabled = true;
+                self.command_palette.input.set("'<,'>");
+            },
+            KeyCode::Esc => {},
+            _ => return,
+        }
+        self.files[self.file_index].selection = None;
+    }
+
+    // the register picked with " is used by the next yank, delete or paste only
+    fn register_contents(&mut self) -> Option<Yank> {
+        let name = self.register.take();
+        match self.registers.get(name) {
+            Ok(None) => {
+                self.error(format!("Register {} is empty", name.unwrap_or('"')));
+                None
+            },
+            Ok(yank) => yank,
+            Err(e) => {
+                self.error(e);
+                None
+            },
+        }
+    }
+
+    fn report(&mut self, result: std::result::Result<(), String>) {
+        if let Err(e) = result {
+            self.error(e);
+        }
+    }
+
+    fn start_substitute(&mut self, mut substitute: Substitute) {
+        let file = &mut self.files[self.file_index];
+        file.history.begin_group();
+        if substitute.confirm && substitute.next(file) {
+            self.substitute = Some(substitute);
+        }
+        else {
+            if !substitute.confirm {
+                substitute.replace_all(file);
             }
+            self.finish_substitute(substitute);
+        }
+    }
 
-            if self.files.is_empty() {
-                let frame = self.home.render()?;
-                self.register(frame, None, false);
+    fn confirm_substitute(&mut self, answer: char) {
+        if let Some(mut substitute) = self.substitute.take() {
+            if substitute.answer(&mut self.files[self.file_index], answer) {
+                self.substitute = Some(substitute);
             }
             else {
-                let frame = self.files[self.file_index].render();
-                self.register(frame, None, false);
+                self.finish_substitute(substitute);
+            }
+        }
+    }
+
+    fn finish_substitute(&mut self, substitute: Substitute) {
+        self.files[self.file_index].history.end_group();
+        if substitute.matches == 0 {
+            self.error(format!("Pattern not found: {}", substitute.regex));
+        }
+        else {
+            self.status_bar.set_command_output(ColourString::new(substitute.summary(), None));
+        }
+    }
+
+    // true when it was saved, the error is shown with the file's name otherwise
+    fn report_save(&mut self, saved: Result<String>) -> bool {
+        match saved {
+            Ok(output) => {
+                self.status_bar.set_command_output(ColourString::new(output, Some(Info::new(self.theme.success, Color::Reset, vec![]))));
+                true
+            },
+            Err(e) => {
+                self.error(format!("{}: {}", self.files[self.file_index].name, e));
+                false
+            },
+        }
+    }
+
+    // stops at the first file that can't be saved
+    fn save_all(&mut self) -> bool {
+        let count = self.files.iter().filter(|file| file.modified).count();
+        let saved = self.files.iter_mut().filter(|file| file.modified).try_for_each(|file| match file.save() {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("{}: {}", file.name, e)),
+        });
+        match saved {
+            Ok(()) => {
+                let message = format!("saved {} file{}", count, if count == 1 { "" } else { "s" });
+                self.status_bar.set_command_output(ColourString::new(message, Some(Info::new(self.theme.success, Color::Reset, vec![]))));
+                true
+            },
+            Err(e) => {
+                self.error(e);
+                false
+            },
+        }
+    }
+
+    fn quit_or_ask(&mut self) {
+        if self.files.iter().any(|file| file.modified) {
+            self.closing = Some(Closing::All);
+        }
+        else {
+            self.quit = true;
+        }
+    }
+
+    fn close_file_or_ask(&mut self) {
+        if self.files[self.file_index].modified {
+            self.closing = Some(Closing::File);
+        }
+        else {
+            self.close_file();
+        }
+    }
+
+    // any other key leaves the question up
+    fn answer_closing(&mut self, closing: Closing, code: KeyCode) {
+        let close = match code {
+            KeyCode::Char('s') if closing == Closing::File => {
+                let saved = self.files[self.file_index].save();
+                self.report_save(saved)
+            },
+            KeyCode::Char('s') => self.save_all(),
+            KeyCode::Char('d') => true,
+            KeyCode::Char('c') | KeyCode::Esc => false,
+            _ => {
+                self.closing = Some(closing);
+                return;
+            },
+        };
+        if close {
+            match closing {
+                Closing::File => self.close_file(),
+                Closing::All => self.quit = true,
             }
+        }
+    }
+
+    fn closing_prompt(&self, closing: Closing) -> String {
+        let modified: Vec<&str> = match closing {
+            Closing::File => vec![self.files[self.file_index].name.as_str()],
+            Closing::All => self.files.iter().filter(|file| file.modified).map(|file| file.name.as_str()).collect(),
+        };
+        match modified.as_slice() {
+            [name] => format!("{} has unsaved changes, save, discard or cancel? (s/d/c)", name),
+            names => format!("{} files have unsaved changes, save them, discard or cancel? (s/d/c)", names.len()),
+        }
+    }
+
+    fn error(&mut self, message: String) {
+        self.status_bar.set_command_output(ColourString::new(message, Some(Info::new(self.theme.error, Color::Reset, vec![]))));
+    }
+
+    // failing to read a file is reported in the status bar instead of ending the render loop
+    fn open(&mut self, path: String) -> Result<()> {
+        match File::new(path.clone()) {
+            Ok(mut file) => {
+                file.cursor.set_min(file.gutter, 0);
+                file.cursor.x = file.cursor.x_min;
+                file.cursor.y = file.cursor.y_min;
+                self.files.push(file);
+                self.file_index = self.files.len() - 1;
+                self.panes[self.pane].file = self.file_index;
+            },
+            Err(e) => self.error(format!("{}: {}", path, e)),
+        }
+        Ok(())
+    }
+
+    // the key after ctrl + w
+    fn pane_command(&mut self, code: KeyCode) -> Result<()> {
+        match code {
+            KeyCode::Char('h') | KeyCode::Left => self.focus_towards(KeyCode::Left)?,
+            KeyCode::Char('j') | KeyCode::Down => self.focus_towards(KeyCode::Down)?,
+            KeyCode::Char('k') | KeyCode::Up => self.focus_towards(KeyCode::Up)?,
+            KeyCode::Char('l') | KeyCode::Right => self.focus_towards(KeyCode::Right)?,
+            KeyCode::Char('w') => self.focus((self.pane + 1) % self.panes.len()),
+            KeyCode::Char('s') => self.split_pane(false),
+            KeyCode::Char('v') => self.split_pane(true),
+            KeyCode::Char('q' | 'c') => self.close_pane(),
+            // + and - change the height, > and < the width
+            KeyCode::Char(c @ ('+' | '-' | '>' | '<')) => {
+                let rect = self.panes_rect()?;
+                let delta = if c == '+' || c == '>' { 1 } else { -1 };
+                self.split.resize(rect, self.pane, c == '>' || c == '<', delta);
+            },
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // the view being left is put back in its pane and the focused pane's view becomes the file's cursor
+    fn focus(&mut self, pane: usize) {
+        if pane == self.pane || pane >= self.panes.len() || self.files.is_empty() {
+            return;
+        }
+        std::mem::swap(&mut self.panes[self.pane].view, &mut self.files[self.file_index].cursor);
+        self.pane = pane;
+        self.file_index = self.panes[pane].file;
+        let file = &mut self.files[self.file_index];
+        std::mem::swap(&mut self.panes[pane].view, &mut file.cursor);
+
+        // another pane may have removed lines under the cursor since
+        if file.hex.is_none() {
+            let position = file.clamp(file.cursor_index());
+            file.set_cursor_index(position);
+        }
+    }
+
+    // the nearest pane in a direction, and of those the one closest to the cursor
+    fn focus_towards(&mut self, direction: KeyCode) -> Result<()> {
+        let panes: Vec<(usize, Rect)> = self.areas()?.into_iter().filter_map(|(area, rect)| match area {
+            Area::Pane(pane) => Some((pane, rect)),
+            _ => None,
+        }).collect();
+        let Some(&(_, current)) = panes.iter().find(|&&(pane, _)| pane == self.pane) else { return Ok(()) };
+        let cursor = &self.files[self.file_index].cursor;
+        let (x, y) = current.to_screen(cursor.x, cursor.y);
+        let outside = |position: u16, start: u16, length: u16| {
+            if position < start { start - position } else { position.saturating_sub((start + length).saturating_sub(1)) }
+        };
+
+        let next = panes.iter().filter_map(|&(pane, rect)| {
+            let (distance, beside) = match direction {
+                KeyCode::Left if rect.x + rect.width <= current.x => (current.x - (rect.x + rect.width), outside(y, rect.y, rect.height)),
+                KeyCode::Right if rect.x >= current.x + current.width => (rect.x - (current.x + current.width), outside(y, rect.y, rect.height)),
+                KeyCode::Up if rect.y + rect.height <= current.y => (current.y - (rect.y + rect.height), outside(x, rect.x, rect.width)),
+                KeyCode::Down if rect.y >= current.y + current.height => (rect.y - (current.y + current.height), outside(x, rect.x, rect.width)),
+                _ => return None,
+            };
+            Some((distance, beside, pane))
+        }).min();
+        if let Some((_, _, pane)) = next {
+            self.focus(pane);
+        }
+        Ok(())
+    }
+
+    // the new pane shows the same place in the file and takes the focus
+    fn split_pane(&mut self, side_by_side: bool) {
+        if self.files.is_empty() {
+            return;
+        }
+        let view = self.files[self.file_index].cursor.clone();
+        let new = self.panes.len();
+        self.panes[self.pane].view = view.clone();
+        self.panes.push(Pane::new(self.file_index, view));
+        self.split.split(self.pane, new, side_by_side);
+        self.pane = new;
+    }
+
+    fn close_pane(&mut self) {
+        if self.panes.len() < 2 {
+            return self.error(String::from("Cannot close the last pane"));
+        }
+        let closed = self.pane;
+        self.focus(if closed == 0 { 1 } else { closed - 1 });
+        self.panes.remove(closed);
+        self.split.remove(closed);
+        if self.pane > closed {
+            self.pane -= 1;
+        }
+    }
+
+    // panes showing the closed file show the one before it instead
+    fn close_file(&mut self) {
+        let closed = self.file_index;
+        self.files.remove(closed);
+        self.file_index = self.file_index.saturating_sub(1);
+        if self.files.is_empty() {
+            self.panes = vec![Pane::new(0, Cursor::new())];
+            self.pane = 0;
+            self.split = Split::Pane(0);
+            return;
+        }
 
-            if self.file_explorer.enabled {
-                let frame = self.file_explorer.render(&self.files[self.file_index]);
-                self.register(frame, Some(PushDirection::Left), false);
+        for (i, pane) in self.panes.iter_mut().enumerate() {
+            if pane.file == closed {
+                pane.file = self.file_index;
+                if i != self.pane {
+                    pane.view = self.files[self.file_index].cursor.clone();
+                }
+            }
+            else if pane.file > closed {
+                pane.file -= 1;
             }
-            
-            if !self.files.is_empty() {
-                let frame = self.tab.render(&self.files, self.file_index);
-                self.register(frame, Some(PushDirection::Up), false);
+        }
+    }
+
+    pub fn parse_command(&mut self) -> Result<()> {
+        let line = self.command_palette.input.text().to_string();
+        self.registers.last_command = line.clone();
+        self.command_palette.input.clear();
+        self.command_palette.history.add(Kind::Command, &line);
+        let substitute = self.files.get(self.file_index).and_then(|file| Substitute::parse(&line, file, self.search.regex()));
+        if let Some(substitute) = substitute {
+            match substitute {
+                _ if self.files[self.file_index].hex.is_some() => self.error(String::from("Substitute is not available in the hex view")),
+                Ok(substitute) => self.start_substitute(substitute),
+                Err(e) => self.error(e),
             }
+            return Ok(());
+        }
+
+        // an empty command line does nothing, like pressing enter in a shell
+        if line.trim().is_empty() {
+            return Ok(());
+        }
+        let (command, args) = match commands::parse(&line) {
+            Ok((command, _)) if command.needs_file && self.files.is_empty() => {
+                self.error(format!("{}: No file is open", command.name));
+                return Ok(());
+            },
+            Ok(parsed) => parsed,
+            Err(e) => {
+                self.error(e);
+                return Ok(());
+            },
+        };
+        match command.name {
+            "quit" | "quit!" | "close" if self.panes.len() > 1 => {
+                self.close_pane();
+            },
+            "quit" | "quitall" => self.quit_or_ask(),
+            "quit!" | "quitall!" => self.quit = true,
+            "wq" => {
+                let saved = self.files[self.file_index].save();
+                if self.report_save(saved) {
+                    if self.panes.len() > 1 {
+                        self.close_pane();
+                    }
+                    else {
+                        self.quit_or_ask();
+                    }
+                }
+            },
+            "close" => {
+                self.close_pane();
+            },
+            "split" | "vsplit" => {
+                self.split_pane(command.name == "vsplit");
+                if let Some(path) = args.first() {
+                    self.open(path.clone())?;
+                }
+            },
+            "open" => {
+                if Path::new(&args[0]).is_file() {
+                    self.open(args[0].clone())?;
+                }
+                else {
+                    self.error(format!("{}: No such file", args[0]));
+                }
+            },
+            "cd" => {
+                let directory = args.first().cloned().or_else(|| env::var("HOME").ok()).unwrap_or_default();
+                match env::set_current_dir(&directory) {
+                    Ok(()) => self.status_bar.set_command_output(ColourString::new(env::current_dir().map(|path| path.display().to_string()).unwrap_or(directory), None)),
+                    Err(e) => self.error(format!("{}: {}", directory, e)),
+                }
+            },
+            "save" => {
+                let saved = match args.first() {
+                    Some(path) => self.files[self.file_index].save_copy(path),
+                    None => self.files[self.file_index].save(),
+                };
+                self.report_save(saved);
+            },
+            "saveas" => {
+                let saved = self.files[self.file_index].save_as(&args[0]);
+                self.report_save(saved);
+            },
+            "saveall" => {
+                self.save_all();
+            },
+            "set" => {
+                for option in &args {
+                    if let Err(e) = self.files[self.file_index].set_option(option) {
+                        self.error(e);
+                        break;
+                    }
+                }
+            },
+            "reopen-with-encoding" => {
+                if let Err(e) = self.files[self.file_index].reopen_with_encoding(&args[0]) {
+                    self.error(e);
+                }
+            },
+            "registers" => {
+                let summary = self.registers.summary(&args.concat());
+                self.status_bar.set_command_output(ColourString::new(summary, None));
+            },
+            "theme" => match args.first() {
+                Some(name) => match Theme::load(name, self.backend.truecolor()) {
+                    Ok(theme) => {
+                        self.theme = theme;
+                        for file in &mut self.files {
+                            file.highlighted_lines.clear();
+                        }
+                    },
+                    Err(e) => self.error(e),
+                },
+                None => {
+                    let summary = format!("theme: {} | available: {}", self.theme.name, Theme::list().join(", "));
+                    self.status_bar.set_command_output(ColourString::new(summary, None));
+                },
+            },
+            "syntaxes" => {
+                self.status_bar.set_command_output(ColourString::new(Syntaxes::get().summary(), None));
+            },
+            "nohlsearch" => {
+                self.search.highlight = false;
+            },
+            "undo" => {
+                self.undo();
+            },
+            "redo" => {
+                self.redo();
+            },
+            "help" => match commands::help(args.first().map(String::as_str)) {
+                Ok(help) => self.status_bar.set_command_output(ColourString::new(help, None)),
+                Err(e) => self.error(e),
+            },
+            _ => {}
+        }
+        Ok(())
+    }
 
-            self.register(self.status_bar.render(), None, true);
+    // until something asks to quit
+    pub fn render(&mut self) -> Result<()> {
+        while !self.quit {
+            self.draw()?;
 
-            if self.command_palette.enabled {
-                let frame = self.command_palette.render();
-                self.register(frame, None, false);
-                self.overlay = true;
+            // nothing changes between events except the clock in the status bar, so wait for one or the next second
+            let second = 1000 - chrono::Local::now().timestamp_subsec_millis().min(999);
+            self.parse_input(Duration::from_millis(second as u64))?;
+            // a burst of input, like a paste or dragging the mouse, is drawn once
+            while !self.quit && self.events.poll(Duration::ZERO)? {
+                self.parse_input(Duration::ZERO)?;
             }
-            
-            self.render_frames()?;
+        }
+        Ok(())
+    }
 
-            if self.command_palette.enabled {
-                queue!(self.renderer, cursor::MoveTo(self.command_palette.cursor.x, self.command_palette.cursor.y))?;
+    // one frame of everything
+    pub fn draw(&mut self) -> Result<()> {
+        let (terminal_x, terminal_y) = self.backend.size()?;
+        self.backend.hide_cursor()?;
+
+        if !self.files.is_empty() {
+            let dt = chrono::Local::now();
+            let file = &self.files[self.file_index];
+            let text = Some(Info::new(self.theme.text, Color::Reset, vec![]));
+
+            let mut message = if let Some(selection) = &file.selection {
+                ColourString::new(format!("{} ", selection.mode.name()), Some(Info::new(self.theme.visual_mode, Color::Reset, vec![])))
             }
-            else if self.file_explorer.selected {
-                queue!(self.renderer, cursor::MoveTo(self.file_explorer.cursor.x, self.file_explorer.cursor.y))?;
+            else if file.insert && file.hex.is_some() {
+                ColourString::new("overwrite ".to_string(), Some(Info::new(self.theme.insert_mode, Color::Reset, vec![])))
             }
-            else if self.files.is_empty() {
-                queue!(self.renderer, cursor::MoveTo(self.home.cursor.x, self.home.cursor.y))?;
+            else if file.insert {
+                ColourString::new("insert ".to_string(), Some(Info::new(self.theme.insert_mode, Color::Reset, vec![])))
             }
             else {
-                queue!(self.renderer, cursor::MoveTo(self.files[self.file_index].cursor.x, self.files[self.file_index].cursor.y))?;
+                ColourString::new("view ".to_string(), Some(Info::new(self.theme.view_mode, Color::Reset, vec![])))
+            };
+            let end_message = match &file.hex {
+                Some(hex) => format!("{} Offset {:#x}", dt.format("%H:%M:%S"), hex.offset),
+                None => {
+                    let (x, y) = file.cursor.position();
+                    let cursors = match file.cursors.len() {
+                        0 => String::new(),
+                        extra => format!("{} cursors ", extra + 1),
+                    };
+                    // not counted while the query is still being typed
+                    let position = (self.search.highlight && !self.command_palette.enabled).then(|| self.search.position(&file.buffer, file.cursor_index())).flatten();
+                    let matches = match position {
+                        Some((index, total)) => format!("match {}/{} ", index, total),
+                        None => String::new(),
+                    };
+                    format!("{}{}{} Ln {}, Col {}", cursors, matches, dt.format("%H:%M:%S"), y + 1, x + 1)
+                }
+            };
+
+            if let Some(name) = self.register {
+                message.push_str(&format!("\"{} ", name), text.clone());
             }
 
-            queue!(self.renderer, cursor::Show, cursor::EnableBlinking)?;
-            self.renderer.flush()?;
+            let language = file.syntax_highlighter.syntax.map(|syntax| format!(" {}", syntax.name().to_lowercase())).unwrap_or_default();
+            let format = if file.hex.is_some() { String::from("binary") } else { format!("{} {}{}", file.encoding.name(), file.file_format(), language) };
+            // the file info is cut short first, so the cursor position always fits with at least a space before the clock
+            let mut info = ColourString::new(format!("{} {} {}", file.name, format_size(file.len_bytes() as u64), format), text.clone());
+            info.truncate((terminal_x as usize).saturating_sub(message.get_content().len() + end_message.len() + 1));
+            message.push_colour_string(info);
+            message.push_str(&" ".repeat((terminal_x as usize).saturating_sub(message.get_content().len() + end_message.len()).max(1)), text.clone());
+            message.push_str(&end_message, text.clone());
+
+            self.status_bar.set_message(message);
+        }
+
+        if let Some(closing) = self.closing {
+            self.status_bar.set_command_output(ColourString::new(self.closing_prompt(closing), Some(Info::new(self.theme.warning, Color::Reset, vec![]))));
+        }
+        if let Some(substitute) = &self.substitute {
+            self.status_bar.set_command_output(ColourString::new(substitute.prompt(), Some(Info::new(self.theme.warning, Color::Reset, vec![]))));
+        }
+        let search = match &self.substitute {
+            Some(substitute) => Some(substitute.regex.clone()),
+            None => self.search.regex().filter(|_| self.search.highlight),
+        };
+
+        let areas = self.areas()?;
+        for &(area, rect) in &areas {
+            let size = (rect.width, rect.height);
+            let frame = match area {
+                Area::Home => self.home.render(&self.theme, size),
+                Area::Pane(pane) if pane == self.pane => self.files[self.file_index].render(search.as_ref(), &self.theme, size),
+                // the other panes draw the file from their own view of it
+                Area::Pane(pane) => {
+                    let pane = &mut self.panes[pane];
+                    let file = &mut self.files[pane.file];
+                    std::mem::swap(&mut pane.view, &mut file.cursor);
+                    let frame = file.render(search.as_ref(), &self.theme, size);
+                    std::mem::swap(&mut pane.view, &mut file.cursor);
+                    frame
+                },
+                Area::Divider { vertical } => {
+                    let line = if vertical { "│".repeat(rect.height as usize) } else { "─".repeat(rect.width as usize) };
+                    let colour = Some(Info::new(self.theme.border, Color::Reset, vec![]));
+                    if vertical { line.chars().map(|c| ColourString::new(c.to_string(), colour.clone())).collect() } else { vec![ColourString::new(line, colour)] }
+                },
+                Area::Panes => vec![],
+                Area::Explorer => self.file_explorer.render(&self.files[self.file_index], &self.theme, size),
+                Area::Tabs => self.tab.render(&self.files, self.file_index, &self.theme, rect.width),
+                Area::StatusBar => self.status_bar.render(rect.width),
+            };
+            self.register(frame, rect, false);
         }
+
+        let screen = Rect::new(0, 0, terminal_x, terminal_y);
+        if self.command_palette.enabled {
+            let frame = self.command_palette.render(&self.theme, (terminal_x, terminal_y));
+            self.register(frame, screen, true);
+            self.overlay = true;
+        }
+
+        self.render_frames()?;
+
+        // cursors are kept relative to the area they are in
+        let (area, cursor) = if self.command_palette.enabled {
+            (None, &self.command_palette.cursor)
+        }
+        else if self.file_explorer.selected {
+            (Some(Area::Explorer), &self.file_explorer.cursor)
+        }
+        else if self.files.is_empty() {
+            (Some(Area::Home), &self.home.cursor)
+        }
+        else {
+            (Some(Area::Pane(self.pane)), &self.files[self.file_index].cursor)
+        };
+        let rect = areas.iter().find(|&&(found, _)| Some(found) == area).map(|&(_, rect)| rect).unwrap_or(screen);
+        // an area too small for its gutter or prompt still keeps the cursor on it
+        let (x, y) = rect.to_screen(cursor.x.min(rect.width.saturating_sub(1)), cursor.y.min(rect.height.saturating_sub(1)));
+        self.backend.show_cursor(x, y)?;
+        if let Some(text) = self.registers.take_terminal_copy() {
+            self.backend.set_clipboard(&text)?;
+        }
+        self.backend.flush()
     }
 }
 
@@ -464,3 +1255,6 @@ fn format_size(size_in_bytes: u64) -> String {
     let size_number = format!("{:.2}", size_in_units).trim_end_matches("0").trim_end_matches(".").to_string();
     format!("{}{}", size_number, unit)
 }
+
+#[cfg(test)]
+mod tests;
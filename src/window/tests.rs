@@ -0,0 +1,784 @@
+use std::{env, fs, path::{Path, PathBuf}, process, time::Duration};
+
+use crossterm::event::{Event, KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
+use crossterm::Result;
+use regex::Regex;
+
+use crate::backend::{MemoryBackend, ScriptedEvents};
+use crate::command_history::{CommandHistory, Kind};
+use crate::layout::{Area, Rect};
+use crate::register::{FakeClipboard, Osc52Clipboard, Registers};
+use crate::theme::Theme;
+use super::Window;
+
+// a window on a screen in memory, with the basic theme so nothing is drawn as background blocks, a history that
+// isn't saved and a clipboard of its own
+fn window(width: u16, height: u16) -> Window<MemoryBackend> {
+    let mut window = Window::with_clipboard(MemoryBackend::new(width, height), Box::new(ScriptedEvents::keys("")), Box::new(FakeClipboard::default()));
+    window.theme = Theme::load("basic", true).unwrap();
+    window.command_palette.history = CommandHistory::new(None);
+    window
+}
+
+// draws, then handles each key and draws again like the render loop does
+fn run(window: &mut Window<MemoryBackend>, keys: &str) -> Result<()> {
+    play(window, ScriptedEvents::keys(keys))
+}
+
+fn play(window: &mut Window<MemoryBackend>, events: ScriptedEvents) -> Result<()> {
+    window.events = Box::new(events);
+    window.draw()?;
+    while window.events.poll(Duration::ZERO)? {
+        window.parse_input(Duration::ZERO)?;
+        window.draw()?;
+    }
+    Ok(())
+}
+
+// the screen as text without trailing spaces, and the clock in the status bar blanked out
+fn screen(window: &Window<MemoryBackend>) -> String {
+    let clock = Regex::new(r"\d\d:\d\d:\d\d").unwrap();
+    let lines: Vec<String> = window.backend.lines().iter().map(|line| clock.replace_all(line.trim_end(), "--:--:--").to_string()).collect();
+    lines.join("\n") + "\n"
+}
+
+// compares with src/window/snapshots/<name>.txt, UPDATE_SNAPSHOTS=1 writes it instead
+fn assert_snapshot(name: &str, window: &Window<MemoryBackend>) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/window/snapshots").join(format!("{}.txt", name));
+    let actual = screen(window);
+    if env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, actual).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&path).unwrap_or_else(|_| panic!("{}: no snapshot, run with UPDATE_SNAPSHOTS=1 to write it", path.display()));
+    assert_eq!(actual, expected, "{} doesn't match the screen", path.display());
+}
+
+fn mouse(kind: MouseEventKind, column: u16, row: u16) -> Event {
+    Event::Mouse(MouseEvent { kind, column, row, modifiers: KeyModifiers::NONE })
+}
+
+// an empty directory of its own for each test
+fn directory(name: &str) -> PathBuf {
+    let directory = env::temp_dir().join(format!("vcte-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+fn file(directory: &Path, name: &str, text: &str) -> String {
+    let path = directory.join(name);
+    fs::write(&path, text).unwrap();
+    path.to_string_lossy().to_string()
+}
+
+#[test]
+fn home_screen() {
+    let mut window = window(60, 12);
+    run(&mut window, "").unwrap();
+    assert_snapshot("home", &window);
+}
+
+#[test]
+fn home_screen_on_a_narrow_terminal() {
+    let mut window = window(20, 5);
+    run(&mut window, "").unwrap();
+    assert_snapshot("home_narrow", &window);
+}
+
+#[test]
+fn insert_and_save() {
+    let directory = directory("insert_and_save");
+    let path = file(&directory, "notes.txt", "world\n");
+    let mut window = window(60, 10);
+    run(&mut window, &format!(":o {}<Enter>ihello <Esc>:s<Enter>", path)).unwrap();
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), "hello world\n");
+    assert!(screen(&window).contains("1 hello world"));
+    assert!(window.backend.lines().last().unwrap().starts_with("saved file to"));
+}
+
+#[test]
+fn crlf_is_kept_on_save() {
+    let directory = directory("crlf");
+    let path = file(&directory, "dos.txt", "one\r\ntwo\r\n");
+    let mut window = window(60, 10);
+    run(&mut window, &format!(":o {}<Enter>ihi<Enter><Esc>:s<Enter>", path)).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"hi\r\none\r\ntwo\r\n");
+}
+
+#[test]
+fn mixed_line_endings_are_left_alone() {
+    let directory = directory("mixed");
+    let path = file(&directory, "mixed.txt", "a\r\nb\nc\r\n");
+    let mut window = window(60, 10);
+    run(&mut window, &format!(":o {}<Enter>six<Esc>:s<Enter>", path)).unwrap();
+    assert_eq!(window.files[0].file_format(), "mixed");
+    assert_eq!(fs::read(&path).unwrap(), b"a\r\nxb\nc\r\n");
+}
+
+#[test]
+fn no_trailing_newline_is_kept() {
+    let directory = directory("no_newline");
+    let path = file(&directory, "last.txt", "first\nlast");
+    let mut window = window(60, 10);
+    run(&mut window, &format!(":o {}<Enter>si!<Esc>:s<Enter>", path)).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"first\n!last");
+}
+
+#[test]
+fn tabs_in_a_makefile_are_kept() {
+    let directory = directory("makefile");
+    let path = file(&directory, "Makefile", "all:\n\tcc main.c\n");
+    let mut window = window(60, 10);
+    run(&mut window, &format!(":o {}<Enter>si<Tab><Esc>:s<Enter>", path)).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"all:\n\t\tcc main.c\n");
+}
+
+#[test]
+fn converting_line_endings_undoes_in_one_step() {
+    let directory = directory("fileformat");
+    let path = file(&directory, "dos.txt", "one\r\ntwo\r\n");
+    let mut window = window(60, 10);
+    run(&mut window, &format!(":o {}<Enter>:set ff=unix<Enter>:s<Enter>", path)).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"one\ntwo\n");
+
+    run(&mut window, "u").unwrap();
+    assert_eq!(window.files[0].buffer.text(), "one\r\ntwo\r\n");
+    assert!(window.files[0].modified);
+}
+
+#[test]
+fn setting_the_encoding_back_leaves_nothing_to_save() {
+    let directory = directory("fenc");
+    let path = file(&directory, "a.txt", "text\n");
+    let mut window = window(60, 10);
+    run(&mut window, &format!(":o {}<Enter>:set bomb<Enter>", path)).unwrap();
+    assert!(window.files[0].modified);
+    run(&mut window, ":set nobomb<Enter>").unwrap();
+    assert!(!window.files[0].modified);
+    run(&mut window, ":set fenc=latin1<Enter>").unwrap();
+    assert!(window.files[0].modified);
+    run(&mut window, ":set fenc=utf-8<Enter>").unwrap();
+    assert!(!window.files[0].modified);
+
+    // and once saved in another one, that is the one to go back to
+    run(&mut window, "ié<Esc>:set fenc=latin1<Enter>:s<Enter>u").unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"\xE9text\n");
+    assert!(window.files[0].modified);
+    run(&mut window, "<C-r>").unwrap();
+    assert!(!window.files[0].modified);
+}
+
+#[test]
+fn hex_edits_are_saved_byte_for_byte_and_undo() {
+    let directory = directory("hex");
+    let bytes = [0x00, 0x01, 0xfe, 0xff, b'\r', b'\n', 0x80, 0x00];
+    let path = file(&directory, "a.bin", "");
+    fs::write(&path, bytes).unwrap();
+    let mut window = window(80, 6);
+    run(&mut window, &format!(":o {}<Enter>di4142<Esc>", path)).unwrap();
+    assert!(window.files[0].modified);
+    assert_eq!(window.files[0].hex.as_ref().unwrap().offset, 3);
+
+    // typing stays one change until insert mode is left
+    run(&mut window, "u").unwrap();
+    assert!(!window.files[0].modified);
+    assert_eq!(window.files[0].hex.as_ref().unwrap().bytes, bytes);
+    assert_eq!(window.files[0].hex.as_ref().unwrap().offset, 1);
+    run(&mut window, "<C-r>:s<Enter>").unwrap();
+    assert!(!window.files[0].modified);
+    assert_eq!(fs::read(&path).unwrap(), [0x00, 0x41, 0x42, 0xff, b'\r', b'\n', 0x80, 0x00]);
+}
+
+#[test]
+fn cursor_is_drawn_inside_the_editor() {
+    let directory = directory("cursor");
+    let path = file(&directory, "a.txt", "one\ntwo\nthree\n");
+    let mut window = window(40, 8);
+    run(&mut window, &format!(":o {}<Enter>sdd", path)).unwrap();
+
+    // the second line is below the tabs, and the third column is after the five columns of the gutter
+    assert_eq!(window.backend.cursor, Some((7, 2)));
+}
+
+#[test]
+fn tabs() {
+    let directory = directory("tabs");
+    let first = file(&directory, "first.rs", "fn main() {}\n");
+    let second = file(&directory, "second.rs", "fn second() {}\n");
+    let mut window = window(50, 6);
+    run(&mut window, &format!(":o {}<Enter>:o {}<Enter><S-Tab>", first, second)).unwrap();
+    assert_snapshot("tabs", &window);
+}
+
+#[test]
+fn status_bar_cuts_the_file_info_before_the_cursor_position() {
+    let directory = directory("status_bar");
+    let path = file(&directory, "a_rather_long_file_name.rs", "fn main() {}\n");
+    let mut window = window(30, 4);
+    run(&mut window, &format!(":o {}<Enter>", path)).unwrap();
+    assert_eq!(screen(&window).lines().last(), Some("view a_ra --:--:-- Ln 1, Col 1"));
+}
+
+#[test]
+fn explorer() {
+    let directory = directory("explorer");
+    let path = file(&directory, "main.rs", "fn main() {\n    println!(\"hi\");\n}\n");
+    file(&directory, "lib.rs", "");
+    fs::create_dir(directory.join("src")).unwrap();
+    let mut window = window(60, 8);
+    run(&mut window, &format!(":o {}<Enter>c", path)).unwrap();
+    assert_snapshot("explorer", &window);
+}
+
+#[test]
+fn command_palette() {
+    let mut window = window(50, 10);
+    run(&mut window, ":theme basic").unwrap();
+    assert_snapshot("command_palette", &window);
+    assert_eq!(window.backend.cursor, Some((31, 2)));
+}
+
+#[test]
+fn unknown_command() {
+    let mut window = window(40, 5);
+    run(&mut window, ":frobnicate<Enter>").unwrap();
+    assert_eq!(window.backend.lines().last().unwrap().trim_end(), "frobnicate: Command not found");
+}
+
+#[test]
+fn misspelt_command_suggests_the_closest() {
+    let mut window = window(60, 5);
+    run(&mut window, ":vsplti<Enter>").unwrap();
+    assert_eq!(window.backend.lines().last().unwrap().trim_end(), "vsplti: Command not found, did you mean vsplit?");
+}
+
+#[test]
+fn quoted_arguments_keep_their_spaces() {
+    let directory = directory("quoted");
+    let path = file(&directory, "my notes.txt", "spaced out\n");
+    let mut window = window(60, 5);
+    run(&mut window, &format!(":o \"{}\"<Enter>", path)).unwrap();
+    assert!(screen(&window).contains("1 spaced out"));
+}
+
+#[test]
+fn help_for_one_command() {
+    let mut window = window(80, 5);
+    run(&mut window, ":help O<Enter>").unwrap();
+    assert_eq!(window.backend.lines().last().unwrap().trim_end(), "open (o) <path> - open a file");
+}
+
+#[test]
+fn command_suggestions() {
+    let mut window = window(50, 14);
+    run(&mut window, ":se").unwrap();
+    assert_snapshot("command_suggestions", &window);
+
+    run(&mut window, "<Tab><Tab><S-Tab>").unwrap();
+    assert_eq!(window.command_palette.input.text(), "set");
+}
+
+#[test]
+fn path_suggestions() {
+    let directory = directory("path_suggestions");
+    file(&directory, "notes.txt", "");
+    file(&directory, "nothing here.txt", "");
+    fs::create_dir(directory.join("north")).unwrap();
+    let mut window = window(60, 14);
+    run(&mut window, &format!(":o {}/nes<Tab>", directory.display())).unwrap();
+    assert_eq!(window.command_palette.suggestions.len(), 1);
+    assert_eq!(window.command_palette.input.text(), format!("o {}/notes.txt", directory.display()));
+
+    // quoted when it has a space, and only directories for cd
+    run(&mut window, &format!("<Esc>:o {}/no<Tab><Tab><Tab>", directory.display())).unwrap();
+    assert_eq!(window.command_palette.input.text(), format!("o '{}/nothing here.txt'", directory.display()));
+    run(&mut window, &format!("<Esc>:cd {}/n<Tab>", directory.display())).unwrap();
+    assert_eq!(window.command_palette.suggestions.len(), 1);
+    assert_eq!(window.command_palette.input.text(), format!("cd {}/north/", directory.display()));
+}
+
+#[test]
+fn history_goes_through_commands_starting_with_what_was_typed() {
+    let mut window = window(60, 10);
+    run(&mut window, ":theme basic<Enter>:noh<Enter>:theme basic<Enter>:theme<Enter>:th").unwrap();
+
+    run(&mut window, "<Up>").unwrap();
+    assert_eq!(window.command_palette.input.text(), "theme");
+    // the repeated command is only there once, as the newest
+    run(&mut window, "<Up><Up>").unwrap();
+    assert_eq!(window.command_palette.input.text(), "theme basic");
+    run(&mut window, "<Down><Down>").unwrap();
+    assert_eq!(window.command_palette.input.text(), "th");
+}
+
+#[test]
+fn reverse_search_through_history() {
+    let mut window = window(60, 10);
+    run(&mut window, ":theme basic<Enter>:theme<Enter>:noh<Enter>:<C-r>th").unwrap();
+    assert_eq!(window.command_palette.input.text(), "theme");
+    run(&mut window, "<C-r>a").unwrap();
+    assert_eq!(window.command_palette.input.text(), "theme basic");
+    assert!(screen(&window).contains("▏:theme basic  (reverse search: tha)▕"));
+
+    // enter runs what was found
+    run(&mut window, "<Enter>").unwrap();
+    assert_eq!(window.command_palette.history.entries(Kind::Command).last().unwrap(), "theme basic");
+}
+
+#[test]
+fn editing_in_the_middle_of_a_command() {
+    let mut window = window(60, 10);
+    run(&mut window, ":heme basc<Home>t<End><Left>i").unwrap();
+    assert_eq!(window.command_palette.input.text(), "theme basic");
+    // the cursor is after the i, past the left border and the colon
+    let (x, _) = window.backend.cursor.unwrap();
+    assert_eq!(screen(&window).lines().nth(2).unwrap().chars().nth(x as usize - 1), Some('i'));
+
+    run(&mut window, "<C-w><C-w>one two<A-b><C-u>theme <End><C-w>basic").unwrap();
+    assert_eq!(window.command_palette.input.text(), "theme basic");
+}
+
+#[test]
+fn backspace_only_closes_an_empty_palette() {
+    let mut window = window(60, 10);
+    run(&mut window, ":ab<Left><BS><BS><Del>").unwrap();
+    assert!(window.command_palette.enabled);
+    assert!(window.command_palette.input.is_empty());
+    run(&mut window, "<BS>").unwrap();
+    assert!(!window.command_palette.enabled);
+}
+
+#[test]
+fn paste_a_register_into_the_palette() {
+    let mut window = window(60, 10);
+    run(&mut window, ":theme basic<Enter>:x <C-v>:").unwrap();
+    assert_eq!(window.command_palette.input.text(), "x theme basic");
+}
+
+#[test]
+fn history_is_saved() {
+    let path = directory("history").join("state").join("history");
+    let mut history = CommandHistory::new(Some(path.clone()));
+    history.add(Kind::Command, "o notes.txt");
+    history.add(Kind::Search, "fn main");
+    history.add(Kind::Command, "noh");
+    history.add(Kind::Command, "o notes.txt");
+
+    let history = CommandHistory::new(Some(path));
+    assert_eq!(history.entries(Kind::Command), ["noh", "o notes.txt"]);
+    assert_eq!(history.entries(Kind::Search), ["fn main"]);
+}
+
+#[test]
+fn splits_show_edits_in_every_pane() {
+    let directory = directory("splits");
+    let path = file(&directory, "a.txt", "text\n");
+    let mut window = window(40, 6);
+    run(&mut window, &format!(":o {}<Enter>:vs<Enter>ione <Esc><C-w>l", path)).unwrap();
+    assert_snapshot("splits", &window);
+}
+
+#[test]
+fn splits_share_the_panes_equally() {
+    let directory = directory("split_sizes");
+    let path = file(&directory, "a.txt", "text\n");
+    let mut window = window(40, 8);
+    run(&mut window, &format!(":o {}<Enter>:vs<Enter>", path)).unwrap();
+    // the new pane goes on the left and the last one gets what doesn't divide evenly
+    assert_eq!(window.rect(Area::Pane(1)).unwrap(), Rect::new(0, 1, 19, 6));
+    assert_eq!(window.rect(Area::Pane(0)).unwrap(), Rect::new(20, 1, 20, 6));
+
+    run(&mut window, ":vs<Enter>").unwrap();
+    let widths: Vec<u16> = [2, 1, 0].into_iter().map(|pane| window.rect(Area::Pane(pane)).unwrap().width).collect();
+    assert_eq!(widths, [12, 12, 14]);
+
+    // a split the other way only divides the focused pane
+    run(&mut window, ":sp<Enter>").unwrap();
+    assert_eq!(window.rect(Area::Pane(3)).unwrap(), Rect::new(0, 1, 12, 2));
+    assert_eq!(window.rect(Area::Pane(2)).unwrap(), Rect::new(0, 4, 12, 3));
+    assert_eq!(window.rect(Area::Pane(1)).unwrap(), Rect::new(13, 1, 12, 6));
+}
+
+#[test]
+fn resizing_a_pane_moves_the_divider_next_to_it() {
+    let directory = directory("split_resize");
+    let path = file(&directory, "a.txt", "text\n");
+    let mut window = window(40, 8);
+    run(&mut window, &format!(":o {}<Enter>:vs<Enter><C-w>><C-w>>", path)).unwrap();
+    assert_eq!(window.rect(Area::Pane(1)).unwrap().width, 21);
+    assert_eq!(window.rect(Area::Pane(0)).unwrap().width, 18);
+
+    // the right pane grows by moving the divider before it
+    run(&mut window, "<C-w>l<C-w>>").unwrap();
+    assert_eq!(window.rect(Area::Pane(1)).unwrap().width, 20);
+    assert_eq!(window.rect(Area::Pane(0)).unwrap().width, 19);
+
+    // there is no split one above the other to take rows from
+    run(&mut window, "<C-w>+").unwrap();
+    assert_eq!(window.rect(Area::Pane(0)).unwrap().height, 6);
+
+    // both sides keep a column
+    run(&mut window, &"<C-w><lt>".repeat(30)).unwrap();
+    assert_eq!(window.rect(Area::Pane(0)).unwrap().width, 1);
+    assert_eq!(window.rect(Area::Pane(1)).unwrap().width, 38);
+}
+
+#[test]
+fn dragging_a_divider_resizes_the_panes() {
+    let directory = directory("split_drag");
+    let path = file(&directory, "a.txt", "text\n");
+    let mut window = window(40, 8);
+    run(&mut window, &format!(":o {}<Enter>:vs<Enter>", path)).unwrap();
+    let drag = ScriptedEvents::keys("").then([
+        mouse(MouseEventKind::Down(MouseButton::Left), 19, 3),
+        mouse(MouseEventKind::Drag(MouseButton::Left), 22, 3),
+        mouse(MouseEventKind::Drag(MouseButton::Left), 25, 4),
+        mouse(MouseEventKind::Up(MouseButton::Left), 25, 4),
+    ]);
+    play(&mut window, drag).unwrap();
+    assert_eq!(window.rect(Area::Pane(1)).unwrap().width, 25);
+    assert_eq!(window.rect(Area::Divider { vertical: true }).unwrap().x, 25);
+    assert_eq!(window.drag, None);
+
+    // going past the edge stops a column short of it
+    let drag = ScriptedEvents::keys("").then([
+        mouse(MouseEventKind::Down(MouseButton::Left), 25, 3),
+        mouse(MouseEventKind::Drag(MouseButton::Left), 60, 3),
+    ]);
+    play(&mut window, drag).unwrap();
+    assert_eq!(window.rect(Area::Pane(0)).unwrap().width, 1);
+    // and the selection in the pane is left alone
+    assert!(window.files[0].selection.is_none());
+}
+
+#[test]
+fn each_pane_keeps_its_own_cursor() {
+    let directory = directory("split_cursors");
+    let path = file(&directory, "a.txt", "one\ntwo\nthree\nfour\n");
+    let mut window = window(40, 12);
+    run(&mut window, &format!(":o {}<Enter>s:sp<Enter>ssd", path)).unwrap();
+    assert_eq!(window.pane, 1);
+    assert_eq!(window.files[0].cursor_index(), (1, 3));
+
+    run(&mut window, "<C-w>j").unwrap();
+    assert_eq!(window.pane, 0);
+    assert_eq!(window.files[0].cursor_index(), (0, 1));
+
+    run(&mut window, "<C-w>w").unwrap();
+    assert_eq!(window.pane, 1);
+    assert_eq!(window.files[0].cursor_index(), (1, 3));
+
+    // clicking in a pane focuses it before moving its cursor
+    let bottom = window.rect(Area::Pane(0)).unwrap();
+    play(&mut window, ScriptedEvents::keys("").then([mouse(MouseEventKind::Down(MouseButton::Left), bottom.x + 7, bottom.y + 2)])).unwrap();
+    assert_eq!(window.pane, 0);
+    assert_eq!(window.files[0].cursor_index(), (2, 2));
+    run(&mut window, "<C-w>k").unwrap();
+    assert_eq!(window.files[0].cursor_index(), (1, 3));
+}
+
+#[test]
+fn quit() {
+    let mut window = window(40, 5);
+    run(&mut window, ":q<Enter>").unwrap();
+    assert!(window.quit);
+}
+
+#[test]
+fn quit_asks_about_unsaved_changes() {
+    let directory = directory("quit_unsaved");
+    let path = file(&directory, "a.txt", "text\n");
+    let mut window = window(80, 6);
+    run(&mut window, &format!(":o {}<Enter>inew <Esc>:q<Enter>", path)).unwrap();
+    assert!(!window.quit);
+    assert_eq!(window.backend.lines().last().unwrap().trim_end(), "a.txt has unsaved changes, save, discard or cancel? (s/d/c)");
+
+    // anything else leaves the question up, and cancelling carries on editing
+    run(&mut window, "jc").unwrap();
+    assert!(!window.quit && window.closing.is_none());
+    run(&mut window, ":q<Enter>d").unwrap();
+    assert!(window.quit);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "text\n");
+}
+
+#[test]
+fn closing_a_modified_file_can_save_it() {
+    let directory = directory("close_unsaved");
+    let path = file(&directory, "a.txt", "text\n");
+    let mut window = window(80, 6);
+    run(&mut window, &format!(":o {}<Enter>inew <Esc>x", path)).unwrap();
+    assert_eq!(window.files.len(), 1);
+    run(&mut window, "s").unwrap();
+    assert!(window.files.is_empty());
+    assert_eq!(fs::read_to_string(&path).unwrap(), "new text\n");
+}
+
+#[test]
+fn save_a_copy_save_as_and_write_quit() {
+    let directory = directory("save_as");
+    let path = file(&directory, "a.txt", "text\n");
+    let copy = directory.join("copy.txt");
+    let moved = directory.join("b.txt");
+    let mut window = window(80, 6);
+    run(&mut window, &format!(":o {}<Enter>inew <Esc>:w {}<Enter>", path, copy.display())).unwrap();
+    assert_eq!(fs::read_to_string(&copy).unwrap(), "new text\n");
+    assert!(window.files[0].modified);
+
+    run(&mut window, &format!(":saveas {}<Enter>", moved.display())).unwrap();
+    assert_eq!(window.files[0].name, "b.txt");
+    assert!(!window.files[0].modified);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "text\n");
+
+    run(&mut window, "i!<Esc>:x<Enter>").unwrap();
+    assert!(window.quit);
+    assert_eq!(fs::read_to_string(&moved).unwrap(), "new !text\n");
+}
+
+#[test]
+fn wide_graphemes_take_two_columns() {
+    let directory = directory("wide");
+    let path = file(&directory, "wide.txt", "中文 e\u{301}😀x\n");
+    let mut window = window(30, 5);
+    run(&mut window, &format!(":o {}<Enter>dddd", path)).unwrap();
+
+    assert_eq!(window.backend.lines()[1].trim_end(), "   1 中文 e\u{301}😀x");
+    // past two wide characters, a space and an e with a combining accent
+    assert_eq!(window.backend.cursor, Some((11, 1)));
+}
+
+#[test]
+fn wide_grapheme_cut_at_the_edge() {
+    let directory = directory("wide_edge");
+    let path = file(&directory, "wide.txt", "中文 e\u{301}😀x\n");
+    let mut window = window(12, 5);
+    run(&mut window, &format!(":o {}<Enter>", path)).unwrap();
+
+    // the emoji would start in the last column, so only a space fits
+    assert_eq!(window.backend.lines()[1], "   1 中文 e\u{301} ");
+}
+
+#[test]
+fn search_counts_matches_and_n_jumps_between_them() {
+    let directory = directory("search");
+    let first = file(&directory, "first.txt", "foo\nbar foo\nFoo\n");
+    let second = file(&directory, "second.txt", "other\n");
+    let mut window = window(80, 8);
+    run(&mut window, &format!(":o {}<Enter>:o {}<Enter>/foo", second, first)).unwrap();
+    // the count waits for the search to be done
+    assert!(!screen(&window).contains("match"));
+    run(&mut window, "<Enter>").unwrap();
+    assert!(screen(&window).contains("match 1/3"));
+
+    run(&mut window, "n").unwrap();
+    assert!(screen(&window).contains("match 2/3"));
+    assert_eq!(window.files[window.file_index].cursor_index(), (4, 1));
+    run(&mut window, "NN").unwrap();
+    assert!(screen(&window).contains("match 3/3"));
+
+    // the count goes once the cursor leaves the match, and is counted again after an edit
+    run(&mut window, "d").unwrap();
+    assert!(!screen(&window).contains("match"));
+    run(&mut window, "nifoo <Esc>n").unwrap();
+    assert!(screen(&window).contains("match 3/4"));
+    assert_eq!(window.files[window.file_index].cursor_index(), (4, 1));
+
+    run(&mut window, "<Tab>").unwrap();
+    assert_eq!(window.files[window.file_index].name, "second.txt");
+}
+
+#[test]
+fn search_with_case_and_whole_word_toggled() {
+    let directory = directory("search_flags");
+    let path = file(&directory, "a.txt", "Foo foobar foo\n");
+    let mut window = window(80, 8);
+    run(&mut window, &format!(":o {}<Enter>/foo<A-w><Enter>", path)).unwrap();
+    assert!(screen(&window).contains("match 1/2"));
+    run(&mut window, "/foo<A-c><Enter>").unwrap();
+    assert_eq!(window.files[0].cursor_index(), (11, 0));
+    assert!(screen(&window).contains("match 1/1"));
+}
+
+#[test]
+fn undo_and_redo_wait_for_a_substitution_to_be_confirmed() {
+    let directory = directory("substitute_confirm");
+    let path = file(&directory, "a.txt", "a a\n");
+    let mut window = window(80, 8);
+    run(&mut window, &format!(":o {}<Enter>ix<Esc>u:s/a/b/gc<Enter>y<C-r>u", path)).unwrap();
+    assert_eq!(window.files[0].buffer.text(), "b a\n");
+    assert!(window.substitute.is_some());
+
+    run(&mut window, "y").unwrap();
+    assert!(window.substitute.is_none());
+    run(&mut window, "u").unwrap();
+    assert_eq!(window.files[0].buffer.text(), "a a\n");
+}
+
+#[test]
+fn visual_mode_yanks_deletes_and_pastes() {
+    use crate::selection::VisualMode;
+
+    let directory = directory("visual");
+    let path = file(&directory, "a.txt", "one two\nthree\nfour\n");
+    let mut window = window(40, 8);
+    run(&mut window, &format!(":o {}<Enter>v<Right><Right>", path)).unwrap();
+    assert!(screen(&window).contains("visual a.txt"));
+    run(&mut window, "d").unwrap();
+    assert_eq!(window.files[0].buffer.text(), " two\nthree\nfour\n");
+    assert_eq!(window.registers.get(None).unwrap().map(|yank| yank.text), Some(String::from("one")));
+    assert!(window.files[0].selection.is_none());
+    run(&mut window, "u").unwrap();
+    assert_eq!(window.files[0].buffer.text(), "one two\nthree\nfour\n");
+
+    run(&mut window, "V<Down>yssp").unwrap();
+    assert_eq!(window.files[0].buffer.text(), "one two\nthree\nfour\none two\nthree\n");
+    assert_eq!(window.files[0].cursor_index(), (0, 3));
+    run(&mut window, "u").unwrap();
+
+    run(&mut window, "ww<C-v><Down><Right>d").unwrap();
+    assert_eq!(window.files[0].buffer.text(), "e two\nree\nfour\n");
+    assert_eq!(window.registers.get(None).unwrap().map(|yank| yank.mode), Some(VisualMode::Block));
+    // a whole block is one change
+    run(&mut window, "u").unwrap();
+    assert_eq!(window.files[0].buffer.text(), "one two\nthree\nfour\n");
+}
+
+#[test]
+fn dragging_the_mouse_selects() {
+    let directory = directory("visual_mouse");
+    let path = file(&directory, "a.txt", "one two\nthree\n");
+    let mut window = window(40, 8);
+    run(&mut window, &format!(":o {}<Enter>", path)).unwrap();
+    // the text starts after the tabs and the gutter
+    let drag = ScriptedEvents::keys("").then([
+        mouse(MouseEventKind::Down(MouseButton::Left), 9, 1),
+        mouse(MouseEventKind::Drag(MouseButton::Left), 10, 2),
+        mouse(MouseEventKind::Drag(MouseButton::Left), 7, 2),
+        mouse(MouseEventKind::Up(MouseButton::Left), 7, 2),
+    ]);
+    play(&mut window, drag).unwrap();
+    assert!(window.files[0].selection.is_some());
+    run(&mut window, "y").unwrap();
+    assert_eq!(window.registers.get(None).unwrap().map(|yank| yank.text), Some(String::from("two\nthr")));
+    assert_eq!(window.files[0].cursor_index(), (4, 0));
+}
+
+#[test]
+fn clipboard_registers() {
+    let directory = directory("clipboard");
+    let path = file(&directory, "a.txt", "copy me\n");
+    let clipboard = FakeClipboard::default();
+    let mut window = window(60, 8);
+    window.registers = Registers::new(Box::new(clipboard.clone()));
+    run(&mut window, &format!(":o {}<Enter>\"+v<Right><Right><Right>y", path)).unwrap();
+    assert_eq!(*clipboard.text.borrow(), "copy");
+
+    *clipboard.text.borrow_mut() = String::from("pasted ");
+    run(&mut window, "\"+P").unwrap();
+    assert_eq!(window.files[0].buffer.text(), "pasted copy me\n");
+}
+
+#[test]
+fn osc_52_is_written_through_the_backend() {
+    let directory = directory("osc52");
+    let path = file(&directory, "a.txt", "over ssh\n");
+    let mut window = window(60, 8);
+    window.registers = Registers::new(Box::new(Osc52Clipboard::default()));
+    run(&mut window, &format!(":o {}<Enter>\"*Vy", path)).unwrap();
+    assert_eq!(window.backend.clipboard.as_deref(), Some("over ssh\n"));
+}
+
+#[test]
+fn edits_at_every_cursor_shift_the_ones_after_them() {
+    let directory = directory("cursors");
+    let path = file(&directory, "a.txt", "ab ab ab\nab\n");
+    let mut window = window(60, 8);
+    run(&mut window, &format!(":o {}<Enter><C-d><C-d><C-d>", path)).unwrap();
+    assert_eq!(window.files[0].cursors, vec![(3, 0), (6, 0), (0, 1)]);
+    assert!(screen(&window).contains("4 cursors"));
+
+    run(&mut window, "iX<Enter><Esc>").unwrap();
+    assert_eq!(window.files[0].buffer.text(), "X\nab X\nab X\nab\nX\nab\n");
+    assert_eq!(window.files[0].cursor_index(), (0, 1));
+    assert_eq!(window.files[0].cursors, vec![(0, 2), (0, 3), (0, 5)]);
+
+    // typing at every cursor is one change
+    run(&mut window, "u").unwrap();
+    assert_eq!(window.files[0].buffer.text(), "ab ab ab\nab\n");
+    assert!(!window.files[0].modified);
+}
+
+#[test]
+fn cursors_that_meet_become_one() {
+    let directory = directory("cursors_merge");
+    let path = file(&directory, "a.txt", "ab ab\n");
+    let mut window = window(60, 8);
+    run(&mut window, &format!(":o {}<Enter><C-d><Right>", path)).unwrap();
+    assert_eq!(window.files[0].cursors, vec![(4, 0)]);
+    run(&mut window, "<Left><Left><Left><Left>").unwrap();
+    assert!(window.files[0].cursors.is_empty());
+    assert!(!screen(&window).contains("cursors"));
+}
+
+#[test]
+fn next_occurrence_is_found_from_the_last_cursor() {
+    let directory = directory("cursors_next");
+    let path = file(&directory, "a.txt", "ab x ab x abc ab\n");
+    let mut window = window(60, 8);
+    run(&mut window, &format!(":o {}<Enter>ddddd<C-d>", path)).unwrap();
+    assert_eq!(window.files[0].cursors, vec![(14, 0)]);
+    // wrapping around to the start, and then there are none left
+    run(&mut window, "<C-d><C-d>").unwrap();
+    assert_eq!(window.files[0].cursors, vec![(14, 0), (0, 0)]);
+}
+
+#[test]
+fn opening_a_block_comment_recolours_the_lines_below() {
+    use crate::syntax_highlighter::Token;
+
+    let directory = directory("block_comment");
+    let path = file(&directory, "main.rs", "fn a() {}\nfn b() {}\nfn c() {}\n");
+    let mut window = window(40, 8);
+    // the colour of the first letter on each line, after the gutter
+    let colours = |window: &Window<MemoryBackend>| (1..4).map(|y| window.backend.cells[y * 40 + 5].colour.clone()).collect::<Vec<_>>();
+    run(&mut window, &format!(":o {}<Enter>", path)).unwrap();
+    let keyword = window.theme.token(Token::Keyword);
+    let comment = window.theme.token(Token::Comment);
+    assert_eq!(colours(&window), vec![keyword.clone(); 3]);
+
+    run(&mut window, "i/*<Esc>").unwrap();
+    assert_eq!(colours(&window), vec![comment.clone(); 3]);
+    run(&mut window, "u").unwrap();
+    assert_eq!(colours(&window), vec![keyword; 3]);
+}
+
+#[test]
+fn resizing_keeps_the_cursor_on_screen() {
+    let directory = directory("resize");
+    let text: String = (0..100).map(|i| format!("{:03} {}\n", i, "x".repeat(56))).collect();
+    let path = file(&directory, "long.txt", &text);
+    let mut window = window(40, 16);
+    run(&mut window, &format!(":o {}<Enter>{}{}", path, "s".repeat(80), "d".repeat(50))).unwrap();
+    assert_eq!(window.files[0].cursor_index(), (50, 80));
+    let before = window.files[0].cursor.clone();
+    assert!(before.x_offset > 0 && before.y_offset > 0);
+
+    window.backend.resize(20, 6);
+    play(&mut window, ScriptedEvents::keys("").then([Event::Resize(20, 6)])).unwrap();
+    let rect = window.rect(Area::Pane(0)).unwrap();
+    let cursor = &window.files[0].cursor;
+    assert_eq!(window.files[0].cursor_index(), (50, 80));
+    assert!(cursor.x <= cursor.x_max && cursor.y <= cursor.y_max);
+    assert!(cursor.y_offset > before.y_offset && cursor.x_offset > before.x_offset);
+    let (x, y) = window.backend.cursor.unwrap();
+    assert!(rect.contains(x, y), "{:?} is outside {:?}", (x, y), rect);
+
+    // every row is drawn again at the new size, with the cursor's line in view
+    let lines = window.backend.lines();
+    assert_eq!(lines.len(), 6);
+    assert!(lines.iter().all(|line| line.chars().count() <= 20));
+    assert!(lines[y as usize].starts_with("  81 "));
+    assert_eq!(screen(&window), " long.txt\n  78 xxxxxxxxxxxxxxx\n  79 xxxxxxxxxxxxxxx\n  80 xxxxxxxxxxxxxxx\n  81 xxxxxxxxxxxxxxx\nview  --:--:-- Ln 81\n");
+}